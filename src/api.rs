@@ -0,0 +1,498 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map, Value};
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::archive::BackupFormat;
+use crate::backup::{create_world_backup, BackupScope};
+use crate::console::{send_console_command, stop_server};
+use crate::control::{try_daemon, ControlCommand};
+use crate::logs::CapturedTail;
+use crate::properties::{read_properties, set_properties};
+use crate::run::{running_pid, spawn_background_server};
+use crate::schedule::restart_with_countdown;
+use crate::server::{get_all_servers, Server};
+use crate::tokens::{authenticate, load_tokens, TokenScope};
+
+const API_PREFIX: &str = "/api/v1";
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+const STOP_TIMEOUT: u64 = 90;
+const LOG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+// proxies drop idle connections, so quiet log streams get a comment line this often
+const LOG_KEEPALIVE: Duration = Duration::from_secs(15);
+const DEFAULT_LOG_BACKLOG: usize = 100;
+
+// an error to send back, with its HTTP status
+struct ApiError {
+    status: u16,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+// errors from the rest of slapaman are plain strings
+impl From<String> for ApiError {
+    fn from(message: String) -> Self {
+        let status = match message.starts_with("server not found") {
+            true => 404,
+            false => 500,
+        };
+        Self { status, message }
+    }
+}
+
+enum Reply {
+    Json(u16, Value),
+    Logs(String, usize),
+}
+
+// serve the HTTP/JSON API until interrupted
+pub fn serve_api(listen: &str) -> Result<(), String> {
+    let server = tiny_http::Server::http(listen)
+        .map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+    println!(
+        "[slapaman] serving the API on http://{}{}",
+        listen, API_PREFIX
+    );
+    if load_tokens()?.is_empty() {
+        println!(
+            "[slapaman] there are no API tokens yet, so every request will be refused (create one with `slapaman token create <label>`)"
+        );
+    }
+
+    // stops and log streams take a while, so every request gets its own thread
+    for request in server.incoming_requests() {
+        thread::spawn(move || handle(request));
+    }
+
+    Ok(())
+}
+
+fn handle(mut request: Request) {
+    let (path, query) = match request.url().split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (request.url().to_string(), String::new()),
+    };
+
+    let reply = path_segments(&path).and_then(|segments| {
+        let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+        let scope = authorize(&request, &segments, &query)?;
+        route(&mut request, &segments, &query, scope)
+    });
+
+    let result = match reply {
+        Ok(Reply::Json(status, body)) => request.respond(json_response(status, &body)),
+        Ok(Reply::Logs(name, backlog)) => stream_logs(request, &name, backlog),
+        Err(e) => request.respond(json_response(e.status, &json!({ "error": e.message }))),
+    };
+    if let Err(e) = result {
+        // clients going away mid-stream is normal for log followers
+        if e.kind() != std::io::ErrorKind::BrokenPipe {
+            println!("[slapaman] api: failed to send response: {}", e);
+        }
+    }
+}
+
+// find the scope of the request's bearer token; EventSource can't set headers, so the log stream
+// (and only the log stream, as query strings end up in proxy and access logs) also takes it as
+// ?access_token=
+fn authorize(request: &Request, segments: &[&str], query: &str) -> Result<TokenScope, ApiError> {
+    let log_stream =
+        *request.method() == Method::Get && matches!(segments, ["instances", _, "logs"]);
+    let header = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map(|h| h.value.as_str().to_string());
+    let secret = match header {
+        Some(value) => value
+            .strip_prefix("Bearer ")
+            .map(|s| s.to_string())
+            .ok_or_else(|| ApiError::new(401, "expected a bearer token"))?,
+        None if log_stream => query_param(query, "access_token")?
+            .ok_or_else(|| ApiError::new(401, "missing bearer token"))?,
+        None => return Err(ApiError::new(401, "missing bearer token")),
+    };
+
+    match authenticate(&secret)? {
+        Some(token) => Ok(token.scope),
+        None => Err(ApiError::new(401, "invalid token")),
+    }
+}
+
+// the percent-decoded segments of a path under the API prefix
+fn path_segments(path: &str) -> Result<Vec<String>, ApiError> {
+    path.strip_prefix(API_PREFIX)
+        .ok_or_else(|| ApiError::new(404, "not found"))?
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect()
+}
+
+fn route(
+    request: &mut Request,
+    segments: &[&str],
+    query: &str,
+    scope: TokenScope,
+) -> Result<Reply, ApiError> {
+    let method = request.method().clone();
+
+    match (&method, segments) {
+        (Method::Get, ["instances"]) => {
+            require(scope, TokenScope::ReadOnly)?;
+            let instances: Vec<Value> = get_all_servers()?.iter().map(describe).collect();
+            Ok(Reply::Json(200, json!(instances)))
+        }
+        (Method::Get, ["instances", name]) => {
+            require(scope, TokenScope::ReadOnly)?;
+            Ok(Reply::Json(
+                200,
+                describe(&Server::load_by_name(&name.to_string())?),
+            ))
+        }
+        (Method::Post, ["instances", name, "start"]) => {
+            require(scope, TokenScope::Operator)?;
+            let body = read_body(request)?;
+            let memory = match body.get("memory") {
+                None | Some(Value::Null) => None,
+                Some(memory) => Some(
+                    memory
+                        .as_u64()
+                        .and_then(|m| u32::try_from(m).ok())
+                        .ok_or_else(|| ApiError::new(400, "memory must be a number of MiB"))?,
+                ),
+            };
+            start(name, memory)?;
+            Ok(Reply::Json(202, json!({ "started": name })))
+        }
+        (Method::Post, ["instances", name, "stop"]) => {
+            require(scope, TokenScope::Operator)?;
+            let body = read_body(request)?;
+            let timeout = body
+                .get("timeout")
+                .and_then(|t| t.as_u64())
+                .unwrap_or(STOP_TIMEOUT);
+            stop(name, timeout)?;
+            Ok(Reply::Json(200, json!({ "stopped": name })))
+        }
+        (Method::Post, ["instances", name, "restart"]) => {
+            require(scope, TokenScope::Operator)?;
+            restart(name)?;
+            Ok(Reply::Json(200, json!({ "restarted": name })))
+        }
+        (Method::Post, ["instances", name, "console"]) => {
+            require(scope, TokenScope::Operator)?;
+            let body = read_body(request)?;
+            let command = body
+                .get("command")
+                .and_then(|c| c.as_str())
+                .filter(|c| !c.trim().is_empty())
+                .ok_or_else(|| ApiError::new(400, "expected a \"command\" string"))?;
+            console(name, command)?;
+            Ok(Reply::Json(200, json!({ "sent": command })))
+        }
+        (Method::Post, ["instances", name, "backups"]) => {
+            require(scope, TokenScope::Operator)?;
+            let body = read_body(request)?;
+            let tag = body
+                .get("tag")
+                .and_then(|t| t.as_str())
+                .map(|t| t.to_string());
+            let format: Option<BackupFormat> = match body.get("format") {
+                None | Some(Value::Null) => None,
+                Some(format) => Some(serde_json::from_value(format.clone()).map_err(|_| {
+                    ApiError::new(
+                        400,
+                        "format must be one of dir, tar.gz, tar.zst, zip or incremental",
+                    )
+                })?),
+            };
+            let backup_scope: Option<BackupScope> = match body.get("scope") {
+                None | Some(Value::Null) => None,
+                Some(backup_scope) => Some(
+                    serde_json::from_value(backup_scope.clone())
+                        .map_err(|_| ApiError::new(400, "scope must be world or instance"))?,
+                ),
+            };
+            let path = backup(name, tag, format, backup_scope)?;
+            Ok(Reply::Json(201, json!({ "path": path })))
+        }
+        (Method::Get, ["instances", name, "logs"]) => {
+            require(scope, TokenScope::ReadOnly)?;
+            Server::load_by_name(&name.to_string())?;
+            let backlog = match query_param(query, "lines")? {
+                Some(lines) => lines
+                    .parse::<usize>()
+                    .map_err(|_| ApiError::new(400, "lines must be a number"))?,
+                None => DEFAULT_LOG_BACKLOG,
+            };
+            Ok(Reply::Logs(name.to_string(), backlog))
+        }
+        (Method::Get, ["instances", name, "properties"]) => {
+            require(scope, TokenScope::ReadOnly)?;
+            let server = Server::load_by_name(&name.to_string())?;
+            let properties: BTreeMap<String, String> = read_properties(&server.path.join(name))?
+                .into_iter()
+                .collect();
+            Ok(Reply::Json(200, json!(properties)))
+        }
+        (Method::Patch, ["instances", name, "properties"]) => {
+            require(scope, TokenScope::Admin)?;
+            let server = Server::load_by_name(&name.to_string())?;
+            let server_dir = server.path.join(name);
+            let changes = property_changes(read_body(request)?)?;
+            set_properties(&server_dir, &changes)?;
+            let properties: BTreeMap<String, String> =
+                read_properties(&server_dir)?.into_iter().collect();
+            Ok(Reply::Json(
+                200,
+                json!({
+                    "properties": properties,
+                    "restart_required": running_pid(&server_dir).is_some(),
+                }),
+            ))
+        }
+        (_, ["instances"]) | (_, ["instances", _]) | (_, ["instances", _, _]) => {
+            Err(ApiError::new(405, "method not allowed"))
+        }
+        _ => Err(ApiError::new(404, "not found")),
+    }
+}
+
+fn require(scope: TokenScope, needed: TokenScope) -> Result<(), ApiError> {
+    match scope >= needed {
+        true => Ok(()),
+        false => Err(ApiError::new(
+            403,
+            format!("this needs a token with the {} scope", needed.name()),
+        )),
+    }
+}
+
+fn describe(server: &Server) -> Value {
+    let pid = running_pid(&server.path.join(&server.name));
+    json!({
+        "name": server.name,
+        "path": server.path.join(&server.name),
+        "version": server.version,
+        "flavor": server.flavor,
+        "running": pid.is_some(),
+        "pid": pid,
+        "memory": server.memory,
+        "autostart": server.autostart,
+        "restart_policy": server.restart_policy,
+        "restarts": server.restarts,
+    })
+}
+
+// the actions go through the daemon when it's running, so it knows which stops are deliberate
+
+fn start(name: &str, memory: Option<u32>) -> Result<(), ApiError> {
+    if let Some(result) = try_daemon(ControlCommand::Start {
+        name: name.to_string(),
+        memory,
+    }) {
+        return result.map(|_| ()).map_err(|e| ApiError::new(409, e));
+    }
+
+    let server = Server::load_by_name(&name.to_string())?;
+    if running_pid(&server.path.join(name)).is_some() {
+        return Err(ApiError::new(409, "server is already running"));
+    }
+    let mut child = spawn_background_server(name, memory)?;
+    // reap the background run whenever it exits
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    Ok(())
+}
+
+fn stop(name: &str, timeout: u64) -> Result<(), ApiError> {
+    let result = match try_daemon(ControlCommand::Stop {
+        name: name.to_string(),
+        timeout,
+    }) {
+        Some(result) => result.map(|_| ()),
+        None => stop_server(&name.to_string(), Duration::from_secs(timeout)),
+    };
+    result.map_err(ApiError::from)
+}
+
+fn restart(name: &str) -> Result<(), ApiError> {
+    let result = match try_daemon(ControlCommand::Restart {
+        name: name.to_string(),
+    }) {
+        Some(result) => result.map(|_| ()),
+        None => restart_with_countdown(&name.to_string(), &[]),
+    };
+    result.map_err(ApiError::from)
+}
+
+fn console(name: &str, command: &str) -> Result<(), ApiError> {
+    let result = match try_daemon(ControlCommand::Console {
+        name: name.to_string(),
+        command: command.to_string(),
+    }) {
+        Some(result) => result.map(|_| ()),
+        None => Server::load_by_name(&name.to_string())
+            .and_then(|server| send_console_command(&server.path.join(name), command)),
+    };
+    result.map_err(ApiError::from)
+}
+
+fn backup(
+    name: &str,
+    tag: Option<String>,
+    format: Option<BackupFormat>,
+    scope: Option<BackupScope>,
+) -> Result<Value, ApiError> {
+    let result = match try_daemon(ControlCommand::WorldBackup {
+        name: name.to_string(),
+        tag: tag.clone(),
+        format,
+        scope,
+    }) {
+        Some(result) => result.map(|data| data["path"].clone()),
+        None => {
+            create_world_backup(0, name.to_string(), tag, format, scope).map(|path| json!(path))
+        }
+    };
+    result.map_err(ApiError::from)
+}
+
+// a PATCH body is an object of property names to new values (strings, numbers or booleans)
+fn property_changes(body: Value) -> Result<Vec<(String, String)>, ApiError> {
+    let object: Map<String, Value> = match body {
+        Value::Object(object) if !object.is_empty() => object,
+        _ => {
+            return Err(ApiError::new(
+                400,
+                "expected an object of property names to values",
+            ))
+        }
+    };
+
+    object
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s,
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => {
+                    return Err(ApiError::new(
+                        400,
+                        format!("{} must be a string, number or boolean", key),
+                    ))
+                }
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+fn read_body(request: &mut Request) -> Result<Value, ApiError> {
+    let mut body = String::new();
+    request
+        .as_reader()
+        .take(MAX_BODY_SIZE)
+        .read_to_string(&mut body)
+        .map_err(|e| ApiError::new(400, format!("failed to read request body: {}", e)))?;
+    if body.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    serde_json::from_str(&body).map_err(|e| ApiError::new(400, format!("invalid JSON: {}", e)))
+}
+
+fn query_param(query: &str, key: &str) -> Result<Option<String>, ApiError> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| percent_decode(&v.replace('+', " ")))
+        .transpose()
+}
+
+fn percent_decode(value: &str) -> Result<String, ApiError> {
+    let invalid = || ApiError::new(400, format!("invalid percent-encoding: {}", value));
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = value.get(i + 1..i + 3).ok_or_else(invalid)?;
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+                i += 3;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+fn json_response(status: u16, body: &Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    Response::from_string(format!("{}\n", body))
+        .with_status_code(status)
+        .with_header(
+            Header::from_bytes("Content-Type", "application/json").expect("static header is valid"),
+        )
+}
+
+// follow an instance's captured output as Server-Sent Events, one `data:` line per log line;
+// tiny_http buffers chunked bodies, so this writes the response itself to flush every event
+fn stream_logs(request: Request, name: &str, backlog: usize) -> std::io::Result<()> {
+    let server_dir = match Server::load_by_name(&name.to_string()) {
+        Ok(server) => server.path.join(name),
+        Err(e) => return request.respond(json_response(404, &json!({ "error": e }))),
+    };
+    let (mut tail, lines) = match CapturedTail::with_backlog(&server_dir, backlog) {
+        Ok(tail) => tail,
+        Err(e) => return request.respond(json_response(500, &json!({ "error": e }))),
+    };
+
+    let mut writer = request.into_writer();
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    send_lines(&mut writer, &lines)?;
+
+    let mut last_write = Instant::now();
+    loop {
+        thread::sleep(LOG_POLL_INTERVAL);
+        let lines = tail.poll().unwrap_or_default();
+        if !lines.is_empty() {
+            send_lines(&mut writer, &lines)?;
+            last_write = Instant::now();
+        } else if last_write.elapsed() >= LOG_KEEPALIVE {
+            writer.write_all(b": keepalive\n\n")?;
+            writer.flush()?;
+            last_write = Instant::now();
+        }
+    }
+}
+
+fn send_lines(writer: &mut impl Write, lines: &[String]) -> std::io::Result<()> {
+    for line in lines {
+        write!(writer, "event: log\ndata: {}\n\n", line)?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,286 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use age::Encryptor;
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::crypto::{BackupKeys, BackupWriter};
+use crate::snapshot::SNAPSHOT_EXTENSION;
+
+// zstd's default level; higher levels are much slower for little gain on region files
+const ZSTD_LEVEL: i32 = 3;
+// the formats an encrypted backup can be written in
+pub const ENCRYPTED_FORMATS_ONLY: &str =
+    "encrypted backups have to be tar.gz or tar.zst (dir, zip and incremental backups can't be encrypted)";
+
+// how a backup is stored on disk
+#[derive(
+    ValueEnum,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+pub enum BackupFormat {
+    /// a plain copy of the world directory
+    #[default]
+    #[value(name = "dir")]
+    #[serde(rename = "dir")]
+    Dir,
+    /// a gzip-compressed tarball
+    #[value(name = "tar.gz")]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    /// a zstd-compressed tarball (faster and smaller than gzip)
+    #[value(name = "tar.zst")]
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    /// a zip file
+    #[value(name = "zip")]
+    #[serde(rename = "zip")]
+    Zip,
+    /// a snapshot manifest over a deduplicated chunk store shared by all incremental backups
+    #[value(name = "incremental")]
+    #[serde(rename = "incremental")]
+    Incremental,
+}
+
+impl BackupFormat {
+    // the formats stored as a single file
+    pub const FILE_FORMATS: [BackupFormat; 4] = [
+        BackupFormat::TarGz,
+        BackupFormat::TarZst,
+        BackupFormat::Zip,
+        BackupFormat::Incremental,
+    ];
+
+    // whether backups in this format can be encrypted, which takes a single file written front to
+    // back
+    pub fn can_encrypt(&self) -> bool {
+        matches!(self, BackupFormat::TarGz | BackupFormat::TarZst)
+    }
+
+    // the file extension a backup in this format gets (none for directories)
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            BackupFormat::Dir => None,
+            BackupFormat::TarGz => Some("tar.gz"),
+            BackupFormat::TarZst => Some("tar.zst"),
+            BackupFormat::Zip => Some("zip"),
+            BackupFormat::Incremental => Some(SNAPSHOT_EXTENSION),
+        }
+    }
+
+    // the format of an existing backup, judging by its name
+    pub fn of(path: &Path) -> Option<Self> {
+        if path.is_dir() {
+            return Some(BackupFormat::Dir);
+        }
+        let name = path.file_name()?.to_str()?;
+        BackupFormat::FILE_FORMATS
+            .into_iter()
+            .find(|format| name.ends_with(&format!(".{}", format.extension().unwrap_or_default())))
+    }
+}
+
+impl fmt::Display for BackupFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupFormat::Dir => write!(f, "dir"),
+            BackupFormat::Incremental => write!(f, "incremental"),
+            _ => write!(f, "{}", self.extension().unwrap_or_default()),
+        }
+    }
+}
+
+// strip a backup's archive extension, leaving the name `build_backup_name` gave it
+pub fn backup_stem(name: &str) -> &str {
+    BackupFormat::FILE_FORMATS
+        .iter()
+        .find_map(|format| name.strip_suffix(&format!(".{}", format.extension()?)))
+        .unwrap_or(name)
+}
+
+// write `entries` (paths relative to `root`) into an archive at `destination`, streaming each file
+// straight from the source so nothing gets staged on disk first; tarballs are encrypted on the way
+// out when given an encryptor
+pub fn write_archive(
+    root: &Path,
+    entries: &[String],
+    destination: &Path,
+    format: BackupFormat,
+    encryptor: Option<Encryptor>,
+) -> Result<(), String> {
+    let files = walk_entries(root, entries)?;
+
+    match format {
+        BackupFormat::Dir | BackupFormat::Incremental => {
+            return Err(format!("a {} backup isn't an archive", format))
+        }
+        BackupFormat::TarGz => {
+            let writer = BackupWriter::create(destination, encryptor)?;
+            let encoder = write_tar(GzEncoder::new(writer, Compression::default()), &files)?;
+            finish(encoder.finish().and_then(|writer| writer.finish()))?;
+        }
+        BackupFormat::TarZst => {
+            let writer = BackupWriter::create(destination, encryptor)?;
+            let mut encoder = zstd::Encoder::new(writer, ZSTD_LEVEL)
+                .map_err(|e| format!("failed to start zstd stream: {}", e))?;
+            // like gzip's CRC, so a damaged archive fails to read back instead of restoring garbage
+            encoder
+                .include_checksum(true)
+                .map_err(|e| format!("failed to start zstd stream: {}", e))?;
+            let encoder = write_tar(encoder, &files)?;
+            finish(encoder.finish().and_then(|writer| writer.finish()))?;
+        }
+        BackupFormat::Zip => {
+            // zip goes back to fill in each entry's header, which an encrypted stream can't do
+            if encryptor.is_some() {
+                return Err(ENCRYPTED_FORMATS_ONLY.to_string());
+            }
+            let file = File::create(destination)
+                .map_err(|e| format!("failed to create {}: {}", destination.display(), e))?;
+            let mut zip = ZipWriter::new(BufWriter::new(file));
+            let options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .large_file(true);
+            for (path, relative) in &files {
+                let name = entry_name(relative);
+                if path.is_dir() {
+                    zip.add_directory(name, options).map_err(|e| {
+                        format!("failed to add {} to zip: {}", relative.display(), e)
+                    })?;
+                    continue;
+                }
+                zip.start_file(name, options)
+                    .map_err(|e| format!("failed to add {} to zip: {}", relative.display(), e))?;
+                let mut input = File::open(path)
+                    .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+                io::copy(&mut input, &mut zip)
+                    .map_err(|e| format!("failed to write {} to zip: {}", relative.display(), e))?;
+            }
+            finish(
+                zip.finish()
+                    .map_err(io::Error::other)
+                    .and_then(|mut writer| writer.flush()),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// unpack an archive backup into `destination`, which must already exist, decrypting it with `keys`
+// if it's encrypted
+pub fn extract_archive(
+    archive: &Path,
+    destination: &Path,
+    keys: &BackupKeys,
+) -> Result<(), String> {
+    let format = BackupFormat::of(archive)
+        .ok_or_else(|| format!("not a backup archive: {}", archive.display()))?;
+
+    // both unpackers refuse entries that would land outside `destination`
+    match format {
+        BackupFormat::Dir | BackupFormat::Incremental => {
+            return Err(format!("a {} backup isn't an archive", format))
+        }
+        BackupFormat::TarGz => {
+            tar::Archive::new(GzDecoder::new(keys.open(archive)?)).unpack(destination)
+        }
+        BackupFormat::TarZst => {
+            let decoder = zstd::Decoder::new(keys.open(archive)?)
+                .map_err(|e| format!("failed to start zstd stream: {}", e))?;
+            tar::Archive::new(decoder).unpack(destination)
+        }
+        BackupFormat::Zip => {
+            let file = File::open(archive)
+                .map_err(|e| format!("failed to open {}: {}", archive.display(), e))?;
+            ZipArchive::new(BufReader::new(file))
+                .and_then(|mut zip| zip.extract(destination))
+                .map_err(io::Error::other)
+        }
+    }
+    .map_err(|e| format!("failed to extract {}: {}", archive.display(), e))
+}
+
+fn write_tar<W: Write>(writer: W, files: &[(PathBuf, PathBuf)]) -> Result<W, String> {
+    let mut builder = tar::Builder::new(writer);
+    // store symlinks as links rather than whatever they point to
+    builder.follow_symlinks(false);
+    for (path, relative) in files {
+        builder
+            .append_path_with_name(path, relative)
+            .map_err(|e| format!("failed to add {} to archive: {}", relative.display(), e))?;
+    }
+    builder
+        .into_inner()
+        .map_err(|e| format!("failed to finish archive: {}", e))
+}
+
+fn finish(result: io::Result<()>) -> Result<(), String> {
+    result.map_err(|e| format!("failed to finish archive: {}", e))
+}
+
+// every file and directory under `root`, paired with its path relative to `root`, parents first
+pub fn walk(root: &Path) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut entries = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut children: Vec<PathBuf> = fs::read_dir(&dir)
+            .map_err(|e| format!("failed to read {}: {}", dir.display(), e))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect();
+        children.sort();
+        for path in children {
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            if path.is_dir() && !path.is_symlink() {
+                pending.push(path.clone());
+            }
+            entries.push((path, relative));
+        }
+    }
+    Ok(entries)
+}
+
+// like `walk`, but only for `entries` (paths relative to `root`) and including the entries
+// themselves
+pub fn walk_entries(root: &Path, entries: &[String]) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = root.join(entry);
+        files.push((path.clone(), PathBuf::from(entry)));
+        if path.is_dir() && !path.is_symlink() {
+            files.extend(
+                walk(&path)?
+                    .into_iter()
+                    .map(|(file, relative)| (file, Path::new(entry).join(relative))),
+            );
+        }
+    }
+    Ok(files)
+}
+
+// a relative path as an archive entry name, which always uses forward slashes
+pub fn entry_name(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -4,7 +4,16 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
-use crate::memory::parse_mem;
+use crate::archive::BackupFormat;
+use crate::backup::BackupScope;
+use crate::daemon::RestartPolicy;
+use crate::logs::{LogLevel, LogSource};
+use crate::memory::{parse_mem, parse_size};
+use crate::selection::RestoreFilter;
+use crate::systemd::SystemdAction;
+use crate::tokens::TokenScope;
+use crate::watchdog::ProbeKind;
+use crate::webhooks::{WebhookEvent, WebhookFormat};
 
 #[derive(Parser)]
 #[command(
@@ -77,12 +86,77 @@ pub enum Commands {
         /// suppress output from the server instance (generally not recommended)
         #[arg(long, default_value = "false")]
         quiet: bool,
+        /// start the server in the background and return once it is ready to accept players
+        #[arg(long, default_value = "false")]
+        wait_ready: bool,
+        /// how long --wait-ready waits for the server, in seconds
+        #[arg(long, default_value = "300")]
+        ready_timeout: u64,
+        /// print server events (ready, joins, chat, deaths, ...) as JSON lines
+        #[arg(long, default_value = "false")]
+        events: bool,
+        /// run the server in this process even when the daemon is running
+        #[arg(long, default_value = "false")]
+        no_daemon: bool,
+    },
+    /// stop a running instance with the console `stop` command
+    Stop {
+        /// the name of the server instance
+        name: String,
+        /// seconds to wait for the server to exit before killing it
+        #[arg(long, default_value = "90")]
+        timeout: u64,
+    },
+    /// show or change the JVM settings an instance runs with
+    Jvm {
+        /// the name of the server instance
+        name: String,
+        /// the amount of memory to allocate when `run` isn't given --memory
+        #[arg(long, value_parser = parse_mem)]
+        memory: Option<u32>, // MiB
+        /// an extra JVM argument (repeatable), e.g. --arg=-XX:+UseG1GC
+        #[arg(long = "arg", allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// remove the stored JVM arguments (before adding any given with --arg)
+        #[arg(long, default_value = "false")]
+        clear_args: bool,
+    },
+    /// supervise autostart instances and accept commands on a local socket
+    Daemon,
+    /// choose whether the daemon starts an instance, and how it restarts it
+    Autostart {
+        /// the name of the server instance
+        name: String,
+        /// whether the daemon should start the instance (true/false)
+        #[arg(action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+        enabled: bool,
+        /// when the daemon should restart the instance after it exits
+        #[arg(long, value_enum)]
+        restart: Option<RestartPolicy>,
+    },
+    /// run an instance as a systemd service
+    Systemd {
+        /// the name of the server instance
+        name: String,
+        /// what to do with the unit
+        #[arg(value_enum)]
+        action: SystemdAction,
+        /// use a user unit (~/.config/systemd/user) instead of a system unit
+        #[arg(long, default_value = "false")]
+        user: bool,
+        /// the account a system unit runs the server as (defaults to the user running slapaman,
+        /// or the one who ran sudo; root has to be asked for here)
+        #[arg(long, conflicts_with = "user")]
+        run_as: Option<String>,
     },
     /// list all instances
     List {
         /// whether to list all instances in detail
         #[arg(long, default_value = "false")]
         detailed: bool,
+        /// show CPU, memory and disk usage for each instance
+        #[arg(long, default_value = "false")]
+        resources: bool,
     },
     /// update an existing instance to a new version
     Update {
@@ -108,20 +182,494 @@ pub enum Commands {
         /// optional tag appended to the backup directory name
         #[arg(long)]
         tag: Option<String>,
+        /// how to store the backup (defaults to the instance's `world-backup-config` format)
+        #[arg(long, value_enum)]
+        format: Option<BackupFormat>,
+        /// what to back up (defaults to the instance's `world-backup-config` scope)
+        #[arg(long, value_enum)]
+        scope: Option<BackupScope>,
+    },
+    /// show or change how an instance's world backups are taken
+    WorldBackupConfig {
+        /// the name of the server instance
+        name: String,
+        /// the format `world-backup` uses when not given --format
+        #[arg(long, value_enum)]
+        format: Option<BackupFormat>,
+        /// what `world-backup` takes when not given --scope
+        #[arg(long, value_enum)]
+        scope: Option<BackupScope>,
+        /// keep the newest N backups
+        #[arg(long)]
+        keep_last: Option<u32>,
+        /// keep the newest backup of each day for this many days
+        #[arg(long)]
+        keep_daily: Option<u32>,
+        /// keep the newest backup of each week for this many weeks
+        #[arg(long)]
+        keep_weekly: Option<u32>,
+        /// keep the newest backup of each month for this many months
+        #[arg(long)]
+        keep_monthly: Option<u32>,
+        /// prune the oldest backups until they all fit in this size (e.g. 20G)
+        #[arg(long, value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// never prune backups that were given a --tag
+        #[arg(long, action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+        protect_tagged: Option<bool>,
+        /// remove the retention policy, keeping every backup
+        #[arg(long, default_value = "false")]
+        clear_retention: bool,
+        /// split region files into 4 KiB sectors in incremental backups, so only changed chunks
+        /// get stored
+        #[arg(long, action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+        region_sectors: Option<bool>,
+        /// announce backups of the running server in-game
+        #[arg(long, action = clap::ArgAction::Set, value_parser = clap::builder::BoolishValueParser::new())]
+        announce: Option<bool>,
+        /// encrypt backups (tar.gz and tar.zst only) with a "passphrase", to age public keys
+        /// (age1..., comma-separated), or not at all ("off")
+        #[arg(long)]
+        encrypt: Option<String>,
+        /// a file holding the passphrase, or the age identities that decrypt backups on this
+        /// machine
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// delete old world backups according to the instance's retention policy
+    WorldPrune {
+        /// the name of the server instance
+        name: String,
+        /// only show what would be deleted
+        #[arg(long, default_value = "false")]
+        dry_run: bool,
+    },
+    /// list an instance's world backups, oldest first
+    WorldBackups {
+        /// the name of the server instance
+        name: String,
+        /// skip reading every backup back to check it
+        #[arg(long, default_value = "false")]
+        no_verify: bool,
+        /// list the backups stored at this destination instead
+        #[arg(long)]
+        from: Option<String>,
+        /// print the list as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+        /// a file holding the passphrase or age identities for encrypted backups
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// show the details of one world backup
+    WorldBackupShow {
+        /// the name of the server instance
+        name: String,
+        /// the backup's index in `world-backups`, "latest", name or path
+        backup: PathBuf,
+        /// print the details as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+        /// a file holding the passphrase or age identities for encrypted backups
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// delete a world backup
+    WorldBackupDelete {
+        /// the name of the server instance
+        name: String,
+        /// the backup's index in `world-backups`, "latest", name or path
+        backup: PathBuf,
+    },
+    /// show what changed between a world backup and another backup or the live world, down to
+    /// the chunks of region files
+    WorldDiff {
+        /// the name of the server instance
+        name: String,
+        /// the backup's index in `world-backups`, "latest", name or path
+        backup: PathBuf,
+        /// the backup to compare it with, or "live" for the instance's world as it is now
+        #[arg(default_value = "live")]
+        other: PathBuf,
+        /// print the differences as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+        /// a file holding the passphrase or age identities for encrypted backups
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// check world backups against the manifests written when they were taken
+    WorldBackupVerify {
+        /// the name of the server instance
+        name: String,
+        /// the backup's index in `world-backups`, "latest", name or path (the newest if omitted)
+        backup: Option<PathBuf>,
+        /// verify every backup of the instance
+        #[arg(long, default_value = "false", conflicts_with = "backup")]
+        all: bool,
+        /// a file holding the passphrase or age identities for encrypted backups
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+    },
+    /// upload a world backup to the instance's backup destinations
+    WorldBackupPush {
+        /// the name of the server instance
+        name: String,
+        /// the backup's index in `world-backups`, "latest", name or path (the newest if omitted)
+        backup: Option<PathBuf>,
+        /// only upload to this destination
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// manage where an instance's backups are uploaded to after they're taken
+    WorldBackupDestination {
+        /// the name of the server instance
+        name: String,
+        #[command(subcommand)]
+        command: DestinationCommands,
     },
     /// restore an instance's world from a backup
     WorldRestore {
         /// the name of the server instance
         name: String,
-        /// the path or identifier of the backup directory to restore
+        /// the backup's index in `world-backups`, "latest", name or path
         backup: PathBuf,
+        /// restore even if the backup fails verification
+        #[arg(long, default_value = "false")]
+        force: bool,
+        /// download the backup from this destination first (the backup is then its index or id
+        /// in `world-backups --from`, or "latest")
+        #[arg(long)]
+        from: Option<String>,
+        /// don't back up the instance's current world before replacing it
+        #[arg(long, default_value = "false")]
+        no_snapshot: bool,
+        /// a file holding the passphrase or age identities for encrypted backups
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// restore only part of the world, leaving the rest as it is; repeatable: overworld,
+        /// nether, end, region:X,Z, area:X1,Z1,X2,Z2 (block coordinates), player:UUID-or-name or
+        /// file:PATH (e.g. file:level.dat), with @nether or @end after regions, areas and files
+        /// in the other dimensions
+        #[arg(long, value_parser = RestoreFilter::parse)]
+        only: Vec<RestoreFilter>,
     },
     /// set the world for an instance to a pre-existing world
     WorldSet {
         /// the name of the server instance
         name: String,
-        /// the path to the world file to copy over to the server instance
+        /// the path to the world to copy over to the server instance (its `_nether` and
+        /// `_the_end` siblings come along on flavors that keep dimensions apart)
         world_path: PathBuf,
+        /// don't back up the instance's current world before replacing it
+        #[arg(long, default_value = "false")]
+        no_snapshot: bool,
+    },
+    /// show an instance's server logs
+    Logs {
+        /// the name of the server instance
+        name: String,
+        /// which logs to read
+        #[arg(long, value_enum, default_value = "captured")]
+        source: LogSource,
+        /// keep printing new lines as they are written
+        #[arg(short, long, default_value = "false")]
+        follow: bool,
+        /// only show lines newer than this (e.g. 30m, 2h, 1d, or "2025-01-31 18:00")
+        #[arg(long)]
+        since: Option<String>,
+        /// only show lines matching this regular expression
+        #[arg(long)]
+        grep: Option<String>,
+        /// only show lines at or above this level
+        #[arg(long, value_enum)]
+        level: Option<LogLevel>,
+    },
+    /// query a server over Server List Ping
+    Ping {
+        /// the name of a server instance, or host[:port] of any server
+        target: String,
+        /// how long to wait for the server, in seconds
+        #[arg(long, default_value = "5")]
+        timeout: u64,
+        /// print the result as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// query an instance over the UDP query protocol (needs enable-query=true)
+    Query {
+        /// the name of the server instance
+        name: String,
+        /// only request the basic stat (no player list or plugins)
+        #[arg(long, default_value = "false")]
+        basic: bool,
+        /// how long to wait for the server, in seconds
+        #[arg(long, default_value = "5")]
+        timeout: u64,
+        /// print the result as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// show whether instances are running and accepting connections
+    Status {
+        /// the name of the server instance (omit = all)
+        name: Option<String>,
+        /// how long to wait for each server, in seconds
+        #[arg(long, default_value = "5")]
+        timeout: u64,
+        /// print the result as JSON
+        #[arg(long, default_value = "false")]
+        json: bool,
+    },
+    /// watch running instances and restart any that stop responding
+    Watchdog {
+        /// the server instances to watch (omit = all)
+        names: Vec<String>,
+        /// how to check that a server is responsive
+        #[arg(long, value_enum, default_value = "ping")]
+        probe: ProbeKind,
+        /// seconds between probes
+        #[arg(long, default_value = "30")]
+        interval: u64,
+        /// seconds to wait for each probe
+        #[arg(long, default_value = "10")]
+        timeout: u64,
+        /// consecutive failed probes before a server is considered hung
+        #[arg(long, default_value = "3")]
+        failures: u32,
+        /// seconds after a server starts before probes count against it
+        #[arg(long, default_value = "180")]
+        grace: u64,
+        /// also fail a probe when nothing has been logged for this many seconds
+        /// (only useful for servers that log regularly)
+        #[arg(long)]
+        stale_log: Option<u64>,
+        /// probe every instance once and exit
+        #[arg(long, default_value = "false")]
+        once: bool,
+    },
+    /// show live resource usage of running instances
+    Top {
+        /// seconds between refreshes
+        #[arg(long, default_value = "2")]
+        interval: u64,
+        /// print one snapshot and exit
+        #[arg(long, default_value = "false")]
+        once: bool,
+    },
+    /// manage an instance's scheduled tasks
+    Schedule {
+        /// the name of the server instance
+        name: String,
+        #[command(subcommand)]
+        command: ScheduleCommands,
+    },
+    /// run scheduled tasks as they come due
+    Scheduler {
+        /// the server instances to run tasks for (omit = all)
+        names: Vec<String>,
+    },
+    /// export instance metrics for Prometheus
+    Metrics {
+        #[command(subcommand)]
+        command: MetricsCommands,
+    },
+    /// serve the HTTP/JSON API
+    Serve {
+        /// the address to listen on
+        #[arg(long, default_value = "127.0.0.1:9230")]
+        listen: String,
+    },
+    /// manage the bearer tokens the HTTP API accepts
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+    /// manage webhooks notified about server events
+    Webhook {
+        #[command(subcommand)]
+        command: WebhookCommands,
+    },
+    /// install and manage slapaman's own JDKs
+    Java {
+        #[command(subcommand)]
+        command: JavaCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum DestinationCommands {
+    /// add a destination; backups there are never pruned by slapaman, so use the storage's own
+    /// lifecycle rules to expire them
+    Add {
+        /// a name to refer to the destination by
+        destination: String,
+        /// a directory (or file:// URL), sftp://[user@]host[:port]/path (/~/path for one in the
+        /// login directory), or s3://bucket[/prefix]
+        url: String,
+        /// the S3 endpoint, for S3-compatible storage like MinIO (e.g. http://localhost:9000)
+        #[arg(long)]
+        endpoint: Option<String>,
+        /// the S3 region
+        #[arg(long)]
+        region: Option<String>,
+        /// the S3 access key (defaults to $AWS_ACCESS_KEY_ID when uploading)
+        #[arg(long, requires = "secret_key")]
+        access_key: Option<String>,
+        /// the S3 secret key (defaults to $AWS_SECRET_ACCESS_KEY when uploading); prefer the
+        /// environment, as a key given here is stored in slapaman's registry
+        #[arg(long, requires = "access_key")]
+        secret_key: Option<String>,
+        /// the private key ssh and sftp log in with
+        #[arg(long)]
+        identity: Option<PathBuf>,
+    },
+    /// list the instance's backup destinations
+    List,
+    /// remove a backup destination (the backups already there are left alone)
+    Remove {
+        /// the name given to `add`
+        destination: String,
+    },
+    /// check that a destination can be reached
+    Test {
+        /// the name given to `add`
+        destination: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ScheduleCommands {
+    /// add a task that runs on a cron schedule
+    Add {
+        /// when to run, as a five-field cron expression in local time (e.g. "0 4 * * *")
+        cron: String,
+        #[command(subcommand)]
+        task: TaskCommands,
+    },
+    /// list the instance's scheduled tasks
+    List,
+    /// remove a scheduled task
+    Remove {
+        /// the id shown by `schedule <name> list`
+        id: u32,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TaskCommands {
+    /// back up the world
+    Backup {
+        /// a tag to add to the backup name
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    /// restart the server, warning players in-game first
+    Restart {
+        /// seconds before the restart to broadcast a warning at
+        #[arg(long, value_delimiter = ',', default_value = "600,300,60,10")]
+        warn: Vec<u64>,
+    },
+    /// send a command to the server console
+    Command {
+        /// the console command, e.g. "say hello"
+        #[arg(required = true, num_args = 1.., trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// check whether a newer version is available
+    UpdateCheck,
+}
+
+#[derive(Subcommand)]
+pub enum MetricsCommands {
+    /// serve OpenMetrics over HTTP at /metrics
+    Serve {
+        /// the address to listen on
+        #[arg(long, default_value = "127.0.0.1:9225")]
+        listen: String,
+        /// how long to wait for each server's ping or RCON reply, in seconds
+        #[arg(long, default_value = "2")]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// create a token; its secret is printed once and can't be shown again
+    Create {
+        /// a note on who or what uses the token
+        label: String,
+        /// what the token may do
+        #[arg(long, value_enum, default_value = "read-only")]
+        scope: TokenScope,
+    },
+    /// list tokens (without their secrets)
+    List,
+    /// revoke a token
+    Revoke {
+        /// the id shown by `token list`
+        id: u32,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum WebhookCommands {
+    /// add a webhook for every instance, or for one with --instance
+    Add {
+        /// the URL to POST notifications to
+        url: String,
+        /// only notify about this server instance
+        #[arg(long)]
+        instance: Option<String>,
+        /// the payload format the receiving end expects
+        #[arg(long, value_enum, default_value = "generic")]
+        format: WebhookFormat,
+        /// the events to send (default: every lifecycle event, but not player joins and leaves)
+        #[arg(long, value_enum, value_delimiter = ',')]
+        events: Vec<WebhookEvent>,
+    },
+    /// list configured webhooks
+    List,
+    /// remove a webhook
+    Remove {
+        /// the id shown by `webhook list`
+        id: u32,
+    },
+    /// send a test notification to a webhook (omit the id to test them all)
+    Test {
+        /// the id shown by `webhook list`
+        id: Option<u32>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum JavaCommands {
+    /// download and install a JDK
+    Install {
+        /// the major Java version to install (e.g. 21)
+        major: u32,
+        /// the Adoptium-compatible API to download from (defaults to $SLAPAMAN_JDK_API or api.adoptium.net)
+        #[arg(long)]
+        api: Option<String>,
+    },
+    /// list installed JDKs
+    List,
+    /// remove an installed JDK
+    Remove {
+        /// the major Java version to remove
+        major: u32,
+    },
+    /// launch an instance with an installed JDK instead of the system java
+    Pin {
+        /// the name of the server instance
+        name: String,
+        /// the major Java version to pin the instance to
+        major: u32,
+    },
+    /// launch an instance with the system java again
+    Unpin {
+        /// the name of the server instance
+        name: String,
     },
 }
 
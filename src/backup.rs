@@ -3,61 +3,439 @@
 
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use chrono::Utc;
+use clap::ValueEnum;
 use fs_extra::{copy_items, dir::CopyOptions};
+use serde_json::json;
 
-use crate::server::Server;
-use crate::world::set_world;
+use crate::archive::{extract_archive, write_archive, BackupFormat, ENCRYPTED_FORMATS_ONLY};
+use crate::catalog::{list_backups, resolve_backup};
+use crate::console::{send_to_running_server, RCON_TIMEOUT};
+use crate::crypto::{BackupKeys, Encryption, KEY_FILE_ENV, PASSPHRASE_ENV};
+use crate::destinations::{pull_backup, push_to_destinations, BackupDestination};
+use crate::events::{EventParser, ServerEvent};
+use crate::integrity::{verify_backup, write_manifest};
+use crate::logs::CapturedTail;
+use crate::resources::format_bytes;
+use crate::retention::{prune_backups, RetentionPolicy};
+use crate::selection::{extract_selection, RestoreFilter, Selection, REGION_FOLDERS};
+use crate::server::{update_server_by_name, Server};
+use crate::snapshot::{is_snapshot, restore_snapshot, write_snapshot};
+use crate::webhooks::{notify, Notification, WebhookEvent};
+use crate::world::{
+    dimension_dir, ensure_stopped, install_world, server_running, swap_in, world_dirs,
+};
 
-const WORLD_DIR_NAME: &str = "world";
-const BACKUPS_DIR_NAME: &str = "backups";
+pub const BACKUPS_DIR_NAME: &str = "backups";
 const LEVEL_DAT: &str = "level.dat";
+// what the server prints once `save-all` is done
+const SAVED_MESSAGE: &str = "Saved the game";
+// how long to wait for that before giving up on a live backup
+const SAVE_TIMEOUT: Duration = Duration::from_secs(60);
+// where backups are unpacked while restoring
+const RESTORE_STAGING_DIR_NAME: &str = ".slapaman-restore";
+// the tag on the backup taken of an instance before a restore or world-set replaces its world
+const SAFETY_SNAPSHOT_TAG: &str = "pre-restore";
+// what an instance-scope backup takes on top of the worlds, if present
+pub const INSTANCE_FILES: [&str; 16] = [
+    "server.properties",
+    "eula.txt",
+    "ops.json",
+    "whitelist.json",
+    "banned-players.json",
+    "banned-ips.json",
+    "usercache.json",
+    "bukkit.yml",
+    "spigot.yml",
+    "paper.yml",
+    "commands.yml",
+    "help.yml",
+    "permissions.yml",
+    "config",
+    "plugins",
+    "mods",
+];
 
+// what a backup takes from the instance directory
+#[derive(
+    ValueEnum,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupScope {
+    /// the world folders of every dimension
+    #[default]
+    World,
+    /// the worlds plus server.properties, player lists, configs, plugins and mods
+    Instance,
+}
+
+impl BackupScope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackupScope::World => "world",
+            BackupScope::Instance => "instance",
+        }
+    }
+}
+
+// how an instance's backups are taken, stored in the registry
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug, Default)]
+pub struct BackupConfig {
+    // used when `world-backup` isn't given a --format
+    #[serde(default)]
+    pub format: BackupFormat,
+    // used when `world-backup` isn't given a --scope
+    #[serde(default)]
+    pub scope: BackupScope,
+    // applied by `world-prune` and after every backup
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    // split region files into sectors in incremental backups, so only changed chunks get stored
+    #[serde(default)]
+    pub sectors: bool,
+    // tell players in-game when a backup of the running server starts and finishes
+    #[serde(default)]
+    pub announce: bool,
+    // where every new backup is also uploaded to
+    #[serde(default)]
+    pub destinations: Vec<BackupDestination>,
+    // how archives and their manifests are encrypted, if at all
+    #[serde(default)]
+    pub encryption: Option<Encryption>,
+    // a file holding the passphrase, or the age identities that decrypt backups on this machine
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+// changes `world-backup-config` makes to an instance's backup config; `None` leaves a setting as
+// it is, and `retention` replaces the whole policy
+#[derive(Default)]
+pub struct BackupConfigChanges {
+    pub format: Option<BackupFormat>,
+    pub scope: Option<BackupScope>,
+    pub retention: Option<RetentionPolicy>,
+    pub sectors: Option<bool>,
+    pub announce: Option<bool>,
+    // `Some(None)` turns encryption off, which forgets the key file too
+    pub encryption: Option<Option<Encryption>>,
+    pub key_file: Option<PathBuf>,
+}
+
+// show or change how an instance's backups are taken
+pub fn configure_backups(name: &String, changes: BackupConfigChanges) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+    let BackupConfigChanges {
+        format,
+        scope,
+        retention,
+        sectors,
+        announce,
+        encryption,
+        key_file,
+    } = changes;
+
+    if format.is_none()
+        && scope.is_none()
+        && retention.is_none()
+        && sectors.is_none()
+        && announce.is_none()
+        && encryption.is_none()
+        && key_file.is_none()
+    {
+        println!("format: {}", server.backup.format);
+        println!("scope: {}", server.backup.scope.name());
+        println!("retention: {}", server.backup.retention.describe());
+        println!(
+            "region sectors: {}",
+            if server.backup.sectors { "on" } else { "off" }
+        );
+        println!(
+            "announce in-game: {}",
+            if server.backup.announce { "on" } else { "off" }
+        );
+        let destinations: Vec<&str> = server
+            .backup
+            .destinations
+            .iter()
+            .map(|d| d.name.as_str())
+            .collect();
+        println!(
+            "destinations: {}",
+            match destinations.is_empty() {
+                true => "none".to_string(),
+                false => destinations.join(", "),
+            }
+        );
+        println!(
+            "encryption: {}",
+            server
+                .backup
+                .encryption
+                .as_ref()
+                .map(|encryption| encryption.describe())
+                .unwrap_or_else(|| "off".to_string())
+        );
+        if let Some(key_file) = &server.backup.key_file {
+            println!("key file: {}", key_file.display());
+        }
+        return Ok(());
+    }
+
+    let encrypted = match &encryption {
+        Some(encryption) => encryption.is_some(),
+        None => server.backup.encryption.is_some(),
+    };
+    if encrypted && !format.unwrap_or(server.backup.format).can_encrypt() {
+        return Err(format!(
+            "{}; pick one with --format",
+            ENCRYPTED_FORMATS_ONLY
+        ));
+    }
+
+    if let Some(format) = format {
+        server.backup.format = format;
+        println!("[slapaman] {} backups now default to {}", name, format);
+    }
+    if let Some(scope) = scope {
+        server.backup.scope = scope;
+        println!(
+            "[slapaman] {} backups now default to the {} scope",
+            name,
+            scope.name()
+        );
+    }
+    if let Some(retention) = retention {
+        println!(
+            "[slapaman] {} backup retention: {}",
+            name,
+            retention.describe()
+        );
+        server.backup.retention = retention;
+    }
+    if let Some(sectors) = sectors {
+        server.backup.sectors = sectors;
+        println!(
+            "[slapaman] {} incremental backups now {} region files into sectors",
+            name,
+            if sectors { "split" } else { "don't split" }
+        );
+    }
+    if let Some(announce) = announce {
+        server.backup.announce = announce;
+        println!(
+            "[slapaman] {} backups of the running server are {} in-game",
+            name,
+            if announce {
+                "announced"
+            } else {
+                "no longer announced"
+            }
+        );
+    }
+    if let Some(encryption) = encryption {
+        match &encryption {
+            Some(Encryption::Passphrase) => println!(
+                "[slapaman] {} backups are now encrypted with a passphrase; without a terminal to ask on (schedules, the daemon) it's read from the key file or ${}, and backups can't be restored without it",
+                name, PASSPHRASE_ENV
+            ),
+            Some(Encryption::Recipients { recipients }) => println!(
+                "[slapaman] {} backups are now encrypted to {} age key(s); restoring them takes a matching identity file",
+                name,
+                recipients.len()
+            ),
+            None => {
+                server.backup.key_file = None;
+                println!(
+                    "[slapaman] {} backups are no longer encrypted (existing encrypted backups stay that way)",
+                    name
+                );
+            }
+        }
+        server.backup.encryption = encryption;
+    }
+    if let Some(key_file) = key_file {
+        if !key_file.is_file() {
+            return Err(format!("key file does not exist: {}", key_file.display()));
+        }
+        let key_file = fs::canonicalize(&key_file)
+            .map_err(|e| format!("failed to resolve {}: {}", key_file.display(), e))?;
+        println!(
+            "[slapaman] {} backups now read their key from {}",
+            name,
+            key_file.display()
+        );
+        server.backup.key_file = Some(key_file);
+    }
+    update_server_by_name(name, &server)
+}
+
+// back up an instance's world, notifying webhooks either way
 pub fn create_world_backup(
     verbose: u8,
     name: String,
     tag: Option<String>,
+    format: Option<BackupFormat>,
+    scope: Option<BackupScope>,
 ) -> Result<PathBuf, String> {
-    let server = Server::load_by_name(&name)?;
-    let server_dir = server.path.join(&name);
+    let result = Server::load_by_name(&name).and_then(|server| {
+        let keys = BackupKeys::new(&server, None);
+        backup_world(verbose, &name, &tag, format, scope, &keys)
+    });
+    notify(match &result {
+        Ok(path) => Notification::new(
+            WebhookEvent::BackupCompleted,
+            &name,
+            format!("backed up {} to {}", name, path.display()),
+            json!({ "path": path, "tag": tag }),
+        ),
+        Err(e) => Notification::new(
+            WebhookEvent::BackupFailed,
+            &name,
+            format!("backing up {} failed: {}", name, e),
+            json!({ "error": e, "tag": tag }),
+        ),
+    });
+
+    // a failed prune shouldn't fail the backup it follows
+    if result.is_ok() {
+        match prune_backups(&name, false) {
+            Ok(decisions) => {
+                for decision in decisions.iter().filter(|d| d.kept_by.is_none()) {
+                    println!("[slapaman] pruned old backup: {}", decision.path.display());
+                }
+            }
+            Err(e) => println!("[slapaman] error pruning old backups: {}", e),
+        }
+    }
+    if let Ok(path) = &result {
+        push_to_destinations(&name, path);
+    }
+
+    result
+}
+
+fn backup_world(
+    verbose: u8,
+    name: &String,
+    tag: &Option<String>,
+    format: Option<BackupFormat>,
+    scope: Option<BackupScope>,
+    keys: &BackupKeys,
+) -> Result<PathBuf, String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
 
     if !server_dir.exists() {
         return Err(format!("server instance does not exist: {}", name));
     }
 
-    let world_dir = server_dir.join(WORLD_DIR_NAME);
-    validate_world_root(&world_dir, "world")?;
+    // every dimension goes into one backup, each under its own folder name
+    let world_dirs = world_dirs(&server, &server_dir)?;
+    validate_world_root(&server_dir.join(&world_dirs[0]), "world")?;
+    let mut entries: Vec<String> = world_dirs
+        .into_iter()
+        .filter(|dir| server_dir.join(dir).is_dir())
+        .collect();
+    if scope.unwrap_or(server.backup.scope) == BackupScope::Instance {
+        entries.extend(
+            INSTANCE_FILES
+                .iter()
+                .filter(|file| server_dir.join(file).exists())
+                .map(|file| file.to_string()),
+        );
+    }
 
     let backups_dir = server_dir.join(BACKUPS_DIR_NAME);
     fs::create_dir_all(&backups_dir)
         .map_err(|e| format!("failed to create backups directory: {}", e))?;
 
-    let backup_name = build_backup_name(&tag);
-    let backup_path = unique_backup_path(&backups_dir, &backup_name);
-    fs::create_dir_all(&backup_path)
-        .map_err(|e| format!("failed to create backup directory: {}", e))?;
-
-    let mut world_contents = Vec::new();
-    for entry in world_dir
-        .read_dir()
-        .map_err(|e| format!("failed to read world directory: {}", e))?
-    {
-        let entry = entry.map_err(|e| format!("failed to read world entry: {}", e))?;
-        world_contents.push(entry.path());
-    }
+    let format = format.unwrap_or(server.backup.format);
+    let backup_name = build_backup_name(tag);
+    let backup_path = unique_backup_path(&backups_dir, &backup_name, format);
 
-    let options = CopyOptions {
-        overwrite: true,
-        skip_exist: false,
-        buffer_size: 64_000,
-        copy_inside: true,
-        content_only: false,
-        depth: 0,
+    // set up before anything else, so asking for a passphrase never holds the server's saving off
+    let (archive_encryptor, manifest_encryptor) = match &server.backup.encryption {
+        Some(_) if !format.can_encrypt() => return Err(ENCRYPTED_FORMATS_ONLY.to_string()),
+        Some(encryption) => (
+            Some(keys.encryptor(encryption)?),
+            Some(keys.encryptor(encryption)?),
+        ),
+        None => (None, None),
     };
 
-    copy_items(&world_contents, &backup_path, &options)
-        .map_err(|e| format!("failed to copy world contents: {}", e))?;
+    let write = || -> Result<(), String> {
+        match format {
+            BackupFormat::Dir => {
+                // copy under a temporary name, so a failed copy never looks like a finished backup
+                let mut partial_path = backup_path.clone().into_os_string();
+                partial_path.push(".partial");
+                let partial_path = PathBuf::from(partial_path);
+                let _ = fs::remove_dir_all(&partial_path);
+                if let Err(e) = copy_entries(&server_dir, &entries, &partial_path) {
+                    let _ = fs::remove_dir_all(&partial_path);
+                    return Err(e);
+                }
+                fs::rename(&partial_path, &backup_path)
+                    .map_err(|e| format!("failed to finish backup: {}", e))?;
+            }
+            BackupFormat::Incremental => {
+                let stats =
+                    write_snapshot(&server_dir, &entries, &backup_path, server.backup.sectors)?;
+                println!(
+                    "[slapaman] stored {} files in {} chunks, {} new ({})",
+                    stats.files,
+                    stats.chunks,
+                    stats.new_chunks,
+                    format_bytes(stats.new_bytes)
+                );
+            }
+            _ => {
+                // write under a temporary name, so a failed backup never looks like a finished one
+                let partial_path = backup_path.with_extension(format!(
+                    "{}.partial",
+                    format.extension().unwrap_or_default()
+                ));
+                if let Err(e) = write_archive(
+                    &server_dir,
+                    &entries,
+                    &partial_path,
+                    format,
+                    archive_encryptor,
+                ) {
+                    let _ = fs::remove_file(&partial_path);
+                    return Err(e);
+                }
+                fs::rename(&partial_path, &backup_path)
+                    .map_err(|e| format!("failed to finish backup: {}", e))?;
+            }
+        }
+        // hashed from the instance while it still matches what was just backed up
+        write_manifest(
+            &server,
+            &server_dir,
+            &entries,
+            &backup_path,
+            manifest_encryptor,
+        )
+    };
+    // a running server keeps writing region files, so hold its saving off while copying; that
+    // includes one started outside slapaman, which is reached over RCON
+    if server_running(&server, &server_dir)? {
+        with_saving_paused(name, &server_dir, server.backup.announce, write)?;
+    } else {
+        write()?;
+    }
 
     if verbose > 0 {
         println!(
@@ -70,19 +448,359 @@ pub fn create_world_backup(
     Ok(backup_path)
 }
 
-pub fn restore_world_backup(verbose: u8, name: String, backup: &PathBuf) -> Result<(), String> {
+// stop the server from writing to the world while `f` copies it: turn autosave off, flush
+// everything to disk, and turn autosave back on afterwards whatever happened
+fn with_saving_paused(
+    name: &String,
+    server_dir: &Path,
+    announce: bool,
+    f: impl FnOnce() -> Result<(), String>,
+) -> Result<(), String> {
+    if announce {
+        say(server_dir, "Backing up the world...");
+    }
+
+    let result = console(server_dir, "save-off")
+        .and_then(|_| flush_world(server_dir))
+        .and_then(|_| f());
+
+    if let Err(e) = console(server_dir, "save-on") {
+        println!(
+            "[slapaman] warning: failed to turn saving back on for {} ({}); send `save-on` to its console",
+            name, e
+        );
+    }
+    if announce {
+        say(
+            server_dir,
+            match result {
+                Ok(_) => "World backup complete",
+                Err(_) => "World backup failed",
+            },
+        );
+    }
+
+    result
+}
+
+// `save-all flush`, then wait for the server to confirm everything is on disk
+fn flush_world(server_dir: &Path) -> Result<(), String> {
+    // follow the log from before asking, so the confirmation can't slip past
+    let mut tail = CapturedTail::with_backlog(server_dir, 0).map(|(tail, _)| tail);
+    let started = Instant::now();
+    // RCON answers with the server's own output once the save is done, which takes a while on a
+    // big world; without a reply in time, the log may still have it
+    match send_to_running_server(server_dir, "save-all flush", SAVE_TIMEOUT) {
+        Ok(Some(reply)) if reply.contains(SAVED_MESSAGE) => return Ok(()),
+        Ok(_) => {}
+        Err(e) => println!(
+            "[slapaman] no reply to save-all flush ({}), watching the log for it",
+            e
+        ),
+    }
+
+    let tail = tail
+        .as_mut()
+        .map_err(|e| format!("can't confirm the world was saved: {}", e))?;
+    let mut parser = EventParser::new();
+    while started.elapsed() < SAVE_TIMEOUT {
+        if tail
+            .poll()?
+            .iter()
+            .any(|line| parser.parse(line) == Some(ServerEvent::Saved))
+        {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+
+    Err(format!(
+        "the server didn't confirm saving the world within {}s",
+        SAVE_TIMEOUT.as_secs()
+    ))
+}
+
+fn say(server_dir: &Path, message: &str) {
+    if let Err(e) = console(server_dir, &format!("say {}", message)) {
+        println!("[slapaman] failed to announce the backup in-game: {}", e);
+    }
+}
+
+fn console(server_dir: &Path, command: &str) -> Result<(), String> {
+    send_to_running_server(server_dir, command, RCON_TIMEOUT).map(|_| ())
+}
+
+// back up what an instance has now before it gets replaced, so a mistaken restore or world-set
+// can be undone; instances without a world yet have nothing to lose
+pub fn take_safety_snapshot(
+    verbose: u8,
+    name: &String,
+    scope: BackupScope,
+    keys: &BackupKeys,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+    if !server_dir
+        .join(&world_dirs(&server, &server_dir)?[0])
+        .join(LEVEL_DAT)
+        .exists()
+    {
+        return Ok(());
+    }
+
+    let path = backup_world(
+        verbose,
+        name,
+        &Some(SAFETY_SNAPSHOT_TAG.to_string()),
+        None,
+        Some(scope),
+        keys,
+    )
+    .map_err(|e| format!("failed to back up the current world first: {}", e))?;
+    let backup_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    println!(
+        "[slapaman] backed up the current world to {}; undo with `slapaman world-restore {} {}`",
+        path.display(),
+        name,
+        backup_name
+    );
+    Ok(())
+}
+
+// how `world-restore` goes about it
+#[derive(Default)]
+pub struct RestoreOptions {
+    // restore a backup that fails verification
+    pub force: bool,
+    // download the backup from this destination first
+    pub from: Option<String>,
+    // back up what the instance has now before it's replaced
+    pub snapshot: bool,
+    // decrypt with the key in this file rather than the instance's configured one or a prompt
+    pub key_file: Option<PathBuf>,
+    // restore just these parts of the world, leaving the rest as it is
+    pub only: Vec<RestoreFilter>,
+}
+
+// restore an instance from a backup, which must pass verification unless forced
+pub fn restore_world_backup(
+    verbose: u8,
+    name: String,
+    backup: &Path,
+    options: &RestoreOptions,
+) -> Result<(), String> {
     let server = Server::load_by_name(&name)?;
     let server_dir = server.path.join(&name);
+    let keys = BackupKeys::new(&server, options.key_file.as_deref());
 
     if !server_dir.exists() {
         return Err(format!("server instance does not exist: {}", name));
     }
+    ensure_stopped(&server, &server_dir)?;
 
     let backups_dir = server_dir.join(BACKUPS_DIR_NAME);
-    let resolved_backup = resolve_backup_path(&backups_dir, backup)?;
-    validate_world_root(&resolved_backup, "backup")?;
+    let resolved_backup = match &options.from {
+        Some(destination) => pull_backup(&server, destination, &backup.to_string_lossy())?,
+        None => resolve_backup(&backups_dir, backup)?,
+    };
+
+    if !keys.available(&resolved_backup) {
+        return Err(format!(
+            "{} is encrypted; give its key with --key-file, ${} or ${}",
+            resolved_backup.display(),
+            KEY_FILE_ENV,
+            PASSPHRASE_ENV
+        ));
+    }
+    let verification = verify_backup(&resolved_backup, &keys);
+    match (verification.passed(), options.force) {
+        (true, _) => (),
+        (false, true) => println!(
+            "[slapaman] warning: restoring a backup that failed verification: {}",
+            verification.describe()
+        ),
+        (false, false) => {
+            return Err(format!(
+                "backup failed verification ({}); see `slapaman world-backup-verify {} {}`, or restore anyway with --force",
+                verification.describe(),
+                name,
+                backup.display()
+            ))
+        }
+    }
+
+    // every kind of backup is unpacked next to the instance's files first, so nothing gets
+    // touched until the whole backup is known to be readable
+    let staging_dir = server_dir.join(RESTORE_STAGING_DIR_NAME);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .map_err(|e| format!("failed to clear old restore staging directory: {}", e))?;
+    }
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("failed to create restore staging directory: {}", e))?;
+
+    if !options.only.is_empty() {
+        let result = restore_selection(
+            verbose,
+            &server,
+            &server_dir,
+            &resolved_backup,
+            &staging_dir,
+            options,
+            &keys,
+        );
+        let _ = fs::remove_dir_all(&staging_dir);
+        return result;
+    }
 
-    set_world(verbose, name, &resolved_backup)
+    let unpacked = if resolved_backup.is_dir() {
+        copy_contents(&resolved_backup, &staging_dir)
+    } else if is_snapshot(&resolved_backup) {
+        restore_snapshot(&resolved_backup, &staging_dir)
+    } else {
+        extract_archive(&resolved_backup, &staging_dir, &keys)
+    };
+    let result = unpacked.and_then(|_| {
+        // backups from before dimensions were backed up together hold a single world's contents
+        let legacy = staging_dir.join(LEVEL_DAT).exists();
+        if options.snapshot {
+            let scope = match legacy || staged_world_only(&server, &server_dir, &staging_dir)? {
+                true => BackupScope::World,
+                false => BackupScope::Instance,
+            };
+            take_safety_snapshot(verbose, &name, scope, &keys)?;
+        }
+        if legacy {
+            install_world(&server, &server_dir, &staging_dir)?;
+            println!("[slapaman] restored world for server instance: {}", name);
+            return Ok(());
+        }
+        install_backup(&server, &server_dir, &staging_dir)
+    });
+    let _ = fs::remove_dir_all(&staging_dir);
+    result
+}
+
+// `world-restore --only`: copy just the files the filters pick out of a backup into the instance,
+// replacing its copies of them; a dimension is restored whole, so region files it has that the
+// backup doesn't are taken out too
+fn restore_selection(
+    verbose: u8,
+    server: &Server,
+    server_dir: &Path,
+    backup: &Path,
+    staging_dir: &Path,
+    options: &RestoreOptions,
+    keys: &BackupKeys,
+) -> Result<(), String> {
+    let world_dirs = world_dirs(server, server_dir)?;
+    let mut selection = Selection::new(&options.only, server_dir, &world_dirs)?;
+    let mut files = extract_selection(backup, keys, &mut selection, &world_dirs, staging_dir)?;
+    files.sort();
+
+    let mut removed = Vec::new();
+    for (filter, count) in selection.filters.iter().zip(&selection.counts) {
+        match (filter, count) {
+            (_, 0) => println!(
+                "[slapaman] warning: nothing in the backup matches {}",
+                filter.describe()
+            ),
+            (RestoreFilter::Dimension(dimension), _) => {
+                let dir = dimension_dir(&world_dirs, *dimension);
+                removed.extend(
+                    REGION_FOLDERS
+                        .iter()
+                        .map(|folder| format!("{}/{}", dir, folder)),
+                );
+            }
+            _ => (),
+        }
+    }
+    if files.is_empty() {
+        return Err(format!(
+            "nothing in {} matches the --only filters",
+            backup.display()
+        ));
+    }
+
+    if options.snapshot {
+        take_safety_snapshot(verbose, &server.name, BackupScope::World, keys)?;
+    }
+    swap_in(server_dir, staging_dir, &files, &removed)?;
+
+    if verbose > 0 {
+        for file in &files {
+            println!("[slapaman] restored {}", file);
+        }
+    }
+    let described: Vec<String> = selection
+        .filters
+        .iter()
+        .zip(&selection.counts)
+        .filter(|(_, count)| **count > 0)
+        .map(|(filter, _)| filter.describe())
+        .collect();
+    println!(
+        "[slapaman] restored {} file(s) ({}) for server instance: {}",
+        files.len(),
+        described.join("; "),
+        server.name
+    );
+    Ok(())
+}
+
+// whether everything unpacked into `staging_dir` is a world folder, i.e. the restore won't touch
+// the instance's other files
+fn staged_world_only(
+    server: &Server,
+    server_dir: &Path,
+    staging_dir: &Path,
+) -> Result<bool, String> {
+    let world_dirs = world_dirs(server, server_dir)?;
+    Ok(fs::read_dir(staging_dir)
+        .map_err(|e| format!("failed to read restored backup: {}", e))?
+        .filter_map(|e| e.ok())
+        .all(|e| world_dirs.contains(&e.file_name().to_string_lossy().to_string())))
+}
+
+// move everything unpacked into `staging_dir` into the instance, replacing what's there; world
+// folders the backup doesn't have are removed too, so the dimensions always match each other
+fn install_backup(server: &Server, server_dir: &Path, staging_dir: &Path) -> Result<(), String> {
+    let mut entries: Vec<String> = fs::read_dir(staging_dir)
+        .map_err(|e| format!("failed to read restored backup: {}", e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    entries.sort();
+    let world_dirs = world_dirs(server, server_dir)?;
+    if !entries.contains(&world_dirs[0]) {
+        return Err(format!("backup has no {} world", world_dirs[0]));
+    }
+    validate_world_root(&staging_dir.join(&world_dirs[0]), "backup")?;
+
+    swap_in(server_dir, staging_dir, &entries, &world_dirs)?;
+
+    println!(
+        "[slapaman] restored {} for server instance: {}",
+        entries.join(", "),
+        server.name
+    );
+    Ok(())
+}
+
+// the most recently written backup in an instance's backups directory, if any
+pub fn latest_backup(server_dir: &Path) -> Option<(PathBuf, SystemTime)> {
+    list_backups(&server_dir.join(BACKUPS_DIR_NAME))
+        .ok()?
+        .into_iter()
+        .filter_map(|backup| {
+            let modified = fs::metadata(&backup.path).ok()?.modified().ok()?;
+            Some((backup.path, modified))
+        })
+        .max_by_key(|(_, modified)| *modified)
 }
 
 fn validate_world_root(path: &Path, label: &str) -> Result<(), String> {
@@ -119,29 +837,58 @@ fn build_backup_name(tag: &Option<String>) -> String {
     name
 }
 
-fn unique_backup_path(backups_dir: &Path, base_name: &str) -> PathBuf {
-    let mut candidate = backups_dir.join(base_name);
+// a path for a new backup named `base_name`, which no backup in any format has yet, so a backup's
+// name alone is enough to find it again
+fn unique_backup_path(backups_dir: &Path, base_name: &str, format: BackupFormat) -> PathBuf {
+    let taken = |name: &str| {
+        backups_dir.join(name).exists()
+            || BackupFormat::FILE_FORMATS.iter().any(|format| {
+                let extension = format.extension().unwrap_or_default();
+                backups_dir.join(format!("{}.{}", name, extension)).exists()
+            })
+    };
+    let mut name = base_name.to_string();
     let mut index = 1;
 
-    while candidate.exists() {
-        candidate = backups_dir.join(format!("{}-{:02}", base_name, index));
+    while taken(&name) {
+        name = format!("{}.{}", base_name, index);
         index += 1;
     }
 
-    candidate
+    match format.extension() {
+        Some(extension) => backups_dir.join(format!("{}.{}", name, extension)),
+        None => backups_dir.join(name),
+    }
 }
 
-fn resolve_backup_path(backups_dir: &Path, backup: &PathBuf) -> Result<PathBuf, String> {
-    if backup.as_path().exists() {
-        return Ok(backup.clone());
-    }
+fn copy_entries(root: &Path, entries: &[String], backup_path: &Path) -> Result<(), String> {
+    fs::create_dir_all(backup_path)
+        .map_err(|e| format!("failed to create backup directory: {}", e))?;
 
-    let candidate = backups_dir.join(backup);
-    if candidate.exists() {
-        return Ok(candidate);
-    }
+    let contents: Vec<PathBuf> = entries.iter().map(|entry| root.join(entry)).collect();
+
+    let options = CopyOptions {
+        overwrite: true,
+        skip_exist: false,
+        buffer_size: 64_000,
+        copy_inside: true,
+        content_only: false,
+        depth: 0,
+    };
+
+    copy_items(&contents, backup_path, &options)
+        .map_err(|e| format!("failed to copy instance contents: {}", e))?;
+
+    Ok(())
+}
 
-    Err(format!("backup not found: {}", backup.display()))
+fn copy_contents(source: &Path, destination: &Path) -> Result<(), String> {
+    let entries: Vec<String> = fs::read_dir(source)
+        .map_err(|e| format!("failed to read {}: {}", source.display(), e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().to_string())
+        .collect();
+    copy_entries(source, &entries, destination)
 }
 
 fn sanitize_tag(tag: &str) -> String {
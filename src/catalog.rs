@@ -0,0 +1,589 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use chrono::NaiveDateTime;
+use flate2::read::GzDecoder;
+use regex::Regex;
+use zip::ZipArchive;
+
+use crate::archive::{backup_stem, entry_name, walk, BackupFormat};
+use crate::backup::BACKUPS_DIR_NAME;
+use crate::crypto::{is_encrypted, BackupKeys, KEY_FILE_ENV, PASSPHRASE_ENV};
+use crate::integrity::{load_manifest, manifest_path, verify_backup, Verification};
+use crate::nbt::{read_world_version, WorldVersion};
+use crate::resources::{dir_size, format_bytes};
+use crate::server::Server;
+use crate::snapshot::{
+    check_snapshot, chunks_dir_for, collect_garbage, Manifest, SnapshotFileReader,
+};
+
+const LEVEL_DAT: &str = "level.dat";
+
+// one backup in an instance's backups directory
+#[derive(serde_derive::Serialize, Clone, Debug)]
+pub struct BackupInfo {
+    // position in `world-backups`, oldest first
+    pub index: usize,
+    // the backup's name without its extension
+    pub id: String,
+    pub path: PathBuf,
+    // when the backup was taken, in UTC
+    #[serde(serialize_with = "serialize_taken")]
+    pub taken: NaiveDateTime,
+    pub tag: Option<String>,
+    // for incremental backups, the size of everything they restore
+    pub size: u64,
+    pub format: BackupFormat,
+    pub encrypted: bool,
+}
+
+pub fn serialize_taken<S: serde::Serializer>(
+    taken: &NaiveDateTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&taken.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+// what `world-backups` and `world-backup-show` report on top of `BackupInfo`
+#[derive(serde_derive::Serialize)]
+struct BackupReport {
+    #[serde(flatten)]
+    info: BackupInfo,
+    world_version: Option<WorldVersion>,
+    // None when verification was skipped
+    verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    verification: Option<Verification>,
+}
+
+// backups named like `world-backup` names them (world-YYYYMMDD-HHMMSS[-tag], plus an extension for
+// archives), oldest first; anything else in the backups directory is left alone
+pub fn list_backups(backups_dir: &Path) -> Result<Vec<BackupInfo>, String> {
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in
+        fs::read_dir(backups_dir).map_err(|e| format!("failed to read backups directory: {}", e))?
+    {
+        let entry = entry.map_err(|e| format!("failed to read backups directory: {}", e))?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        let id = backup_stem(&name);
+        let ((taken, tag), format) = match (parse_backup_id(id), BackupFormat::of(&path)) {
+            (Some(parsed), Some(format)) => (parsed, format),
+            _ => continue,
+        };
+        let size = match format {
+            BackupFormat::Incremental => Manifest::load(&path)
+                .map(|manifest| manifest.entries.iter().map(|e| e.size).sum())
+                .unwrap_or(0),
+            _ => dir_size(&path),
+        };
+        backups.push(BackupInfo {
+            index: 0,
+            id: id.to_string(),
+            tag,
+            encrypted: format.can_encrypt() && is_encrypted(&path),
+            path,
+            taken,
+            size,
+            format,
+        });
+    }
+
+    backups.sort_by(|a, b| a.taken.cmp(&b.taken).then(a.id.cmp(&b.id)));
+    for (i, backup) in backups.iter_mut().enumerate() {
+        backup.index = i + 1;
+    }
+    Ok(backups)
+}
+
+// `world-<timestamp>[-<tag>][.<n>]`; tags never contain a dot, so the suffix that keeps two backups
+// taken in the same second apart can't be mistaken for one
+static BACKUP_ID_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^world-(\d{8}-\d{6})(?:-([^.]+))?(?:\.\d+)?$").unwrap());
+
+// when a backup named like `world-backup` names them was taken, and its tag if it has one
+pub fn parse_backup_id(id: &str) -> Option<(NaiveDateTime, Option<String>)> {
+    let caps = BACKUP_ID_RE.captures(id)?;
+    let taken = NaiveDateTime::parse_from_str(&caps[1], "%Y%m%d-%H%M%S").ok()?;
+    Some((taken, caps.get(2).map(|tag| tag.as_str().to_string())))
+}
+
+// a backup can be given as a path, by name within the backups directory (with or without its
+// extension), by its index in `world-backups`, or as "latest"
+pub fn resolve_backup(backups_dir: &Path, backup: &Path) -> Result<PathBuf, String> {
+    if backup.exists() {
+        return Ok(backup.to_path_buf());
+    }
+
+    let candidate = backups_dir.join(backup);
+    if candidate.exists() {
+        return Ok(candidate);
+    }
+
+    for format in BackupFormat::FILE_FORMATS {
+        let mut name = backup.as_os_str().to_os_string();
+        name.push(format!(".{}", format.extension().unwrap_or_default()));
+        let candidate = backups_dir.join(name);
+        if candidate.exists() {
+            return Ok(candidate);
+        }
+    }
+
+    let selector = backup.to_string_lossy();
+    if selector == "latest" {
+        return list_backups(backups_dir)?
+            .pop()
+            .map(|backup| backup.path)
+            .ok_or_else(|| "there are no backups yet".to_string());
+    }
+    if let Ok(index) = selector.parse::<usize>() {
+        return list_backups(backups_dir)?
+            .into_iter()
+            .find(|backup| backup.index == index)
+            .map(|backup| backup.path)
+            .ok_or_else(|| format!("no backup with index {}", index));
+    }
+
+    Err(format!("backup not found: {}", backup.display()))
+}
+
+// the game version of the world in a backup, read from its level.dat
+pub fn backup_world_version(path: &Path, keys: &BackupKeys) -> Result<WorldVersion, String> {
+    match BackupFormat::of(path) {
+        Some(BackupFormat::Dir) => {
+            let entries: Vec<String> = fs::read_dir(path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().join(LEVEL_DAT).exists())
+                .map(|e| format!("{}/{}", e.file_name().to_string_lossy(), LEVEL_DAT))
+                .chain(Some(LEVEL_DAT.to_string()).filter(|_| path.join(LEVEL_DAT).exists()))
+                .collect();
+            let level_dat = pick_level_dat(entries).ok_or("backup has no level.dat")?;
+            let file = File::open(path.join(level_dat))
+                .map_err(|e| format!("failed to open level.dat: {}", e))?;
+            read_world_version(BufReader::new(file))
+        }
+        Some(BackupFormat::TarGz) | Some(BackupFormat::TarZst) => {
+            let mut archive = open_tar(path, keys)?;
+            // the overworld is always written first
+            for entry in archive
+                .entries()
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            {
+                let entry =
+                    entry.map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                let name = entry
+                    .path()
+                    .map(|p| p.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if is_level_dat(&name) {
+                    return read_world_version(entry);
+                }
+            }
+            Err("backup has no level.dat".to_string())
+        }
+        Some(BackupFormat::Zip) => {
+            let mut zip = open_zip(path)?;
+            let level_dat = pick_level_dat(zip.file_names().map(|n| n.to_string()).collect())
+                .ok_or("backup has no level.dat")?;
+            let entry = zip
+                .by_name(&level_dat)
+                .map_err(|e| format!("failed to read level.dat: {}", e))?;
+            read_world_version(entry)
+        }
+        Some(BackupFormat::Incremental) => {
+            let manifest = Manifest::load(path)?;
+            let level_dat =
+                pick_level_dat(manifest.entries.iter().map(|e| e.path.clone()).collect())
+                    .ok_or("backup has no level.dat")?;
+            let entry = manifest
+                .entries
+                .iter()
+                .find(|e| e.path == level_dat)
+                .ok_or("backup has no level.dat")?;
+            read_world_version(SnapshotFileReader::new(&chunks_dir_for(path), entry))
+        }
+        None => Err(format!("not a backup: {}", path.display())),
+    }
+}
+
+// check that a backup can be read back in full
+pub fn check_backup(path: &Path, keys: &BackupKeys) -> Result<(), String> {
+    if BackupFormat::of(path) == Some(BackupFormat::Incremental) {
+        check_snapshot(path)?;
+    }
+
+    let mut found = false;
+    visit_backup_files(path, keys, |name, reader| {
+        found |= is_level_dat(name);
+        io::copy(reader, &mut io::sink())
+            .map(|_| ())
+            .map_err(|e| format!("failed to read {} from the backup: {}", name, e))
+    })?;
+
+    match found {
+        true => Ok(()),
+        false => Err("backup has no level.dat".to_string()),
+    }
+}
+
+// call `f` with the name (relative, with forward slashes) and contents of every regular file in a
+// backup, whatever its format
+pub fn visit_backup_files(
+    path: &Path,
+    keys: &BackupKeys,
+    mut f: impl FnMut(&str, &mut dyn Read) -> Result<(), String>,
+) -> Result<(), String> {
+    match BackupFormat::of(path) {
+        Some(BackupFormat::Dir) => {
+            for (file, relative) in walk(path)? {
+                if file.is_symlink() || !file.is_file() {
+                    continue;
+                }
+                let mut reader = BufReader::new(
+                    File::open(&file)
+                        .map_err(|e| format!("failed to open {}: {}", file.display(), e))?,
+                );
+                f(&entry_name(&relative), &mut reader)?;
+            }
+        }
+        Some(BackupFormat::TarGz) | Some(BackupFormat::TarZst) => {
+            let mut archive = open_tar(path, keys)?;
+            for entry in archive
+                .entries()
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            {
+                let mut entry =
+                    entry.map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                if !entry.header().entry_type().is_file() {
+                    continue;
+                }
+                let name = entry
+                    .path()
+                    .map(|p| entry_name(&p))
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                f(name.trim_start_matches("./"), &mut entry)?;
+            }
+            // the compression checksum comes after the last entry
+            io::copy(&mut archive.into_inner(), &mut io::sink())
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        }
+        Some(BackupFormat::Zip) => {
+            let mut zip = open_zip(path)?;
+            for i in 0..zip.len() {
+                // reading an entry to the end checks its CRC
+                let mut entry = zip
+                    .by_index(i)
+                    .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+                if entry.is_dir() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                f(&name, &mut entry)?;
+            }
+        }
+        Some(BackupFormat::Incremental) => {
+            let chunks_dir = chunks_dir_for(path);
+            for entry in Manifest::load(path)?.entries.iter().filter(|e| !e.dir) {
+                f(
+                    &entry.path,
+                    &mut SnapshotFileReader::new(&chunks_dir, entry),
+                )?;
+            }
+        }
+        None => return Err(format!("not a backup: {}", path.display())),
+    }
+
+    Ok(())
+}
+
+// `world-backups`: every backup of an instance, oldest first
+pub fn list_world_backups(
+    name: &String,
+    verify: bool,
+    json: bool,
+    key_file: Option<&Path>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let keys = BackupKeys::new(&server, key_file);
+    let backups = list_backups(&server.path.join(name).join(BACKUPS_DIR_NAME))?;
+    let reports: Vec<BackupReport> = backups
+        .into_iter()
+        .map(|info| report(info, verify, &keys))
+        .collect();
+
+    if json {
+        let json = serde_json::to_string_pretty(&reports)
+            .map_err(|e| format!("failed to serialize backups: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    if reports.is_empty() {
+        println!(
+            "no backups for {} (take one with `slapaman world-backup {}`)",
+            name, name
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{:>3}  {:<40} {:<19} {:<12} {:>10}  {:<11} {:<10} verified",
+        "#", "id", "taken (UTC)", "tag", "size", "format", "world"
+    );
+    for report in &reports {
+        println!(
+            "{:>3}  {:<40} {:<19} {:<12} {:>10}  {:<11} {:<10} {}",
+            report.info.index,
+            report.info.id,
+            report.info.taken.format("%Y-%m-%d %H:%M:%S"),
+            report.info.tag.as_deref().unwrap_or("-"),
+            format_bytes(report.info.size),
+            match report.info.encrypted {
+                true => format!("{}+age", report.info.format),
+                false => report.info.format.to_string(),
+            },
+            report
+                .world_version
+                .as_ref()
+                .map(|v| v.describe())
+                .unwrap_or_else(|| "?".to_string()),
+            match report.info.encrypted && report.verification.is_none() && verify {
+                true => "locked",
+                false => describe_verified(report.verification.as_ref()),
+            },
+        );
+    }
+
+    Ok(())
+}
+
+// `world-backup-show`: everything known about one backup
+pub fn show_world_backup(
+    name: &String,
+    backup: &Path,
+    json: bool,
+    key_file: Option<&Path>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let keys = BackupKeys::new(&server, key_file);
+    let info = find_backup(name, backup)?;
+    let contents = backup_contents(&info.path, &keys).unwrap_or_default();
+    let report = report(info, true, &keys);
+
+    if json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("failed to serialize backup: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!("backup {} of {}", report.info.index, name);
+    println!("id: {}", report.info.id);
+    println!("path: {}", report.info.path.display());
+    println!(
+        "taken: {} UTC",
+        report.info.taken.format("%Y-%m-%d %H:%M:%S")
+    );
+    println!("tag: {}", report.info.tag.as_deref().unwrap_or("-"));
+    match report.info.encrypted {
+        true => println!("format: {} (encrypted)", report.info.format),
+        false => println!("format: {}", report.info.format),
+    }
+    println!("size: {}", format_bytes(report.info.size));
+    if let Some(version) = &report.world_version {
+        println!("world version: {}", version.describe());
+        if let Some(data_version) = version.data_version {
+            println!("data version: {}", data_version);
+        }
+    }
+    if !contents.is_empty() {
+        println!("contents: {}", contents.join(", "));
+    }
+    if let Ok(Some(manifest)) = load_manifest(&report.info.path, &keys) {
+        println!(
+            "taken from: {} {} (slapaman {})",
+            manifest.flavor, manifest.server_version, manifest.slapaman_version
+        );
+    }
+    if report.info.encrypted && report.verification.is_none() {
+        println!(
+            "verified: - (encrypted; give its key with --key-file, ${} or ${})",
+            KEY_FILE_ENV, PASSPHRASE_ENV
+        );
+    }
+    if let Some(verification) = &report.verification {
+        println!(
+            "verified: {} ({})",
+            describe_verified(report.verification.as_ref()),
+            verification.describe()
+        );
+        for (label, paths) in [
+            ("missing", &verification.missing),
+            ("corrupted", &verification.corrupted),
+            ("extra", &verification.extra),
+        ] {
+            for path in paths {
+                println!("  {} {}", label, path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// `world-backup-delete`
+pub fn delete_world_backup(name: &String, backup: &Path) -> Result<(), String> {
+    let info = find_backup(name, backup)?;
+    let removed = match info.path.is_dir() {
+        true => fs::remove_dir_all(&info.path),
+        false => fs::remove_file(&info.path),
+    };
+    removed.map_err(|e| format!("failed to remove {}: {}", info.path.display(), e))?;
+    let _ = fs::remove_file(manifest_path(&info.path));
+    println!("[slapaman] deleted backup {} of {}", info.id, name);
+
+    if info.format == BackupFormat::Incremental {
+        let backups_dir = info.path.parent().unwrap_or(Path::new("."));
+        let (removed, freed) = collect_garbage(backups_dir)?;
+        if removed > 0 {
+            println!(
+                "[slapaman] removed {} unreferenced chunks ({})",
+                removed,
+                format_bytes(freed)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn find_backup(name: &String, backup: &Path) -> Result<BackupInfo, String> {
+    let server = Server::load_by_name(name)?;
+    let backups_dir = server.path.join(name).join(BACKUPS_DIR_NAME);
+    let path = resolve_backup(&backups_dir, backup)?;
+    list_backups(&backups_dir)?
+        .into_iter()
+        .find(|info| info.path == path)
+        .ok_or_else(|| format!("not one of {}'s backups: {}", name, path.display()))
+}
+
+// the world version of an encrypted backup is only read when it's being decrypted for
+// verification anyway, and neither happens when there's no key to decrypt it with
+fn report(info: BackupInfo, verify: bool, keys: &BackupKeys) -> BackupReport {
+    let readable = !info.encrypted || keys.available(&info.path);
+    let world_version = match readable && (!info.encrypted || verify) {
+        true => backup_world_version(&info.path, keys).ok(),
+        false => None,
+    };
+    let verification = match verify && readable {
+        true => Some(verify_backup(&info.path, keys)),
+        false => None,
+    };
+    BackupReport {
+        world_version,
+        verified: verification.as_ref().map(|v| v.passed()),
+        verification,
+        info,
+    }
+}
+
+fn describe_verified(verification: Option<&Verification>) -> &'static str {
+    match verification {
+        Some(v) if !v.passed() => "FAILED",
+        Some(v) if v.has_manifest => "ok",
+        Some(_) => "readable",
+        None => "-",
+    }
+}
+
+// the top-level files and folders a backup restores
+fn backup_contents(path: &Path, keys: &BackupKeys) -> Result<Vec<String>, String> {
+    let names: Vec<String> = match BackupFormat::of(path) {
+        Some(BackupFormat::Dir) => fs::read_dir(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect(),
+        Some(BackupFormat::TarGz) | Some(BackupFormat::TarZst) => {
+            let mut archive = open_tar(path, keys)?;
+            let entries = archive
+                .entries()
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+            entries
+                .filter_map(|e| e.ok())
+                .filter_map(|e| e.path().ok().map(|p| p.to_string_lossy().to_string()))
+                .collect()
+        }
+        Some(BackupFormat::Zip) => open_zip(path)?
+            .file_names()
+            .map(|n| n.to_string())
+            .collect(),
+        Some(BackupFormat::Incremental) => Manifest::load(path)?
+            .entries
+            .into_iter()
+            .map(|e| e.path)
+            .collect(),
+        None => return Err(format!("not a backup: {}", path.display())),
+    };
+
+    let mut top_level: Vec<String> = names
+        .iter()
+        .filter_map(|name| name.trim_start_matches("./").split('/').next())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect();
+    top_level.sort();
+    top_level.dedup();
+    Ok(top_level)
+}
+
+// a level.dat at the top of a backup, or in one of its world folders
+fn is_level_dat(name: &str) -> bool {
+    let name = name.trim_start_matches("./");
+    name == LEVEL_DAT
+        || name
+            .strip_suffix(&format!("/{}", LEVEL_DAT))
+            .is_some_and(|dir| !dir.contains('/'))
+}
+
+// the overworld's level.dat among a backup's files, which is the one not in a dimension folder
+fn pick_level_dat(mut names: Vec<String>) -> Option<String> {
+    names.retain(|name| is_level_dat(name));
+    names.sort_by_key(|name| {
+        (
+            name.contains('/'),
+            name.contains("_nether/") || name.contains("_the_end/"),
+            name.clone(),
+        )
+    });
+    names.into_iter().next()
+}
+
+fn open_tar(path: &Path, keys: &BackupKeys) -> Result<tar::Archive<Box<dyn Read>>, String> {
+    let reader = keys.open(path)?;
+    let decoder: Box<dyn Read> = match BackupFormat::of(path) {
+        Some(BackupFormat::TarGz) => Box::new(GzDecoder::new(reader)),
+        Some(BackupFormat::TarZst) => Box::new(
+            zstd::Decoder::new(reader)
+                .map_err(|e| format!("failed to start zstd stream: {}", e))?,
+        ),
+        _ => return Err(format!("not a tarball: {}", path.display())),
+    };
+    Ok(tar::Archive::new(decoder))
+}
+
+fn open_zip(path: &Path) -> Result<ZipArchive<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    ZipArchive::new(BufReader::new(file))
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
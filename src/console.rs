@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::ChildStdin;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::net::rcon::RconClient;
+use crate::process::{pid_alive, terminate};
+use crate::properties::rcon_address;
+use crate::run::{mark_stop_reason, running_pid};
+use crate::server::Server;
+
+// a named pipe in the instance directory; `slapaman run` feeds whatever is written to it into
+// the server's console
+pub const CONSOLE_FIFO_NAME: &str = "slapaman.console";
+pub const RCON_TIMEOUT: Duration = Duration::from_secs(5);
+// after the server ignores `stop` for this long, it gets a signal instead
+const KILL_GRACE: Duration = Duration::from_secs(15);
+
+pub fn console_fifo(server_dir: &Path) -> PathBuf {
+    server_dir.join(CONSOLE_FIFO_NAME)
+}
+
+// pass console commands from our stdin and the console pipe to the server's stdin
+pub fn attach_console(server_dir: &Path, stdin: ChildStdin) {
+    let (tx, rx) = mpsc::channel::<String>();
+
+    thread::spawn(move || {
+        let mut stdin = stdin;
+        for command in rx {
+            if writeln!(stdin, "{}", command)
+                .and_then(|_| stdin.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    forward_reader(std::io::stdin(), tx.clone());
+
+    if let Err(e) = listen_on_fifo(server_dir, tx) {
+        println!(
+            "[slapaman] console commands from other slapaman processes are unavailable: {}",
+            e
+        );
+    }
+}
+
+// remove the console pipe once the server has exited
+pub fn detach_console(server_dir: &Path) {
+    let _ = std::fs::remove_file(console_fifo(server_dir));
+}
+
+// send one command to a running server's console, over the console pipe or RCON
+pub fn send_console_command(server_dir: &Path, command: &str) -> Result<(), String> {
+    send_console_command_with_reply(server_dir, command).map(|_| ())
+}
+
+// like `send_console_command`, but also returns what the server answered when the command went
+// over RCON (the console pipe has no way to answer; its output only shows up in the logs)
+pub fn send_console_command_with_reply(
+    server_dir: &Path,
+    command: &str,
+) -> Result<Option<String>, String> {
+    if running_pid(server_dir).is_none() {
+        return Err("server is not running".to_string());
+    }
+    send_to_running_server(server_dir, command, RCON_TIMEOUT)
+}
+
+// send a command to a server that's known to be running, whether slapaman started it or not,
+// waiting up to `timeout` for an answer when it goes over RCON
+pub fn send_to_running_server(
+    server_dir: &Path,
+    command: &str,
+    timeout: Duration,
+) -> Result<Option<String>, String> {
+    let command = command.trim();
+
+    #[cfg(unix)]
+    {
+        use std::fs::OpenOptions;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // the pipe is only read while `slapaman run` is up; a server started some other way can
+        // still be reached over RCON
+        let fifo = console_fifo(server_dir);
+        if fifo.exists() && running_pid(server_dir).is_some() {
+            // without O_NONBLOCK, opening a pipe nobody reads from would hang
+            let mut pipe = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&fifo)
+                .map_err(|e| format!("failed to open console: {}", e))?;
+            return pipe
+                .write_all(format!("{}\n", command).as_bytes())
+                .map(|_| None)
+                .map_err(|e| format!("failed to write to console: {}", e));
+        }
+    }
+
+    match rcon_address(server_dir)? {
+        Some((host, port, password)) => {
+            let mut client = RconClient::connect(&host, port, &password, timeout)?;
+            client.command(command).map(Some)
+        }
+        None => Err(
+            "server has no console slapaman can reach (start it with `slapaman run` or enable RCON)"
+                .to_string(),
+        ),
+    }
+}
+
+// send `stop` and wait for the server to exit, killing it if it takes longer than `timeout`
+pub fn stop_server(name: &String, timeout: Duration) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+    let pid = match running_pid(&server_dir) {
+        Some(pid) => pid,
+        None => {
+            println!("[slapaman] server is not running: {}", name);
+            return Ok(());
+        }
+    };
+
+    match send_console_command(&server_dir, "stop") {
+        Ok(_) => {
+            let started = Instant::now();
+            while started.elapsed() < timeout {
+                if !pid_alive(pid) {
+                    return Ok(());
+                }
+                thread::sleep(Duration::from_millis(250));
+            }
+            println!(
+                "[slapaman] server did not stop within {}s, terminating it",
+                timeout.as_secs()
+            );
+        }
+        Err(e) => println!(
+            "[slapaman] failed to send stop to the console ({}), terminating the server",
+            e
+        ),
+    }
+
+    mark_stop_reason(&server_dir, "terminated because it didn't stop when asked");
+    terminate(pid, KILL_GRACE)
+}
+
+fn forward_reader<R: std::io::Read + Send + 'static>(reader: R, tx: Sender<String>) {
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines() {
+            match line {
+                Ok(line) => {
+                    if tx.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+fn listen_on_fifo(server_dir: &Path, tx: Sender<String>) -> Result<(), String> {
+    use std::ffi::CString;
+    use std::fs::OpenOptions;
+    use std::os::unix::ffi::OsStrExt;
+
+    let fifo = console_fifo(server_dir);
+    let _ = std::fs::remove_file(&fifo);
+    let c_path = CString::new(fifo.as_os_str().as_bytes())
+        .map_err(|_| "console path contains a null byte".to_string())?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+        return Err(format!(
+            "failed to create {}: {}",
+            fifo.display(),
+            std::io::Error::last_os_error()
+        ));
+    }
+
+    // holding the pipe open for writing too means it never reports end-of-file between writers
+    let pipe = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&fifo)
+        .map_err(|e| format!("failed to open {}: {}", fifo.display(), e))?;
+    forward_reader(pipe, tx);
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen_on_fifo(_server_dir: &Path, _tx: Sender<String>) -> Result<(), String> {
+    Err("named pipes are only supported on unix".to_string())
+}
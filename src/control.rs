@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
+use serde_json::Value;
+
+use crate::archive::BackupFormat;
+use crate::backup::BackupScope;
+
+// bumped whenever a request or response changes incompatibly
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const SOCKET_NAME: &str = "slapaman.sock";
+
+// one request per line on the daemon's socket, answered by one response line, e.g.
+// {"version":1,"request":"stop","name":"survival","timeout":90}
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: ControlCommand,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum ControlCommand {
+    Ping,
+    List,
+    Start {
+        name: String,
+        memory: Option<u32>,
+    },
+    Stop {
+        name: String,
+        timeout: u64,
+    },
+    Restart {
+        name: String,
+    },
+    Update {
+        name: String,
+        version: String,
+        flavor: Option<String>,
+    },
+    WorldBackup {
+        name: String,
+        tag: Option<String>,
+        #[serde(default)]
+        format: Option<BackupFormat>,
+        #[serde(default)]
+        scope: Option<BackupScope>,
+    },
+    Console {
+        name: String,
+        command: String,
+    },
+}
+
+// one response line per request; `data` depends on the command
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct Response {
+    pub version: u32,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub data: Value,
+}
+
+impl Response {
+    pub fn ok(data: Value) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: true,
+            error: None,
+            data,
+        }
+    }
+
+    pub fn error(error: String) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            ok: false,
+            error: Some(error),
+            data: Value::Null,
+        }
+    }
+}
+
+// what the daemon reports for each instance in a `list`
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct InstanceState {
+    pub name: String,
+    pub running: bool,
+    pub pid: Option<u32>,
+    pub supervised: bool,
+    pub autostart: bool,
+    pub restart_policy: String,
+    pub restarts: u64,
+}
+
+pub fn socket_path() -> PathBuf {
+    ProjectDirs::from("com", "wyomingwade", "slapaman")
+        .expect("could not determine a home directory")
+        .data_dir()
+        .join(SOCKET_NAME)
+}
+
+// send a command to the daemon; None when no daemon is listening
+pub fn try_daemon(command: ControlCommand) -> Option<Result<Value, String>> {
+    let mut stream = connect()?;
+    Some(exchange(&mut stream, command))
+}
+
+pub fn daemon_running() -> bool {
+    connect().is_some()
+}
+
+#[cfg(unix)]
+fn connect() -> Option<std::os::unix::net::UnixStream> {
+    std::os::unix::net::UnixStream::connect(socket_path()).ok()
+}
+
+#[cfg(not(unix))]
+fn connect() -> Option<std::net::TcpStream> {
+    None
+}
+
+fn exchange<S: std::io::Read + std::io::Write>(
+    stream: &mut S,
+    command: ControlCommand,
+) -> Result<Value, String> {
+    use std::io::{BufRead, BufReader};
+
+    let request = Request {
+        version: PROTOCOL_VERSION,
+        command,
+    };
+    let line = serde_json::to_string(&request)
+        .map_err(|e| format!("failed to encode daemon request: {}", e))?;
+    writeln!(stream, "{}", line).map_err(|e| format!("failed to talk to the daemon: {}", e))?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| format!("failed to read the daemon's reply: {}", e))?;
+    if reply.is_empty() {
+        return Err("the daemon closed the connection without replying".to_string());
+    }
+    let response: Response = serde_json::from_str(&reply)
+        .map_err(|e| format!("failed to decode the daemon's reply: {}", e))?;
+
+    match response.ok {
+        true => Ok(response.data),
+        false => Err(response
+            .error
+            .unwrap_or_else(|| "the daemon reported an error".to_string())),
+    }
+}
@@ -5,11 +5,14 @@ use directories::ProjectDirs;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use serde_json::json;
 
 use crate::run::run_server;
 use crate::server::{add_server_to_list, does_server_exist, update_server_by_name, Server};
 use crate::version::{download_server_version, format_version_string, Version};
+use crate::webhooks::{notify, Notification, WebhookEvent};
 
 pub async fn create_new_server(
     // slapaman params
@@ -70,11 +73,17 @@ pub async fn create_new_server(
     }
 
     println!("[slapaman] created server instance: {}", &name);
+    notify(Notification::new(
+        WebhookEvent::Created,
+        &name,
+        format!("{} was created ({} {})", name, flavor, version_string),
+        json!({ "version": version_string, "flavor": flavor, "path": server_dir }),
+    ));
 
     Ok(())
 }
 
-fn agree_to_eula(server_dir: &PathBuf) -> Result<(), String> {
+fn agree_to_eula(server_dir: &Path) -> Result<(), String> {
     let eula_path = server_dir.join("eula.txt");
     let mut eula_file = File::create(eula_path).unwrap();
     eula_file.write_all(b"eula=true\n").unwrap();
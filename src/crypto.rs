@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+use age::stream::StreamWriter;
+use age::{DecryptError, Decryptor, Encryptor, Identity, IdentityFile, Recipient};
+
+use crate::server::Server;
+
+// every age file starts with this, so encrypted backups are told apart by content rather than name
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+// read instead of prompting, for scheduled backups and scripts
+pub const PASSPHRASE_ENV: &str = "SLAPAMAN_BACKUP_PASSPHRASE";
+// a file holding the passphrase or age identities, used over the instance's configured one
+pub const KEY_FILE_ENV: &str = "SLAPAMAN_BACKUP_KEY_FILE";
+
+// how an instance's backup archives and manifests are encrypted; either way they're ordinary age
+// files, so the `age` tool can decrypt them too
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Encryption {
+    // anyone with the passphrase can restore
+    Passphrase,
+    // anyone with the private key of one of these age public keys (age1...) can restore, and
+    // backups can be taken without any secret at all
+    Recipients { recipients: Vec<String> },
+}
+
+impl Encryption {
+    // parse `world-backup-config --encrypt`: "passphrase", "off" (None) or comma-separated age
+    // public keys
+    pub fn parse(value: &str) -> Result<Option<Self>, String> {
+        match value {
+            "off" => return Ok(None),
+            "passphrase" => return Ok(Some(Encryption::Passphrase)),
+            _ => (),
+        }
+        let recipients: Vec<String> = value
+            .split(',')
+            .map(|recipient| recipient.trim().to_string())
+            .filter(|recipient| !recipient.is_empty())
+            .collect();
+        for recipient in &recipients {
+            recipient.parse::<age::x25519::Recipient>().map_err(|_| {
+                format!(
+                    "not an age public key: {} (expected \"passphrase\", \"off\" or keys like age1...)",
+                    recipient
+                )
+            })?;
+        }
+        match recipients.is_empty() {
+            true => Err("no age public keys given".to_string()),
+            false => Ok(Some(Encryption::Recipients { recipients })),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Encryption::Passphrase => "passphrase".to_string(),
+            Encryption::Recipients { recipients } => format!("age ({})", recipients.join(", ")),
+        }
+    }
+}
+
+// where the secrets for encrypted backups come from: a key file (given on the command line, in
+// $SLAPAMAN_BACKUP_KEY_FILE or in the instance's config), $SLAPAMAN_BACKUP_PASSPHRASE, or a prompt
+// when there's a terminal to ask on; nothing is looked up until it's needed, and then it's kept
+// for the rest of the command so the user is asked at most once
+pub struct BackupKeys {
+    name: String,
+    key_file: Option<PathBuf>,
+    passphrase: RefCell<Option<SecretString>>,
+    identities: RefCell<Option<Vec<Box<dyn Identity>>>>,
+}
+
+impl BackupKeys {
+    pub fn new(server: &Server, key_file: Option<&Path>) -> Self {
+        let key_file = key_file
+            .map(|path| path.to_path_buf())
+            .or_else(|| env::var_os(KEY_FILE_ENV).map(PathBuf::from))
+            .or_else(|| server.backup.key_file.clone());
+        Self {
+            name: server.name.clone(),
+            key_file,
+            passphrase: RefCell::new(None),
+            identities: RefCell::new(None),
+        }
+    }
+
+    // whether there's anywhere to get the kind of key `path` needs from, without asking for it
+    // yet; files that aren't encrypted need none
+    pub fn available(&self, path: &Path) -> bool {
+        let scrypt = File::open(path)
+            .ok()
+            .and_then(|file| Decryptor::new_buffered(BufReader::new(file)).ok())
+            .map(|decryptor| decryptor.is_scrypt());
+        let has_identities = self
+            .key_file
+            .as_deref()
+            .and_then(|key_file| fs::read_to_string(key_file).ok())
+            .map(|contents| is_identity_file(&contents));
+        match scrypt {
+            Some(true) => {
+                has_identities == Some(false)
+                    || env::var_os(PASSPHRASE_ENV).is_some()
+                    || io::stdin().is_terminal()
+            }
+            Some(false) => has_identities == Some(true),
+            None => true,
+        }
+    }
+
+    // an encryptor for one new file; each file gets its own
+    pub fn encryptor(&self, encryption: &Encryption) -> Result<Encryptor, String> {
+        match encryption {
+            Encryption::Passphrase => Ok(Encryptor::with_user_passphrase(self.passphrase(true)?)),
+            Encryption::Recipients { recipients } => {
+                let recipients = recipients
+                    .iter()
+                    .map(|recipient| {
+                        recipient
+                            .parse::<age::x25519::Recipient>()
+                            .map_err(|_| format!("not an age public key: {}", recipient))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn Recipient))
+                    .map_err(|e| format!("failed to set up encryption: {}", e))
+            }
+        }
+    }
+
+    // open a backup file for reading, decrypting it on the fly if it's encrypted
+    pub fn open(&self, path: &Path) -> Result<Box<dyn Read>, String> {
+        let file =
+            File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        let mut reader = BufReader::new(file);
+        let encrypted = reader
+            .fill_buf()
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            .starts_with(AGE_MAGIC);
+        if !encrypted {
+            return Ok(Box::new(reader));
+        }
+
+        let decryptor = Decryptor::new_buffered(reader)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let decrypted = match decryptor.is_scrypt() {
+            true => {
+                let identity = age::scrypt::Identity::new(self.passphrase(false)?);
+                decryptor.decrypt(iter::once(&identity as &dyn Identity))
+            }
+            false => {
+                self.load_identities()?;
+                let identities = self.identities.borrow();
+                let identities = identities.as_deref().unwrap_or_default();
+                decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))
+            }
+        };
+        match decrypted {
+            Ok(reader) => Ok(Box::new(reader)),
+            Err(DecryptError::DecryptionFailed) => {
+                // don't keep a wrong passphrase around for the next file
+                self.passphrase.replace(None);
+                Err(format!("wrong passphrase for {}", path.display()))
+            }
+            Err(DecryptError::NoMatchingKeys) => Err(format!(
+                "none of the keys in {} can decrypt {}",
+                self.key_file
+                    .as_deref()
+                    .map(|path| path.display().to_string())
+                    .unwrap_or_default(),
+                path.display()
+            )),
+            Err(e) => Err(format!("failed to decrypt {}: {}", path.display(), e)),
+        }
+    }
+
+    fn passphrase(&self, confirm: bool) -> Result<SecretString, String> {
+        if let Some(passphrase) = self.passphrase.borrow().as_ref() {
+            return Ok(passphrase.clone());
+        }
+
+        // a key file holding identities is for other backups, so it doesn't stop the fallbacks
+        let from_file = match self.key_file.as_deref() {
+            Some(key_file) => {
+                let contents = read_key_file(key_file)?;
+                (!is_identity_file(&contents))
+                    .then(|| contents.lines().next().unwrap_or_default().to_string())
+            }
+            None => None,
+        };
+        let passphrase = match (from_file, env::var(PASSPHRASE_ENV)) {
+            (Some(passphrase), _) => passphrase,
+            (None, Ok(passphrase)) => passphrase,
+            (None, Err(_)) if io::stdin().is_terminal() => {
+                let prompt = format!("passphrase for {}'s backups: ", self.name);
+                let passphrase = rpassword::prompt_password(prompt)
+                    .map_err(|e| format!("failed to read passphrase: {}", e))?;
+                if confirm
+                    && rpassword::prompt_password("again, to confirm: ")
+                        .map_err(|e| format!("failed to read passphrase: {}", e))?
+                        != passphrase
+                {
+                    return Err("the passphrases didn't match".to_string());
+                }
+                passphrase
+            }
+            (None, Err(_)) => {
+                return Err(format!(
+                    "{}'s backups are encrypted with a passphrase; set {}, or point {} or --key-file at a file holding it",
+                    self.name, PASSPHRASE_ENV, KEY_FILE_ENV
+                ))
+            }
+        };
+        if passphrase.is_empty() {
+            return Err("the passphrase can't be empty".to_string());
+        }
+
+        let passphrase = SecretString::from(passphrase);
+        self.passphrase.replace(Some(passphrase.clone()));
+        Ok(passphrase)
+    }
+
+    fn load_identities(&self) -> Result<(), String> {
+        if self.identities.borrow().is_some() {
+            return Ok(());
+        }
+        let key_file = self.key_file.as_deref().ok_or_else(|| {
+            format!(
+                "{}'s backups are encrypted to age keys; give the identity file with --key-file or {}",
+                self.name, KEY_FILE_ENV
+            )
+        })?;
+        let contents = read_key_file(key_file)?;
+        let identities = IdentityFile::from_buffer(contents.as_bytes())
+            .and_then(|file| file.into_identities().map_err(io::Error::other))
+            .map_err(|e| {
+                format!(
+                    "failed to read identities from {}: {}",
+                    key_file.display(),
+                    e
+                )
+            })?;
+        self.identities.replace(Some(identities));
+        Ok(())
+    }
+}
+
+// whether a file is age-encrypted, without needing any keys
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut magic = [0; AGE_MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|_| magic == AGE_MAGIC)
+}
+
+// a new backup file, encrypted when given an encryptor
+pub enum BackupWriter {
+    Plain(BufWriter<File>),
+    Encrypted(StreamWriter<BufWriter<File>>),
+}
+
+impl BackupWriter {
+    pub fn create(path: &Path, encryptor: Option<Encryptor>) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        let writer = BufWriter::new(file);
+        match encryptor {
+            Some(encryptor) => encryptor
+                .wrap_output(writer)
+                .map(BackupWriter::Encrypted)
+                .map_err(|e| format!("failed to start encrypting {}: {}", path.display(), e)),
+            None => Ok(BackupWriter::Plain(writer)),
+        }
+    }
+
+    // write out the end of the encrypted stream, without which it can't be decrypted
+    pub fn finish(self) -> io::Result<()> {
+        let mut writer = match self {
+            BackupWriter::Plain(writer) => writer,
+            BackupWriter::Encrypted(writer) => writer.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+impl Write for BackupWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            BackupWriter::Plain(writer) => writer.write(buf),
+            BackupWriter::Encrypted(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            BackupWriter::Plain(writer) => writer.flush(),
+            BackupWriter::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+fn read_key_file(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
+
+fn is_identity_file(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.trim().starts_with("AGE-SECRET-KEY-"))
+}
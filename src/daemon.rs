@@ -0,0 +1,548 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::process::Child;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::backup::create_world_backup;
+use crate::console::{send_console_command, stop_server};
+use crate::control::{ControlCommand, InstanceState, Request, Response, PROTOCOL_VERSION};
+use crate::process::pid_alive;
+use crate::run::{running_pid, spawn_background_server};
+use crate::schedule::{broadcast_countdown, run_task, Scheduler, TaskAction};
+use crate::server::{get_all_servers, record_restart, update_server_by_name, Server};
+use crate::update::update_server;
+use crate::version::Version;
+
+const SUPERVISE_INTERVAL: Duration = Duration::from_secs(1);
+const STOP_TIMEOUT: Duration = Duration::from_secs(90);
+// restarts back off from this, doubling up to the maximum
+const RESTART_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+// a server that stays up this long has its backoff reset
+const STABLE_UPTIME: Duration = Duration::from_secs(600);
+
+#[derive(
+    ValueEnum,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// leave the server down when it exits
+    Never,
+    /// restart the server when it crashes, but not after a clean `stop`
+    #[default]
+    OnFailure,
+    /// restart the server whenever it exits, unless slapaman stopped it
+    Always,
+}
+
+// the daemon's view of one instance
+#[derive(Default)]
+struct Supervised {
+    // set when the daemon started the JVM's `slapaman run` itself
+    child: Option<Child>,
+    // set when the server was already running and the daemon adopted it
+    adopted: Option<u32>,
+    // whether the server should be running
+    wanted: bool,
+    // an update or restart is in progress, so leave the server alone
+    maintenance: bool,
+    memory: Option<u32>,
+    started: Option<Instant>,
+    failures: u32,
+    restart_at: Option<Instant>,
+}
+
+impl Supervised {
+    fn is_running(&self) -> bool {
+        self.child.is_some() || self.adopted.is_some()
+    }
+}
+
+#[derive(Default)]
+struct Supervisor {
+    instances: HashMap<String, Supervised>,
+}
+
+type Shared = Arc<Mutex<Supervisor>>;
+
+pub fn set_autostart(
+    name: &String,
+    enabled: bool,
+    restart: Option<RestartPolicy>,
+) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+    server.autostart = enabled;
+    if let Some(restart) = restart {
+        server.restart_policy = restart;
+    }
+    update_server_by_name(name, &server)
+}
+
+// supervise autostart instances and serve the control socket until interrupted
+pub fn run_daemon() -> Result<(), String> {
+    let supervisor: Shared = Arc::new(Mutex::new(Supervisor::default()));
+
+    let listener = listen()?;
+    println!(
+        "[slapaman] daemon listening on {}",
+        crate::control::socket_path().display()
+    );
+
+    let loop_supervisor = supervisor.clone();
+    thread::spawn(move || supervise(loop_supervisor));
+
+    serve(listener, supervisor)
+}
+
+// the supervision loop: pick up autostart instances, reap exits, apply restart policies and run
+// schedules
+fn supervise(supervisor: Shared) {
+    let mut scheduler = Scheduler::new();
+
+    loop {
+        let servers = match get_all_servers() {
+            Ok(servers) => servers,
+            Err(e) => {
+                println!("[slapaman] daemon: failed to load instances: {}", e);
+                thread::sleep(SUPERVISE_INTERVAL);
+                continue;
+            }
+        };
+
+        {
+            let mut state = supervisor.lock().unwrap();
+            for server in &servers {
+                if !state.instances.contains_key(&server.name) && server.autostart {
+                    state.instances.insert(
+                        server.name.clone(),
+                        Supervised {
+                            wanted: true,
+                            ..Default::default()
+                        },
+                    );
+                }
+            }
+            // forget instances that were removed or renamed, once they're down
+            state.instances.retain(|name, instance| {
+                instance.is_running() || servers.iter().any(|s| s.name == *name)
+            });
+
+            for server in &servers {
+                if let Some(instance) = state.instances.get_mut(&server.name) {
+                    check_instance(server, instance);
+                }
+            }
+        }
+
+        let task_supervisor = supervisor.clone();
+        scheduler.tick(&servers, move |name, action| {
+            run_daemon_task(&task_supervisor, name, action)
+        });
+
+        thread::sleep(SUPERVISE_INTERVAL);
+    }
+}
+
+fn check_instance(server: &Server, instance: &mut Supervised) {
+    let name = &server.name;
+
+    // notice exits
+    let exit = match (&mut instance.child, instance.adopted) {
+        (Some(child), _) => match child.try_wait() {
+            Ok(Some(status)) => Some(status.success()),
+            _ => None,
+        },
+        // we can't see how an adopted server exited, so assume the worst
+        (None, Some(pid)) if !pid_alive(pid) => Some(false),
+        _ => None,
+    };
+    if let Some(success) = exit {
+        instance.child = None;
+        instance.adopted = None;
+        if instance
+            .started
+            .is_some_and(|s| s.elapsed() > STABLE_UPTIME)
+        {
+            instance.failures = 0;
+        }
+
+        if instance.wanted && !instance.maintenance {
+            let restart = match server.restart_policy {
+                RestartPolicy::Never => false,
+                RestartPolicy::OnFailure => !success,
+                RestartPolicy::Always => true,
+            };
+            match restart {
+                true => {
+                    let backoff = RESTART_BACKOFF
+                        .saturating_mul(2u32.saturating_pow(instance.failures))
+                        .min(MAX_RESTART_BACKOFF);
+                    println!(
+                        "[slapaman] daemon: {} exited ({}), restarting in {}s",
+                        name,
+                        if success { "cleanly" } else { "with an error" },
+                        backoff.as_secs()
+                    );
+                    instance.failures += 1;
+                    instance.restart_at = Some(Instant::now() + backoff);
+                    if let Err(e) = record_restart(name) {
+                        println!(
+                            "[slapaman] daemon: failed to record restart of {}: {}",
+                            name, e
+                        );
+                    }
+                }
+                false => {
+                    println!("[slapaman] daemon: {} exited and will stay down", name);
+                    instance.wanted = false;
+                }
+            }
+        }
+    }
+
+    // start servers that should be running
+    if !instance.wanted || instance.maintenance || instance.is_running() {
+        return;
+    }
+    if instance.restart_at.is_some_and(|at| Instant::now() < at) {
+        return;
+    }
+    instance.restart_at = None;
+
+    let server_dir = server.path.join(name);
+    if let Some(pid) = running_pid(&server_dir) {
+        println!(
+            "[slapaman] daemon: adopting running server {} (pid {})",
+            name, pid
+        );
+        instance.adopted = Some(pid);
+        instance.started = Some(Instant::now());
+        return;
+    }
+
+    match spawn_background_server(name, instance.memory) {
+        Ok(child) => {
+            println!("[slapaman] daemon: started {}", name);
+            instance.child = Some(child);
+            instance.started = Some(Instant::now());
+        }
+        Err(e) => {
+            println!("[slapaman] daemon: failed to start {}: {}", name, e);
+            instance.failures += 1;
+            instance.restart_at = Some(Instant::now() + MAX_RESTART_BACKOFF);
+        }
+    }
+}
+
+// scheduled restarts go through the supervisor so it doesn't mistake them for crashes
+fn run_daemon_task(supervisor: &Shared, name: &String, action: &TaskAction) -> Result<(), String> {
+    match action {
+        TaskAction::Restart { warnings } => {
+            broadcast_countdown(name, warnings)?;
+            restart_instance(supervisor, name)
+        }
+        _ => run_task(name, action),
+    }
+}
+
+fn handle(supervisor: &Shared, request: Request) -> Response {
+    if request.version != PROTOCOL_VERSION {
+        return Response::error(format!(
+            "protocol version mismatch: the daemon speaks version {}, the request used {}",
+            PROTOCOL_VERSION, request.version
+        ));
+    }
+
+    let result: Result<Value, String> = match request.command {
+        ControlCommand::Ping => Ok(json!({
+            "pid": std::process::id(),
+            "version": env!("CARGO_PKG_VERSION"),
+        })),
+        ControlCommand::List => list_instances(supervisor).map(|states| json!(states)),
+        ControlCommand::Start { name, memory } => start_instance(supervisor, &name, memory),
+        ControlCommand::Stop { name, timeout } => {
+            stop_instance(supervisor, &name, Duration::from_secs(timeout)).map(|_| Value::Null)
+        }
+        ControlCommand::Restart { name } => {
+            restart_instance(supervisor, &name).map(|_| Value::Null)
+        }
+        ControlCommand::Update {
+            name,
+            version,
+            flavor,
+        } => with_instance_stopped(supervisor, &name, || {
+            let runtime = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to start async runtime: {}", e))?;
+            runtime.block_on(update_server(&name, Version::from_string(version), flavor))
+        })
+        .map(|_| Value::Null),
+        ControlCommand::WorldBackup {
+            name,
+            tag,
+            format,
+            scope,
+        } => with_maintenance(supervisor, &name, || {
+            create_world_backup(0, name.clone(), tag, format, scope)
+        })
+        .map(|path| json!({ "path": path })),
+        ControlCommand::Console { name, command } => Server::load_by_name(&name)
+            .and_then(|server| send_console_command(&server.path.join(&name), &command))
+            .map(|_| Value::Null),
+    };
+
+    match result {
+        Ok(data) => Response::ok(data),
+        Err(e) => Response::error(e),
+    }
+}
+
+fn list_instances(supervisor: &Shared) -> Result<Vec<InstanceState>, String> {
+    let servers = get_all_servers()?;
+    let state = supervisor.lock().unwrap();
+
+    Ok(servers
+        .iter()
+        .map(|server| {
+            let instance = state.instances.get(&server.name);
+            let pid = running_pid(&server.path.join(&server.name));
+            InstanceState {
+                name: server.name.clone(),
+                running: pid.is_some(),
+                pid,
+                supervised: instance.is_some_and(|i| i.wanted || i.is_running()),
+                autostart: server.autostart,
+                restart_policy: format!("{:?}", server.restart_policy).to_lowercase(),
+                restarts: server.restarts,
+            }
+        })
+        .collect())
+}
+
+fn start_instance(
+    supervisor: &Shared,
+    name: &String,
+    memory: Option<u32>,
+) -> Result<Value, String> {
+    let server = Server::load_by_name(name)?;
+    if !server.path.join(name).exists() {
+        return Err(format!("server instance does not exist: {}", name));
+    }
+
+    let mut state = supervisor.lock().unwrap();
+    let instance = state.instances.entry(name.clone()).or_default();
+    if instance.is_running() {
+        return Err(format!("server is already running: {}", name));
+    }
+    instance.wanted = true;
+    instance.memory = memory;
+    instance.failures = 0;
+    instance.restart_at = None;
+    check_instance(&server, instance);
+
+    match instance.is_running() {
+        true => Ok(json!({ "pid": instance.child.as_ref().map(|c| c.id()).or(instance.adopted) })),
+        false => Err(format!(
+            "the daemon failed to start {} (see the daemon's output)",
+            name
+        )),
+    }
+}
+
+fn stop_instance(supervisor: &Shared, name: &String, timeout: Duration) -> Result<(), String> {
+    {
+        let mut state = supervisor.lock().unwrap();
+        if let Some(instance) = state.instances.get_mut(name) {
+            instance.wanted = false;
+        }
+    }
+    stop_server(name, timeout)?;
+    wait_for_exit(supervisor, name);
+    Ok(())
+}
+
+fn restart_instance(supervisor: &Shared, name: &String) -> Result<(), String> {
+    with_instance_stopped(supervisor, name, || Ok(()))?;
+    let mut state = supervisor.lock().unwrap();
+    let instance = state.instances.entry(name.clone()).or_default();
+    instance.wanted = true;
+    instance.restart_at = None;
+    drop(state);
+    record_restart(name)
+}
+
+// stop a server for the duration of `f`, then bring it back if it was running before
+fn with_instance_stopped<T>(
+    supervisor: &Shared,
+    name: &String,
+    f: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    let server = Server::load_by_name(name)?;
+    let was_running = running_pid(&server.path.join(name)).is_some();
+
+    with_maintenance(supervisor, name, || {
+        if was_running {
+            stop_server(name, STOP_TIMEOUT)?;
+            wait_for_exit(supervisor, name);
+        }
+        let result = f();
+
+        if was_running {
+            let mut state = supervisor.lock().unwrap();
+            let instance = state.instances.entry(name.clone()).or_default();
+            instance.wanted = true;
+            instance.restart_at = None;
+        }
+        result
+    })
+}
+
+// keep the supervisor's hands off an instance while `f` runs, even if `f` panics
+fn with_maintenance<T>(
+    supervisor: &Shared,
+    name: &String,
+    f: impl FnOnce() -> Result<T, String>,
+) -> Result<T, String> {
+    struct Guard<'a> {
+        supervisor: &'a Shared,
+        name: &'a String,
+    }
+    impl Drop for Guard<'_> {
+        fn drop(&mut self) {
+            if let Ok(mut state) = self.supervisor.lock() {
+                if let Some(instance) = state.instances.get_mut(self.name) {
+                    instance.maintenance = false;
+                }
+            }
+        }
+    }
+
+    {
+        let mut state = supervisor.lock().unwrap();
+        let instance = state.instances.entry(name.clone()).or_default();
+        if instance.maintenance {
+            return Err(format!("{} is busy with another operation", name));
+        }
+        instance.maintenance = true;
+    }
+    let _guard = Guard { supervisor, name };
+    f()
+}
+
+// wait until the supervision loop has reaped the server's `slapaman run`
+fn wait_for_exit(supervisor: &Shared, name: &String) {
+    let started = Instant::now();
+    while started.elapsed() < STOP_TIMEOUT {
+        {
+            let mut state = supervisor.lock().unwrap();
+            match state.instances.get_mut(name) {
+                Some(instance) => {
+                    if let Some(child) = &mut instance.child {
+                        if matches!(child.try_wait(), Ok(Some(_))) {
+                            instance.child = None;
+                        }
+                    }
+                    if instance.adopted.is_some_and(|pid| !pid_alive(pid)) {
+                        instance.adopted = None;
+                    }
+                    if !instance.is_running() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+#[cfg(unix)]
+fn listen() -> Result<std::os::unix::net::UnixListener, String> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let path = crate::control::socket_path();
+    if crate::control::daemon_running() {
+        return Err(format!(
+            "a daemon is already listening on {}",
+            path.display()
+        ));
+    }
+    // a socket nobody answers on was left behind by a daemon that died
+    let _ = std::fs::remove_file(&path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let listener = UnixListener::bind(&path)
+        .map_err(|e| format!("failed to listen on {}: {}", path.display(), e))?;
+    // only the owner gets to control the servers
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("failed to secure {}: {}", path.display(), e))?;
+
+    Ok(listener)
+}
+
+#[cfg(unix)]
+fn serve(listener: std::os::unix::net::UnixListener, supervisor: Shared) -> Result<(), String> {
+    use std::io::{BufRead, BufReader, Write};
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("[slapaman] daemon: failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let supervisor = supervisor.clone();
+        thread::spawn(move || {
+            let reader = match stream.try_clone() {
+                Ok(reader) => BufReader::new(reader),
+                Err(_) => return,
+            };
+            for line in reader.lines() {
+                let line = match line {
+                    Ok(line) if line.trim().is_empty() => continue,
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                let response = match serde_json::from_str::<Request>(&line) {
+                    Ok(request) => handle(&supervisor, request),
+                    Err(e) => Response::error(format!("invalid request: {}", e)),
+                };
+                let reply = serde_json::to_string(&response).unwrap_or_default();
+                if writeln!(stream, "{}", reply).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn listen() -> Result<(), String> {
+    Err("the daemon needs Unix domain sockets, which this platform lacks".to_string())
+}
+
+#[cfg(not(unix))]
+fn serve(_listener: (), _supervisor: Shared) -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive::{entry_name, walk};
+use crate::destinations::{hash_file, partial_path, RemoteFile};
+use crate::snapshot::safe_join;
+
+// every file under `prefix`, with keys relative to it
+pub fn list(root: &Path, prefix: &str) -> Result<Vec<RemoteFile>, String> {
+    let dir = safe_join(root, prefix)?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for (path, relative) in walk(&dir)? {
+        if path.is_symlink() || !path.is_file() {
+            continue;
+        }
+        let size = fs::metadata(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            .len();
+        files.push(RemoteFile {
+            key: entry_name(&relative),
+            size,
+        });
+    }
+    Ok(files)
+}
+
+pub fn upload(root: &Path, files: &[(PathBuf, String)]) -> Result<(), String> {
+    for (local, key) in files {
+        let target = safe_join(root, key)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+        // copied under a temporary name, so an interrupted upload never looks finished
+        let partial = partial_path(&target);
+        fs::copy(local, &partial)
+            .and_then(|_| fs::rename(&partial, &target))
+            .map_err(|e| {
+                let _ = fs::remove_file(&partial);
+                format!("failed to copy {} to {}: {}", key, root.display(), e)
+            })?;
+    }
+    Ok(())
+}
+
+pub fn download(root: &Path, files: &[(String, PathBuf)]) -> Result<(), String> {
+    for (key, local) in files {
+        fs::copy(safe_join(root, key)?, local)
+            .map_err(|e| format!("failed to copy {} from {}: {}", key, root.display(), e))?;
+    }
+    Ok(())
+}
+
+pub fn hashes(root: &Path, keys: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut hashes = HashMap::new();
+    for key in keys {
+        let path = safe_join(root, key)?;
+        if path.is_file() {
+            hashes.insert(key.clone(), hash_file(&path)?);
+        }
+    }
+    Ok(hashes)
+}
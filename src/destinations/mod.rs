@@ -0,0 +1,676 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+pub mod local;
+pub mod s3;
+pub mod sftp;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+
+use crate::archive::{backup_stem, entry_name, walk, BackupFormat};
+use crate::backup::BACKUPS_DIR_NAME;
+use crate::catalog::{parse_backup_id, resolve_backup, serialize_taken};
+use crate::integrity::{manifest_path, MANIFEST_SUFFIX};
+use crate::resources::format_bytes;
+use crate::server::{update_server_by_name, Server};
+use crate::snapshot::{chunk_path, is_snapshot, safe_join, Manifest, CHUNKS_DIR_NAME};
+
+use self::s3::S3Target;
+use self::sftp::SftpTarget;
+
+// where backups pulled from a destination are downloaded to before they're moved into place
+const DOWNLOAD_STAGING_DIR_NAME: &str = ".slapaman-download";
+
+// somewhere besides the instance's own backups directory that its backups are copied to, stored
+// in the registry with the rest of its backup config; each instance's backups go in a folder
+// named after it, laid out like its backups directory
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct BackupDestination {
+    pub name: String,
+    #[serde(flatten)]
+    pub target: DestinationTarget,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DestinationTarget {
+    // a directory on another disk, or a mounted network share
+    Local { path: PathBuf },
+    Sftp(SftpTarget),
+    S3(S3Target),
+}
+
+// the settings `world-backup-destination add` takes on top of the URL
+#[derive(Default)]
+pub struct DestinationOptions {
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub identity: Option<PathBuf>,
+}
+
+// one file stored at a destination
+pub struct RemoteFile {
+    pub key: String,
+    pub size: u64,
+}
+
+// a backup stored at a destination
+#[derive(serde_derive::Serialize, Clone, Debug)]
+pub struct RemoteBackup {
+    // position in `world-backups --from`, oldest first
+    pub index: usize,
+    pub id: String,
+    // the backup's file or folder name
+    pub name: String,
+    #[serde(serialize_with = "serialize_taken")]
+    pub taken: NaiveDateTime,
+    pub tag: Option<String>,
+    // what the backup takes up at the destination (for incremental backups, just the snapshot)
+    pub size: u64,
+    pub format: BackupFormat,
+    #[serde(skip)]
+    keys: Vec<String>,
+}
+
+impl DestinationTarget {
+    // a destination from a URL: a local path (or file://), sftp://[user@]host[:port]/path, or
+    // s3://bucket[/prefix]
+    pub fn parse(url: &str, options: DestinationOptions) -> Result<Self, String> {
+        let DestinationOptions {
+            endpoint,
+            region,
+            access_key,
+            secret_key,
+            identity,
+        } = options;
+        let is_s3 = url.starts_with("s3://");
+        if !is_s3 && (endpoint.is_some() || region.is_some() || access_key.is_some()) {
+            return Err(
+                "--endpoint, --region and the S3 keys only apply to s3:// destinations".to_string(),
+            );
+        }
+        if !url.starts_with("sftp://") && identity.is_some() {
+            return Err("--identity only applies to sftp:// destinations".to_string());
+        }
+
+        if let Some(rest) = url.strip_prefix("s3://") {
+            return S3Target::parse(rest, endpoint, region, access_key, secret_key)
+                .map(DestinationTarget::S3);
+        }
+        if let Some(rest) = url.strip_prefix("sftp://") {
+            return SftpTarget::parse(rest, identity).map(DestinationTarget::Sftp);
+        }
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        if path.contains("://") {
+            return Err(format!("unsupported backup destination: {}", url));
+        }
+        let path = std::path::absolute(path)
+            .map_err(|e| format!("invalid destination path {}: {}", path, e))?;
+        Ok(DestinationTarget::Local { path })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            DestinationTarget::Local { path } => path.display().to_string(),
+            DestinationTarget::Sftp(target) => target.describe(),
+            DestinationTarget::S3(target) => target.describe(),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<RemoteFile>, String> {
+        match self {
+            DestinationTarget::Local { path } => local::list(path, prefix),
+            DestinationTarget::Sftp(target) => sftp::list(target, prefix),
+            DestinationTarget::S3(target) => s3::list(target, prefix),
+        }
+    }
+
+    fn upload(&self, files: &[(PathBuf, String)]) -> Result<(), String> {
+        match self {
+            DestinationTarget::Local { path } => local::upload(path, files),
+            DestinationTarget::Sftp(target) => sftp::upload(target, files),
+            DestinationTarget::S3(target) => s3::upload(target, files),
+        }
+    }
+
+    fn download(&self, files: &[(String, PathBuf)]) -> Result<(), String> {
+        match self {
+            DestinationTarget::Local { path } => local::download(path, files),
+            DestinationTarget::Sftp(target) => sftp::download(target, files),
+            DestinationTarget::S3(target) => s3::download(target, files),
+        }
+    }
+
+    // SHA-256 of each of `keys` as stored at the destination; keys that aren't there are left out
+    fn hashes(&self, keys: &[String]) -> Result<HashMap<String, String>, String> {
+        match self {
+            DestinationTarget::Local { path } => local::hashes(path, keys),
+            DestinationTarget::Sftp(target) => sftp::hashes(target, keys),
+            DestinationTarget::S3(target) => s3::hashes(target, keys),
+        }
+    }
+}
+
+pub fn add_destination(
+    name: &String,
+    destination: &str,
+    url: &str,
+    options: DestinationOptions,
+) -> Result<String, String> {
+    let mut server = Server::load_by_name(name)?;
+    if destination.is_empty()
+        || !destination
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid destination name {:?}: use letters, digits, - and _",
+            destination
+        ));
+    }
+    if server
+        .backup
+        .destinations
+        .iter()
+        .any(|d| d.name == destination)
+    {
+        return Err(format!(
+            "{} already has a backup destination named {}",
+            name, destination
+        ));
+    }
+
+    let stores_secret = options.secret_key.is_some();
+    let target = DestinationTarget::parse(url, options)?;
+    let description = target.describe();
+    server.backup.destinations.push(BackupDestination {
+        name: destination.to_string(),
+        target,
+    });
+    update_server_by_name(name, &server)?;
+    if stores_secret {
+        println!(
+            "[slapaman] note: the secret key is stored in slapaman's registry, which only you can read; leave out --access-key and --secret-key to use $AWS_ACCESS_KEY_ID and $AWS_SECRET_ACCESS_KEY instead"
+        );
+    }
+    Ok(description)
+}
+
+pub fn remove_destination(name: &String, destination: &str) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+    let before = server.backup.destinations.len();
+    server.backup.destinations.retain(|d| d.name != destination);
+    if server.backup.destinations.len() == before {
+        return Err(format!(
+            "no backup destination named {} for {}",
+            destination, name
+        ));
+    }
+    update_server_by_name(name, &server)
+}
+
+pub fn list_destinations(name: &String) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    if server.backup.destinations.is_empty() {
+        println!("no backup destinations for {}", name);
+        return Ok(());
+    }
+
+    for destination in &server.backup.destinations {
+        println!("{}: {}", destination.name, destination.target.describe());
+    }
+    Ok(())
+}
+
+// check a destination can be reached by listing what's there
+pub fn test_destination(name: &String, destination: &str) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let destination = find_destination(&server, destination)?;
+    let backups = remote_backups(destination, name)?;
+    println!(
+        "[slapaman] reached {} ({}): {} backups of {} there",
+        destination.name,
+        destination.target.describe(),
+        backups.len(),
+        name
+    );
+    Ok(())
+}
+
+// copy a new backup to every destination the instance has; a failed upload is reported but
+// doesn't fail the backup, which is safe locally either way
+pub fn push_to_destinations(name: &String, backup: &Path) {
+    let server = match Server::load_by_name(name) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("[slapaman] error uploading backup: {}", e);
+            return;
+        }
+    };
+    for destination in &server.backup.destinations {
+        if let Err(e) = push_and_report(&server, destination, backup) {
+            println!(
+                "[slapaman] error uploading backup to {}: {}",
+                destination.name, e
+            );
+        }
+    }
+}
+
+// `world-backup-push`: copy an existing backup (by default the newest) to the instance's
+// destinations, or just to one
+pub fn push_world_backup(
+    name: &String,
+    backup: Option<&Path>,
+    to: Option<&str>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let backups_dir = server.path.join(name).join(BACKUPS_DIR_NAME);
+    let backup = resolve_backup(&backups_dir, backup.unwrap_or(Path::new("latest")))?;
+    let destinations: Vec<&BackupDestination> = match to {
+        Some(to) => vec![find_destination(&server, to)?],
+        None => server.backup.destinations.iter().collect(),
+    };
+    if destinations.is_empty() {
+        return Err(format!(
+            "{} has no backup destinations (add one with `slapaman world-backup-destination {} add`)",
+            name, name
+        ));
+    }
+
+    let mut failed = Vec::new();
+    for destination in destinations {
+        if let Err(e) = push_and_report(&server, destination, &backup) {
+            println!(
+                "[slapaman] error uploading backup to {}: {}",
+                destination.name, e
+            );
+            failed.push(destination.name.clone());
+        }
+    }
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(format!("uploading to {} failed", failed.join(", "))),
+    }
+}
+
+fn push_and_report(
+    server: &Server,
+    destination: &BackupDestination,
+    backup: &Path,
+) -> Result<(), String> {
+    let backups_dir = server.path.join(&server.name).join(BACKUPS_DIR_NAME);
+    let files = backup_files(&backups_dir, backup)?;
+
+    // a chunk that's already there is skipped outright, as chunks are named by their contents
+    // (which is what keeps incremental backups incremental); anything else is only skipped when
+    // the stored copy hashes the same
+    let existing: HashMap<String, u64> = destination
+        .target
+        .list(&server.name)?
+        .into_iter()
+        .map(|file| (file.key, file.size))
+        .collect();
+    let chunks_prefix = format!("{}/", CHUNKS_DIR_NAME);
+    let mut sized = Vec::new();
+    for (path, key) in &files {
+        let size = fs::metadata(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+            .len();
+        let present = existing.get(key) == Some(&size);
+        sized.push((path, key, size, present));
+    }
+    let present: Vec<String> = sized
+        .iter()
+        .filter(|(_, key, _, present)| *present && !key.starts_with(&chunks_prefix))
+        .map(|(_, key, _, _)| format!("{}/{}", server.name, key))
+        .collect();
+    let stored = destination.target.hashes(&present)?;
+
+    let mut pending = Vec::new();
+    let mut bytes = 0;
+    for (path, key, size, present) in sized {
+        let remote_key = format!("{}/{}", server.name, key);
+        let skip = match (present, key.starts_with(&chunks_prefix)) {
+            (false, _) => false,
+            (true, true) => true,
+            (true, false) => stored.get(&remote_key) == Some(&hash_file(path)?),
+        };
+        if !skip {
+            pending.push((path.clone(), remote_key));
+            bytes += size;
+        }
+    }
+    destination.target.upload(&pending)?;
+
+    // read back what the destination stored and compare it with the local files
+    let keys: Vec<String> = pending.iter().map(|(_, key)| key.clone()).collect();
+    let stored = destination.target.hashes(&keys)?;
+    let mut mismatched = Vec::new();
+    for (path, key) in &pending {
+        if stored.get(key) != Some(&hash_file(path)?) {
+            mismatched.push(key.clone());
+        }
+    }
+    if !mismatched.is_empty() {
+        return Err(format!(
+            "{} of the uploaded files don't match the backup: {}",
+            mismatched.len(),
+            mismatched.join(", ")
+        ));
+    }
+
+    println!(
+        "[slapaman] uploaded {} to {}: {} files ({}), {} already there, all verified",
+        backup.file_name().unwrap_or_default().to_string_lossy(),
+        destination.name,
+        pending.len(),
+        format_bytes(bytes),
+        files.len() - pending.len()
+    );
+    Ok(())
+}
+
+// every local file that makes up a backup, keyed by its path relative to the backups directory;
+// the snapshot and integrity manifests come last, so a backup is only complete at a destination
+// once everything it refers to is
+fn backup_files(backups_dir: &Path, backup: &Path) -> Result<Vec<(PathBuf, String)>, String> {
+    let name = backup
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .ok_or_else(|| format!("not a backup: {}", backup.display()))?;
+    let mut files = Vec::new();
+
+    if backup.is_dir() {
+        for (path, relative) in walk(backup)? {
+            if !path.is_symlink() && path.is_file() {
+                files.push((path, format!("{}/{}", name, entry_name(&relative))));
+            }
+        }
+    } else {
+        if is_snapshot(backup) {
+            let chunks_dir = backups_dir.join(CHUNKS_DIR_NAME);
+            let mut seen = HashSet::new();
+            for hash in Manifest::load(backup)?
+                .entries
+                .iter()
+                .flat_map(|entry| &entry.chunks)
+            {
+                if seen.insert(hash) {
+                    let path = chunk_path(&chunks_dir, hash)?;
+                    let relative = path.strip_prefix(backups_dir).unwrap_or(&path);
+                    files.push((path.clone(), entry_name(relative)));
+                }
+            }
+        }
+        files.push((backup.to_path_buf(), name.clone()));
+    }
+
+    let manifest = manifest_path(backup);
+    if manifest.exists() {
+        files.push((manifest, format!("{}{}", name, MANIFEST_SUFFIX)));
+    }
+    Ok(files)
+}
+
+// the backups of an instance stored at a destination, oldest first
+fn remote_backups(
+    destination: &BackupDestination,
+    name: &str,
+) -> Result<Vec<RemoteBackup>, String> {
+    // group files by the backup file or folder they belong to
+    let mut groups: BTreeMap<String, Vec<RemoteFile>> = BTreeMap::new();
+    for file in destination.target.list(name)? {
+        // keys come from whatever the destination lists, so ones that could point outside the
+        // backups directory once downloaded are ignored
+        if file.key.ends_with(".partial") || !is_safe_key(&file.key) {
+            continue;
+        }
+        let top = file.key.split('/').next().unwrap_or_default().to_string();
+        groups.entry(top).or_default().push(file);
+    }
+
+    let mut backups = Vec::new();
+    for (top, files) in &groups {
+        let id = backup_stem(top);
+        let format = match files.iter().any(|file| file.key.contains('/')) {
+            true if id == top => BackupFormat::Dir,
+            true => continue,
+            false => match BackupFormat::FILE_FORMATS.into_iter().find(|format| {
+                top.ends_with(&format!(".{}", format.extension().unwrap_or_default()))
+            }) {
+                Some(format) => format,
+                None => continue,
+            },
+        };
+        // skips the chunk store and integrity manifests, among anything else
+        let (taken, tag) = match parse_backup_id(id) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        // integrity manifests travel with their backups rather than being listed themselves
+        let mut keys: Vec<String> = files.iter().map(|file| file.key.clone()).collect();
+        if let Some(manifest) = groups.get(&format!("{}{}", top, MANIFEST_SUFFIX)) {
+            keys.extend(manifest.iter().map(|file| file.key.clone()));
+        }
+        backups.push(RemoteBackup {
+            index: 0,
+            id: id.to_string(),
+            name: top.clone(),
+            taken,
+            tag,
+            size: files.iter().map(|file| file.size).sum(),
+            format,
+            keys,
+        });
+    }
+
+    backups.sort_by(|a, b| a.taken.cmp(&b.taken).then(a.id.cmp(&b.id)));
+    for (i, backup) in backups.iter_mut().enumerate() {
+        backup.index = i + 1;
+    }
+    Ok(backups)
+}
+
+// `world-backups --from`: the backups stored at a destination
+pub fn list_remote_world_backups(
+    name: &String,
+    destination: &str,
+    json: bool,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let destination = find_destination(&server, destination)?;
+    let backups = remote_backups(destination, name)?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&backups)
+            .map_err(|e| format!("failed to serialize backups: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    if backups.is_empty() {
+        println!(
+            "no backups of {} at {} (upload one with `slapaman world-backup-push {} --to {}`)",
+            name, destination.name, name, destination.name
+        );
+        return Ok(());
+    }
+
+    println!(
+        "{:>3}  {:<40} {:<19} {:<12} {:>10}  format",
+        "#", "id", "taken (UTC)", "tag", "stored"
+    );
+    for backup in &backups {
+        println!(
+            "{:>3}  {:<40} {:<19} {:<12} {:>10}  {}",
+            backup.index,
+            backup.id,
+            backup.taken.format("%Y-%m-%d %H:%M:%S"),
+            backup.tag.as_deref().unwrap_or("-"),
+            format_bytes(backup.size),
+            backup.format
+        );
+    }
+    Ok(())
+}
+
+// download a backup from a destination into the instance's backups directory (unless it's
+// already there), returning its local path
+pub fn pull_backup(server: &Server, destination: &str, backup: &str) -> Result<PathBuf, String> {
+    let destination = find_destination(server, destination)?;
+    let backups = remote_backups(destination, &server.name)?;
+    let remote = match backup {
+        "latest" => backups.last(),
+        selector => match selector.parse::<usize>() {
+            Ok(index) => backups.iter().find(|b| b.index == index),
+            Err(_) => backups
+                .iter()
+                .find(|b| b.name == selector || b.id == selector),
+        },
+    }
+    .ok_or_else(|| format!("no backup {} at {}", backup, destination.name))?;
+
+    let backups_dir = server.path.join(&server.name).join(BACKUPS_DIR_NAME);
+    let local = safe_join(&backups_dir, &remote.name)?;
+    if local.exists() {
+        println!(
+            "[slapaman] {} is already in the local backups, using that",
+            remote.name
+        );
+        return Ok(local);
+    }
+
+    // everything lands in a staging directory first, so an interrupted download never leaves
+    // what looks like a complete backup behind
+    let staging_dir = backups_dir.join(DOWNLOAD_STAGING_DIR_NAME);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .map_err(|e| format!("failed to clear old download directory: {}", e))?;
+    }
+    let result = download_into(destination, server, remote, &backups_dir, &staging_dir);
+    let _ = fs::remove_dir_all(&staging_dir);
+    result?;
+
+    println!(
+        "[slapaman] downloaded {} from {}",
+        remote.name, destination.name
+    );
+    Ok(local)
+}
+
+fn download_into(
+    destination: &BackupDestination,
+    server: &Server,
+    remote: &RemoteBackup,
+    backups_dir: &Path,
+    staging_dir: &Path,
+) -> Result<(), String> {
+    let fetch = |keys: &[String]| -> Result<(), String> {
+        let mut files = Vec::new();
+        for key in keys {
+            let local = safe_join(staging_dir, key)?;
+            if let Some(parent) = local.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+            }
+            files.push((format!("{}/{}", server.name, key), local));
+        }
+        destination.target.download(&files)
+    };
+    fetch(&remote.keys)?;
+
+    // an incremental backup also needs whichever of its chunks the local store lacks
+    if remote.format == BackupFormat::Incremental {
+        let chunks_dir = backups_dir.join(CHUNKS_DIR_NAME);
+        let mut missing = Vec::new();
+        let mut seen = HashSet::new();
+        for hash in Manifest::load(&safe_join(staging_dir, &remote.name)?)?
+            .entries
+            .iter()
+            .flat_map(|entry| &entry.chunks)
+        {
+            let path = chunk_path(&chunks_dir, hash)?;
+            if seen.insert(hash) && !path.exists() {
+                let relative = path.strip_prefix(backups_dir).unwrap_or(&path);
+                missing.push(entry_name(relative));
+            }
+        }
+        fetch(&missing)?;
+        for key in &missing {
+            let target = safe_join(backups_dir, key)?;
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+            }
+            fs::rename(safe_join(staging_dir, key)?, &target)
+                .map_err(|e| format!("failed to store chunk {}: {}", key, e))?;
+        }
+    }
+
+    // the backup itself goes into place before its manifest, like when it was taken
+    let mut tops: Vec<String> = remote
+        .keys
+        .iter()
+        .map(|key| key.split('/').next().unwrap_or(key).to_string())
+        .collect();
+    tops.sort_by_key(|top| top.ends_with(MANIFEST_SUFFIX));
+    tops.dedup();
+    for top in tops {
+        fs::rename(safe_join(staging_dir, &top)?, safe_join(backups_dir, &top)?)
+            .map_err(|e| format!("failed to move {} into place: {}", top, e))?;
+    }
+    Ok(())
+}
+
+fn find_destination<'a>(
+    server: &'a Server,
+    destination: &str,
+) -> Result<&'a BackupDestination, String> {
+    server
+        .backup
+        .destinations
+        .iter()
+        .find(|d| d.name == destination)
+        .ok_or_else(|| {
+            format!(
+                "no backup destination named {} for {}",
+                destination, server.name
+            )
+        })
+}
+
+// whether a key from a destination is a plain relative path, with no empty, `.` or `..`
+// components and no backslashes
+fn is_safe_key(key: &str) -> bool {
+    !key.contains('\\')
+        && key
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+}
+
+pub fn hash_file(path: &Path) -> Result<String, String> {
+    let mut reader = BufReader::new(
+        File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?,
+    );
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// where a file is written before being renamed into place
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".partial");
+    PathBuf::from(name)
+}
@@ -0,0 +1,268 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::destinations::RemoteFile;
+
+// a directory on another machine, reached with the system's ssh and sftp clients (so keys,
+// agents and ~/.ssh/config all work as they do for the user)
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct SftpTarget {
+    pub host: String,
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    // absolute, or relative to the login directory
+    pub path: String,
+    // a private key to log in with instead of ssh's defaults
+    #[serde(default)]
+    pub identity: Option<PathBuf>,
+}
+
+impl SftpTarget {
+    // parse the part of an sftp:// URL after the scheme: [user@]host[:port]/path, where a path
+    // starting with /~/ is relative to the login directory
+    pub fn parse(rest: &str, identity: Option<PathBuf>) -> Result<Self, String> {
+        let (authority, path) = rest
+            .split_once('/')
+            .ok_or("sftp destinations need a path, e.g. sftp://user@host/srv/backups")?;
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user.to_string()), host_port),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                Some(
+                    port.parse::<u16>()
+                        .map_err(|_| format!("invalid port: {}", port))?,
+                ),
+            ),
+            None => (host_port, None),
+        };
+        if host.is_empty() {
+            return Err("sftp destinations need a host".to_string());
+        }
+        let path = match path.strip_prefix("~/") {
+            Some(relative) => relative.trim_end_matches('/').to_string(),
+            None => format!("/{}", path.trim_end_matches('/')),
+        };
+        if path.is_empty() || path == "/" {
+            return Err("sftp destinations need a directory to put backups in".to_string());
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            user,
+            port,
+            path,
+            identity,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        let mut url = "sftp://".to_string();
+        if let Some(user) = &self.user {
+            url.push_str(&format!("{}@", user));
+        }
+        url.push_str(&self.host);
+        if let Some(port) = self.port {
+            url.push_str(&format!(":{}", port));
+        }
+        match self.path.starts_with('/') {
+            true => url.push_str(&self.path),
+            false => url.push_str(&format!("/~/{}", self.path)),
+        }
+        url
+    }
+
+    fn login(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    fn remote_path(&self, key: &str) -> String {
+        format!("{}/{}", self.path, key)
+    }
+
+    fn ssh(&self, script: &str) -> Command {
+        let mut command = Command::new("ssh");
+        command.args(["-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.args(["-p", &port.to_string()]);
+        }
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+        command.arg(self.login()).arg(script);
+        command
+    }
+
+    fn sftp(&self) -> Command {
+        let mut command = Command::new("sftp");
+        // read commands from stdin and stop at the first one that fails
+        command.args(["-o", "BatchMode=yes", "-b", "-"]);
+        if let Some(port) = self.port {
+            command.args(["-P", &port.to_string()]);
+        }
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+        command.arg(self.login());
+        command
+    }
+}
+
+// every file under `prefix`, with keys relative to it
+pub fn list(target: &SftpTarget, prefix: &str) -> Result<Vec<RemoteFile>, String> {
+    let output = run(
+        target.ssh(&list_script(&target.remote_path(prefix))),
+        "",
+        "list",
+    )?;
+
+    let mut files = Vec::new();
+    for line in output.lines() {
+        let (key, size) = line
+            .rsplit_once('\t')
+            .ok_or_else(|| format!("unexpected output from {}: {}", target.host, line))?;
+        files.push(RemoteFile {
+            key: key.to_string(),
+            size: size
+                .trim()
+                .parse()
+                .map_err(|_| format!("unexpected output from {}: {}", target.host, line))?,
+        });
+    }
+    Ok(files)
+}
+
+// a script printing each file under `dir` as its relative path and size, separated by a tab;
+// GNU find prints both itself, and other finds (BSD, busybox) leave it to wc, which some pad with
+// spaces
+fn list_script(dir: &str) -> String {
+    format!(
+        concat!(
+            "if [ -d {dir} ] && cd {dir}; then ",
+            "if find . -maxdepth 0 -printf '' 2>/dev/null; then find . -type f -printf '%P\\t%s\\n'; ",
+            "else find . -type f -exec sh -c 'for f; do printf \"%s\\t%s\\n\" \"${{f#./}}\" \"$(wc -c < \"$f\")\"; done' sh {{}} +; ",
+            "fi; fi"
+        ),
+        dir = shell_quote(dir)
+    )
+}
+
+pub fn upload(target: &SftpTarget, files: &[(PathBuf, String)]) -> Result<(), String> {
+    // sftp can't create parents on its own, and a leading - lets a mkdir fail when the directory
+    // is already there
+    let mut dirs = BTreeSet::new();
+    for (_, key) in files {
+        let mut path = Path::new(&target.remote_path(key)).to_path_buf();
+        while path.pop() && !path.as_os_str().is_empty() && path != Path::new("/") {
+            dirs.insert(path.to_string_lossy().to_string());
+        }
+    }
+
+    let mut batch = String::new();
+    for dir in &dirs {
+        batch.push_str(&format!("-mkdir {}\n", sftp_quote(dir)));
+    }
+    // uploaded under a temporary name, so an interrupted upload never looks finished
+    for (local, key) in files {
+        let remote = target.remote_path(key);
+        let partial = format!("{}.partial", remote);
+        batch.push_str(&format!(
+            "put {} {}\nrename {} {}\n",
+            sftp_quote(&local.to_string_lossy()),
+            sftp_quote(&partial),
+            sftp_quote(&partial),
+            sftp_quote(&remote)
+        ));
+    }
+    run(target.sftp(), &batch, "upload to").map(|_| ())
+}
+
+pub fn download(target: &SftpTarget, files: &[(String, PathBuf)]) -> Result<(), String> {
+    let mut batch = String::new();
+    for (key, local) in files {
+        batch.push_str(&format!(
+            "get {} {}\n",
+            sftp_quote(&target.remote_path(key)),
+            sftp_quote(&local.to_string_lossy())
+        ));
+    }
+    run(target.sftp(), &batch, "download from").map(|_| ())
+}
+
+// SHA-256 of each file, hashed on the remote host so nothing has to come back over the network
+pub fn hashes(target: &SftpTarget, keys: &[String]) -> Result<HashMap<String, String>, String> {
+    // files that aren't there are simply left out of sha256sum's output
+    let script = format!(
+        "cd {} 2>/dev/null && xargs -0 sha256sum -- 2>/dev/null; exit 0",
+        shell_quote(&target.path)
+    );
+    let input: String = keys.iter().map(|key| format!("{}\0", key)).collect();
+    let output = run(target.ssh(&script), &input, "hash files on")?;
+
+    Ok(output
+        .lines()
+        .filter_map(|line| line.split_once("  "))
+        .map(|(hash, key)| (key.to_string(), hash.to_string()))
+        .collect())
+}
+
+// run ssh or sftp with `input` on stdin, returning what it printed
+fn run(mut command: Command, input: &str, action: &str) -> Result<String, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {:?}: {}", command.get_program(), e))?;
+
+    // written from another thread, so a full stdout pipe can't stall both sides
+    let mut stdin = child.stdin.take().ok_or("failed to open stdin")?;
+    let input = input.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run {:?}: {}", command.get_program(), e))?;
+    let _ = writer.join();
+
+    // the remote scripts always exit 0, so any failure is ssh's or sftp's own
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "failed to {} the remote host: {}",
+            action,
+            stderr.trim().lines().last().unwrap_or("no error output")
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// quote for the remote login shell
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+// quote for an sftp batch file, escaping glob characters as sftp expands them
+fn sftp_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '*' | '?' | '[' | ']') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
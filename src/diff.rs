@@ -0,0 +1,553 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use chrono::DateTime;
+use sha2::{Digest, Sha256};
+
+use crate::archive::{entry_name, walk_entries};
+use crate::catalog::{find_backup, visit_backup_files};
+use crate::crypto::{BackupKeys, KEY_FILE_ENV, PASSPHRASE_ENV};
+use crate::run::running_pid;
+use crate::selection::{instance_path, region_location};
+use crate::server::Server;
+use crate::world::{world_dirs, Dimension};
+
+// what `world-diff` takes to mean the instance's world as it is now
+const LIVE: &str = "live";
+// a region file starts with a table of where each of its 32x32 chunks is, then one of when each
+// was last saved, both 4 KiB of big-endian u32s
+const REGION_CHUNKS: usize = 1024;
+const SECTOR_SIZE: usize = 4096;
+// chunks along each side of a region, and blocks along each side of a chunk
+const REGION_WIDTH: i32 = 32;
+const CHUNK_WIDTH: i32 = 16;
+
+// one file on one side of a diff
+struct FileState {
+    sha256: String,
+    // for region files, each chunk slot's state, in the order of the header
+    chunks: Option<Vec<Option<ChunkState>>>,
+}
+
+#[derive(PartialEq)]
+struct ChunkState {
+    // when the game last saved the chunk, in seconds since the epoch
+    saved: u32,
+    // SHA-256 of its compressed data
+    sha256: String,
+}
+
+#[derive(serde_derive::Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+    // saved again with exactly the same contents
+    Resaved,
+}
+
+impl Change {
+    fn name(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Changed => "changed",
+            Change::Resaved => "resaved",
+        }
+    }
+}
+
+#[derive(serde_derive::Serialize)]
+struct ChunkChange {
+    dimension: Dimension,
+    // region, entities or poi
+    folder: String,
+    // chunk coordinates; the chunk covers blocks x*16 to x*16+15 and likewise for z
+    x: i32,
+    z: i32,
+    change: Change,
+    // when the chunk was saved on the newer side, or on the older one if it was removed
+    #[serde(serialize_with = "serialize_saved")]
+    saved: u32,
+}
+
+#[derive(serde_derive::Serialize)]
+struct DiffReport {
+    from: String,
+    to: String,
+    added: Vec<String>,
+    removed: Vec<String>,
+    changed: Vec<String>,
+    unchanged: usize,
+    chunks: Vec<ChunkChange>,
+}
+
+// `world-diff`: the files added, removed and changed between a backup and another backup or the
+// live world, and for region files, which chunks; only the world folders are compared
+pub fn diff_world(
+    name: &String,
+    backup: &Path,
+    other: &Path,
+    verbose: u8,
+    json: bool,
+    key_file: Option<&Path>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+    let keys = BackupKeys::new(&server, key_file);
+    let world_dirs = world_dirs(&server, &server_dir)?;
+
+    let (from, before) = load_backup(name, backup, &keys, &world_dirs)?;
+    let (to, after) = match other == Path::new(LIVE) {
+        false => load_backup(name, other, &keys, &world_dirs)?,
+        true => {
+            if !json && running_pid(&server_dir).is_some() {
+                println!(
+                    "[slapaman] note: {} is running, so changes it hasn't saved yet won't show up",
+                    name
+                );
+            }
+            (
+                "the live world".to_string(),
+                load_live(&server_dir, &world_dirs)?,
+            )
+        }
+    };
+    let report = compare(from, to, &before, &after, &world_dirs);
+
+    if json {
+        let json = serde_json::to_string_pretty(&report)
+            .map_err(|e| format!("failed to serialize diff: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!("comparing {} with {}", report.from, report.to);
+    println!(
+        "files: {} added, {} removed, {} changed, {} unchanged",
+        report.added.len(),
+        report.removed.len(),
+        report.changed.len(),
+        report.unchanged
+    );
+    for (change, paths) in [
+        (Change::Added, &report.added),
+        (Change::Removed, &report.removed),
+        (Change::Changed, &report.changed),
+    ] {
+        for path in paths {
+            println!("  {:<8} {}", change.name(), path);
+        }
+    }
+    if report.chunks.is_empty() {
+        return Ok(());
+    }
+
+    println!("chunks:");
+    let mut groups: BTreeMap<(Dimension, &str), Vec<&ChunkChange>> = BTreeMap::new();
+    for chunk in &report.chunks {
+        groups
+            .entry((chunk.dimension, chunk.folder.as_str()))
+            .or_default()
+            .push(chunk);
+    }
+    for ((dimension, folder), chunks) in groups {
+        println!("  {} {}: {}", dimension.name(), folder, summarize(&chunks));
+        if verbose > 0 {
+            for chunk in chunks {
+                println!(
+                    "    {:<8} chunk {},{} (blocks {},{}) saved {}",
+                    chunk.change.name(),
+                    chunk.x,
+                    chunk.z,
+                    chunk.x * CHUNK_WIDTH,
+                    chunk.z * CHUNK_WIDTH,
+                    format_saved(chunk.saved)
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the counts, coordinate range and save times of one dimension folder's chunk changes; resaved
+// chunks are only counted, as nothing in them changed
+fn summarize(chunks: &[&ChunkChange]) -> String {
+    let mut counts: BTreeMap<Change, usize> = BTreeMap::new();
+    for chunk in chunks {
+        *counts.entry(chunk.change).or_default() += 1;
+    }
+    let mut summary: Vec<String> = counts
+        .iter()
+        .map(|(change, count)| format!("{} {}", count, change.name()))
+        .collect();
+
+    let significant: Vec<&&ChunkChange> = chunks
+        .iter()
+        .filter(|chunk| chunk.change != Change::Resaved)
+        .collect();
+    if let (Some(min_x), Some(max_x), Some(min_z), Some(max_z), Some(first), Some(last)) = (
+        significant.iter().map(|c| c.x).min(),
+        significant.iter().map(|c| c.x).max(),
+        significant.iter().map(|c| c.z).min(),
+        significant.iter().map(|c| c.z).max(),
+        significant.iter().map(|c| c.saved).min(),
+        significant.iter().map(|c| c.saved).max(),
+    ) {
+        summary.push(format!(
+            "chunks x {}..{}, z {}..{} (blocks x {}..{}, z {}..{})",
+            min_x,
+            max_x,
+            min_z,
+            max_z,
+            min_x * CHUNK_WIDTH,
+            (max_x + 1) * CHUNK_WIDTH - 1,
+            min_z * CHUNK_WIDTH,
+            (max_z + 1) * CHUNK_WIDTH - 1
+        ));
+        summary.push(match first == last {
+            true => format!("saved {}", format_saved(first)),
+            false => format!("saved {} to {}", format_saved(first), format_saved(last)),
+        });
+    }
+    summary.join(", ")
+}
+
+fn compare(
+    from: String,
+    to: String,
+    before: &BTreeMap<String, FileState>,
+    after: &BTreeMap<String, FileState>,
+    world_dirs: &[String],
+) -> DiffReport {
+    let mut report = DiffReport {
+        from,
+        to,
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+        chunks: Vec::new(),
+    };
+
+    let mut paths: Vec<&String> = before.keys().chain(after.keys()).collect();
+    paths.sort();
+    paths.dedup();
+    for path in paths {
+        let (old, new) = (before.get(path), after.get(path));
+        match (old, new) {
+            (None, Some(_)) => report.added.push(path.clone()),
+            (Some(_), None) => report.removed.push(path.clone()),
+            (Some(old), Some(new)) if old.sha256 == new.sha256 => {
+                report.unchanged += 1;
+                continue;
+            }
+            (Some(_), Some(_)) => report.changed.push(path.clone()),
+            (None, None) => continue,
+        }
+
+        let Some((dimension, folder, region_x, region_z)) = region_location(path, world_dirs)
+        else {
+            continue;
+        };
+        // a region named with coordinates too large for its blocks to have any can't be placed
+        let (Some(origin_x), Some(origin_z)) = (region_origin(region_x), region_origin(region_z))
+        else {
+            continue;
+        };
+        let no_chunks = Vec::new();
+        let old_chunks = old.and_then(|f| f.chunks.as_ref()).unwrap_or(&no_chunks);
+        let new_chunks = new.and_then(|f| f.chunks.as_ref()).unwrap_or(&no_chunks);
+        for i in 0..REGION_CHUNKS {
+            let old = old_chunks.get(i).and_then(|c| c.as_ref());
+            let new = new_chunks.get(i).and_then(|c| c.as_ref());
+            let (change, saved) = match (old, new) {
+                (None, Some(new)) => (Change::Added, new.saved),
+                (Some(old), None) => (Change::Removed, old.saved),
+                (Some(old), Some(new)) if old == new => continue,
+                (Some(old), Some(new)) if old.sha256 == new.sha256 => (Change::Resaved, new.saved),
+                (Some(_), Some(new)) => (Change::Changed, new.saved),
+                (None, None) => continue,
+            };
+            report.chunks.push(ChunkChange {
+                dimension,
+                folder: folder.clone(),
+                x: origin_x + (i as i32 % REGION_WIDTH),
+                z: origin_z + (i as i32 / REGION_WIDTH),
+                change,
+                saved,
+            });
+        }
+    }
+
+    report
+}
+
+// the first chunk coordinate of a region, if every block in it has a coordinate that fits in an
+// i32
+fn region_origin(region: i32) -> Option<i32> {
+    let blocks = REGION_WIDTH * CHUNK_WIDTH;
+    region.checked_add(1)?.checked_mul(blocks)?;
+    region.checked_mul(blocks).map(|_| region * REGION_WIDTH)
+}
+
+// the world files in a backup, by their path relative to the instance directory
+fn load_backup(
+    name: &String,
+    backup: &Path,
+    keys: &BackupKeys,
+    world_dirs: &[String],
+) -> Result<(String, BTreeMap<String, FileState>), String> {
+    let info = find_backup(name, backup)?;
+    if !keys.available(&info.path) {
+        return Err(format!(
+            "{} is encrypted; give its key with --key-file, ${} or ${}",
+            info.id, KEY_FILE_ENV, PASSPHRASE_ENV
+        ));
+    }
+
+    let mut files = BTreeMap::new();
+    visit_backup_files(&info.path, keys, |name, reader| {
+        let path = instance_path(name, world_dirs);
+        if !in_world(&path, world_dirs) {
+            return Ok(());
+        }
+        let state = read_state(&path, reader, world_dirs)
+            .map_err(|e| format!("failed to read {} from {}: {}", name, info.id, e))?;
+        files.insert(path, state);
+        Ok(())
+    })?;
+
+    let label = format!(
+        "backup {} ({}, taken {} UTC)",
+        info.index,
+        info.id,
+        info.taken.format("%Y-%m-%d %H:%M:%S")
+    );
+    Ok((label, files))
+}
+
+// the files in the instance's world folders as they are on disk
+fn load_live(
+    server_dir: &Path,
+    world_dirs: &[String],
+) -> Result<BTreeMap<String, FileState>, String> {
+    let existing: Vec<String> = world_dirs
+        .iter()
+        .filter(|dir| server_dir.join(dir).is_dir())
+        .cloned()
+        .collect();
+
+    let mut files = BTreeMap::new();
+    for (file, relative) in walk_entries(server_dir, &existing)? {
+        if file.is_symlink() || !file.is_file() {
+            continue;
+        }
+        let path = entry_name(&relative);
+        let state = File::open(&file)
+            .and_then(|mut reader| read_state(&path, &mut reader, world_dirs))
+            .map_err(|e| format!("failed to read {}: {}", file.display(), e))?;
+        files.insert(path, state);
+    }
+    Ok(files)
+}
+
+fn in_world(path: &str, world_dirs: &[String]) -> bool {
+    path.split('/')
+        .next()
+        .is_some_and(|first| world_dirs.iter().any(|dir| dir == first))
+}
+
+// hash a file, and each of its chunks if it's a region file
+fn read_state(path: &str, reader: &mut dyn Read, world_dirs: &[String]) -> io::Result<FileState> {
+    if region_location(path, world_dirs).is_none() {
+        let mut hasher = Sha256::new();
+        io::copy(reader, &mut hasher)?;
+        return Ok(FileState {
+            sha256: format!("{:x}", hasher.finalize()),
+            chunks: None,
+        });
+    }
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    Ok(FileState {
+        sha256: format!("{:x}", Sha256::digest(&data)),
+        chunks: Some(read_chunks(&data)),
+    })
+}
+
+// each chunk slot of an Anvil region file: where its data is is in the location table (a 3-byte
+// sector offset and a 1-byte sector count, zero when the chunk hasn't been generated), and its
+// data starts with a 4-byte length; files too short for the header have no chunks, and chunks
+// pointing past the end of the file are hashed as far as they go; slots whose length can't even be
+// added up are left out
+fn read_chunks(data: &[u8]) -> Vec<Option<ChunkState>> {
+    if data.len() < 2 * SECTOR_SIZE {
+        return Vec::new();
+    }
+    (0..REGION_CHUNKS)
+        .map(|i| {
+            let location = read_u32(data, i * 4)?;
+            let (offset, sectors) = ((location >> 8) as usize, location & 0xff);
+            if offset == 0 || sectors == 0 {
+                return None;
+            }
+            let start = (offset * SECTOR_SIZE).min(data.len());
+            let length = read_u32(data, start).unwrap_or_default() as usize;
+            let end = start.checked_add(4)?.checked_add(length)?;
+            let payload = &data[(start + 4).min(data.len())..end.min(data.len())];
+            Some(ChunkState {
+                saved: read_u32(data, SECTOR_SIZE + i * 4)?,
+                sha256: format!("{:x}", Sha256::digest(payload)),
+            })
+        })
+        .collect()
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+    data.get(at..at.checked_add(4)?)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn format_saved(saved: u32) -> String {
+    DateTime::from_timestamp(saved as i64, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_else(|| "at an unknown time".to_string())
+}
+
+fn serialize_saved<S: serde::Serializer>(saved: &u32, serializer: S) -> Result<S::Ok, S::Error> {
+    match DateTime::from_timestamp(*saved as i64, 0) {
+        Some(time) => serializer.serialize_str(&time.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_dirs() -> Vec<String> {
+        ["world", "world_nether", "world_the_end"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    // a region file holding each (slot, saved, payload) one sector apiece, after the header
+    fn region(chunks: &[(usize, u32, &[u8])]) -> Vec<u8> {
+        let mut data = vec![0u8; 2 * SECTOR_SIZE];
+        for &(slot, saved, payload) in chunks {
+            let offset = data.len() / SECTOR_SIZE;
+            data[slot * 4..slot * 4 + 4].copy_from_slice(&((offset as u32) << 8 | 1).to_be_bytes());
+            data[SECTOR_SIZE + slot * 4..SECTOR_SIZE + slot * 4 + 4]
+                .copy_from_slice(&saved.to_be_bytes());
+            let mut sector = (payload.len() as u32).to_be_bytes().to_vec();
+            sector.extend_from_slice(payload);
+            sector.resize(SECTOR_SIZE, 0);
+            data.extend_from_slice(&sector);
+        }
+        data
+    }
+
+    fn state(data: &[u8]) -> FileState {
+        FileState {
+            sha256: format!("{:x}", Sha256::digest(data)),
+            chunks: Some(read_chunks(data)),
+        }
+    }
+
+    #[test]
+    fn read_chunks_hashes_each_slot_payload() {
+        let chunks = read_chunks(&region(&[(0, 100, b"first"), (1023, 200, b"last")]));
+        assert_eq!(chunks.len(), REGION_CHUNKS);
+        assert_eq!(chunks.iter().filter(|c| c.is_some()).count(), 2);
+        let first = chunks[0].as_ref().unwrap();
+        assert_eq!(first.saved, 100);
+        assert_eq!(first.sha256, format!("{:x}", Sha256::digest(b"first")));
+        assert_eq!(chunks[1023].as_ref().unwrap().saved, 200);
+
+        assert!(read_chunks(&[0u8; SECTOR_SIZE]).is_empty());
+    }
+
+    #[test]
+    fn read_chunks_survives_slots_pointing_anywhere() {
+        let mut data = region(&[(0, 1, b"payload")]);
+        // a length running past the end of the file, and a slot past the end of the file
+        data[2 * SECTOR_SIZE..2 * SECTOR_SIZE + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        data[4..8].copy_from_slice(&0xffff_ff01u32.to_be_bytes());
+        let chunks = read_chunks(&data);
+        let truncated = &data[2 * SECTOR_SIZE + 4..];
+        assert_eq!(
+            chunks[0].as_ref().unwrap().sha256,
+            format!("{:x}", Sha256::digest(truncated))
+        );
+        assert_eq!(
+            chunks[1].as_ref().unwrap().sha256,
+            format!("{:x}", Sha256::digest(b""))
+        );
+    }
+
+    #[test]
+    fn compare_reports_each_kind_of_chunk_change() {
+        let path = "world/region/r.-1.2.mca".to_string();
+        let before = region(&[
+            (1, 10, b"removed"),
+            (2, 10, b"changed"),
+            (33, 10, b"resaved"),
+            (34, 10, b"untouched"),
+        ]);
+        let after = region(&[
+            (0, 20, b"added"),
+            (2, 20, b"changed again"),
+            (33, 20, b"resaved"),
+            (34, 10, b"untouched"),
+        ]);
+        let report = compare(
+            "before".to_string(),
+            "after".to_string(),
+            &BTreeMap::from([(path.clone(), state(&before))]),
+            &BTreeMap::from([(path.clone(), state(&after))]),
+            &world_dirs(),
+        );
+        assert_eq!(report.changed, vec![path]);
+        let chunks: Vec<(i32, i32, &str, u32)> = report
+            .chunks
+            .iter()
+            .map(|c| (c.x, c.z, c.change.name(), c.saved))
+            .collect();
+        // region -1,2 starts at chunk -32,64 and slots run along x first
+        assert_eq!(
+            chunks,
+            vec![
+                (-32, 64, "added", 20),
+                (-31, 64, "removed", 10),
+                (-30, 64, "changed", 20),
+                (-31, 65, "resaved", 20),
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_skips_regions_beyond_the_coordinate_range() {
+        let path = format!("world/region/r.{}.0.mca", i32::MAX);
+        let report = compare(
+            "before".to_string(),
+            "after".to_string(),
+            &BTreeMap::new(),
+            &BTreeMap::from([(path.clone(), state(&region(&[(0, 1, b"x")])))]),
+            &world_dirs(),
+        );
+        assert_eq!(report.added, vec![path]);
+        assert!(report.chunks.is_empty());
+        assert_eq!(region_origin(-1), Some(-32));
+        assert_eq!(region_origin(i32::MIN / 512), Some(i32::MIN / 16));
+        assert_eq!(region_origin(i32::MIN / 512 - 1), None);
+    }
+}
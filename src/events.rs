@@ -0,0 +1,405 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+// something noteworthy the server reported in its console output
+#[derive(serde_derive::Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Ready {
+        startup_secs: f64,
+    },
+    PlayerJoin {
+        player: String,
+        uuid: Option<String>,
+    },
+    PlayerLeave {
+        player: String,
+        reason: Option<String>,
+    },
+    Chat {
+        player: String,
+        message: String,
+    },
+    Death {
+        player: String,
+        message: String,
+    },
+    Advancement {
+        player: String,
+        kind: String,
+        advancement: String,
+    },
+    LagWarning {
+        behind_ms: u64,
+        ticks: u64,
+    },
+    PortBindFailed {
+        message: String,
+    },
+    EulaNotAccepted,
+    Saved,
+    Stopping,
+}
+
+impl ServerEvent {
+    // failures that mean the server is never going to finish starting
+    pub fn is_startup_failure(&self) -> bool {
+        matches!(
+            self,
+            ServerEvent::PortBindFailed { .. } | ServerEvent::EulaNotAccepted
+        )
+    }
+}
+
+// turns console lines into events; it keeps a little state between lines because some events
+// (the UUID of a joining player, the reason a player left) are spread across several of them
+pub struct EventParser {
+    header_re: Regex,
+    ansi_re: Regex,
+    ready_re: Regex,
+    uuid_re: Regex,
+    join_re: Regex,
+    left_re: Regex,
+    lost_connection_re: Regex,
+    chat_re: Regex,
+    advancement_re: Regex,
+    lag_re: Regex,
+    death_re: Regex,
+    online: HashSet<String>,
+    pending_uuids: HashMap<String, String>,
+    pending_reasons: HashMap<String, String>,
+}
+
+impl Default for EventParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self {
+            // vanilla: [12:00:00] [Server thread/INFO]: message
+            // paper:   [12:00:00 INFO]: message
+            // fabric:  [12:00:00] [Server thread/INFO] (Minecraft) message
+            header_re: Regex::new(
+                r"^\[\d{2}:\d{2}:\d{2}(?: [A-Z]+)?\](?: \[[^\]]+/[A-Z]+\])?(?::| \([^)]*\)) ?(.*)$",
+            )
+            .unwrap(),
+            ansi_re: Regex::new(r"\x1b\[[0-9;]*m").unwrap(),
+            ready_re: Regex::new(r"^Done \((\d+(?:[.,]\d+)?)s\)!").unwrap(),
+            uuid_re: Regex::new(r"^UUID of player (\S+) is ([0-9a-fA-F-]{32,36})").unwrap(),
+            join_re: Regex::new(r"^(\S+) joined the game$").unwrap(),
+            left_re: Regex::new(r"^(\S+) left the game$").unwrap(),
+            lost_connection_re: Regex::new(r"^(\S+) lost connection: (.*)$").unwrap(),
+            chat_re: Regex::new(r"^(?:\[Not Secure\] )?<([^>]+)> (.*)$").unwrap(),
+            advancement_re: Regex::new(
+                r"^(\S+) has (made the advancement|completed the challenge|reached the goal) \[(.+)\]$",
+            )
+            .unwrap(),
+            lag_re: Regex::new(
+                r"^Can't keep up! Is the server overloaded\? Running (\d+)ms or (\d+) ticks behind",
+            )
+            .unwrap(),
+            death_re: Regex::new(
+                r"^(?:was |were |fell |drowned|died|blew up|burned|hit the ground|starved|suffocated|withered away|froze to death|went up in flames|went off with a bang|walked into|tried to swim in lava|experienced kinetic energy|discovered the floor was lava|didn't want to live|left the confines|got finished off)",
+            )
+            .unwrap(),
+            online: HashSet::new(),
+            pending_uuids: HashMap::new(),
+            pending_reasons: HashMap::new(),
+        }
+    }
+
+    pub fn parse(&mut self, line: &str) -> Option<ServerEvent> {
+        let line = self.ansi_re.replace_all(line, "");
+        let message = match self.header_re.captures(&line) {
+            Some(caps) => caps.get(1).map_or("", |m| m.as_str()).to_string(),
+            // startup failures can be printed before logging is set up
+            None => line.trim().to_string(),
+        };
+
+        // startup failures
+        if message.to_lowercase().contains("failed to bind to port") {
+            return Some(ServerEvent::PortBindFailed { message });
+        }
+        if message.contains("You need to agree to the EULA") {
+            return Some(ServerEvent::EulaNotAccepted);
+        }
+
+        // lifecycle
+        if let Some(caps) = self.ready_re.captures(&message) {
+            let startup_secs = caps[1].replace(',', ".").parse::<f64>().unwrap_or(0.0);
+            return Some(ServerEvent::Ready { startup_secs });
+        }
+        if message == "Saved the game" {
+            return Some(ServerEvent::Saved);
+        }
+        if message == "Stopping server" || message == "Stopping the server" {
+            self.online.clear();
+            return Some(ServerEvent::Stopping);
+        }
+        if let Some(caps) = self.lag_re.captures(&message) {
+            return Some(ServerEvent::LagWarning {
+                behind_ms: caps[1].parse().unwrap_or(0),
+                ticks: caps[2].parse().unwrap_or(0),
+            });
+        }
+
+        // players
+        if let Some(caps) = self.uuid_re.captures(&message) {
+            self.pending_uuids
+                .insert(caps[1].to_string(), caps[2].to_lowercase());
+            return None;
+        }
+        if let Some(caps) = self.join_re.captures(&message) {
+            let player = caps[1].to_string();
+            let uuid = self.pending_uuids.remove(&player);
+            self.online.insert(player.clone());
+            return Some(ServerEvent::PlayerJoin { player, uuid });
+        }
+        if let Some(caps) = self.lost_connection_re.captures(&message) {
+            if self.online.contains(&caps[1]) {
+                self.pending_reasons
+                    .insert(caps[1].to_string(), caps[2].to_string());
+            }
+            return None;
+        }
+        if let Some(caps) = self.left_re.captures(&message) {
+            let player = caps[1].to_string();
+            let reason = self.pending_reasons.remove(&player);
+            self.online.remove(&player);
+            return Some(ServerEvent::PlayerLeave { player, reason });
+        }
+        if let Some(caps) = self.chat_re.captures(&message) {
+            return Some(ServerEvent::Chat {
+                player: caps[1].to_string(),
+                message: caps[2].to_string(),
+            });
+        }
+        if let Some(caps) = self.advancement_re.captures(&message) {
+            let kind = match &caps[2] {
+                "completed the challenge" => "challenge",
+                "reached the goal" => "goal",
+                _ => "advancement",
+            };
+            return Some(ServerEvent::Advancement {
+                player: caps[1].to_string(),
+                kind: kind.to_string(),
+                advancement: caps[3].to_string(),
+            });
+        }
+
+        // death messages have no fixed shape, so only trust them for players we know are online
+        if let Some((player, rest)) = message.split_once(' ') {
+            if self.online.contains(player) && self.death_re.is_match(rest) {
+                return Some(ServerEvent::Death {
+                    player: player.to_string(),
+                    message: message.clone(),
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+
+    // the same message as vanilla, Paper and Fabric log it
+    fn in_every_format(thread: &str, level: &str, message: &str) -> [String; 3] {
+        [
+            format!("[14:02:33] [{}/{}]: {}", thread, level, message),
+            format!("[14:02:33 {}]: {}", level, message),
+            format!("[14:02:33] [{}/{}] (Minecraft) {}", thread, level, message),
+        ]
+    }
+
+    fn parse_each(thread: &str, level: &str, message: &str) -> Vec<Option<ServerEvent>> {
+        in_every_format(thread, level, message)
+            .iter()
+            .map(|line| EventParser::new().parse(line))
+            .collect()
+    }
+
+    fn assert_each(thread: &str, level: &str, message: &str, expected: ServerEvent) {
+        for (line, event) in in_every_format(thread, level, message)
+            .iter()
+            .zip(parse_each(thread, level, message))
+        {
+            assert_eq!(event.as_ref(), Some(&expected), "{}", line);
+        }
+    }
+
+    // a parser that has seen Steve join
+    fn with_steve_online(line: &str) -> EventParser {
+        let mut parser = EventParser::new();
+        let join = line.replace("{}", "Steve joined the game");
+        assert!(parser.parse(&join).is_some());
+        parser
+    }
+
+    #[test]
+    fn lifecycle_events() {
+        assert_each(
+            "Server thread",
+            "INFO",
+            r#"Done (12.345s)! For help, type "help""#,
+            ServerEvent::Ready {
+                startup_secs: 12.345,
+            },
+        );
+        assert_each(
+            "Server thread",
+            "INFO",
+            "Saved the game",
+            ServerEvent::Saved,
+        );
+        assert_each(
+            "Server thread",
+            "INFO",
+            "Stopping server",
+            ServerEvent::Stopping,
+        );
+        assert_each(
+            "Server thread",
+            "WARN",
+            "Can't keep up! Is the server overloaded? Running 2503ms or 50 ticks behind",
+            ServerEvent::LagWarning {
+                behind_ms: 2503,
+                ticks: 50,
+            },
+        );
+        // the save command's first line is not the save finishing
+        assert!(parse_each(
+            "Server thread",
+            "INFO",
+            "Saving the game (this may take a moment!)"
+        )
+        .iter()
+        .all(Option::is_none));
+    }
+
+    #[test]
+    fn startup_failures() {
+        assert_each(
+            "Server thread",
+            "WARN",
+            "**** FAILED TO BIND TO PORT!",
+            ServerEvent::PortBindFailed {
+                message: "**** FAILED TO BIND TO PORT!".to_string(),
+            },
+        );
+        let eula = "You need to agree to the EULA in order to run the server. Go to eula.txt for more info.";
+        assert_each("ServerMain", "INFO", eula, ServerEvent::EulaNotAccepted);
+        assert!(EventParser::new()
+            .parse(eula)
+            .is_some_and(|event| event.is_startup_failure()));
+    }
+
+    #[test]
+    fn joins_carry_the_uuid_logged_before_them() {
+        for (uuid_line, join_line) in in_every_format(
+            "User Authenticator #1",
+            "INFO",
+            &format!("UUID of player Steve is {}", UUID),
+        )
+        .iter()
+        .zip(in_every_format(
+            "Server thread",
+            "INFO",
+            "Steve joined the game",
+        )) {
+            let mut parser = EventParser::new();
+            assert_eq!(parser.parse(uuid_line), None);
+            assert_eq!(
+                parser.parse(&join_line),
+                Some(ServerEvent::PlayerJoin {
+                    player: "Steve".to_string(),
+                    uuid: Some(UUID.to_string()),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn leaves_carry_the_reason_logged_before_them() {
+        for line in in_every_format("Server thread", "INFO", "{}") {
+            let mut parser = with_steve_online(&line);
+            let lost = line.replace("{}", "Steve lost connection: Disconnected");
+            assert_eq!(parser.parse(&lost), None);
+            assert_eq!(
+                parser.parse(&line.replace("{}", "Steve left the game")),
+                Some(ServerEvent::PlayerLeave {
+                    player: "Steve".to_string(),
+                    reason: Some("Disconnected".to_string()),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn chat_with_and_without_signatures() {
+        for message in ["<Steve> hello there", "[Not Secure] <Steve> hello there"] {
+            assert_each(
+                "Server thread",
+                "INFO",
+                message,
+                ServerEvent::Chat {
+                    player: "Steve".to_string(),
+                    message: "hello there".to_string(),
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn advancements_of_each_kind() {
+        for (verb, kind) in [
+            ("made the advancement", "advancement"),
+            ("completed the challenge", "challenge"),
+            ("reached the goal", "goal"),
+        ] {
+            assert_each(
+                "Server thread",
+                "INFO",
+                &format!("Steve has {} [Monster Hunter]", verb),
+                ServerEvent::Advancement {
+                    player: "Steve".to_string(),
+                    kind: kind.to_string(),
+                    advancement: "Monster Hunter".to_string(),
+                },
+            );
+        }
+    }
+
+    #[test]
+    fn deaths_only_of_players_online() {
+        for line in in_every_format("Server thread", "INFO", "{}") {
+            let death = line.replace("{}", "Steve was slain by Zombie");
+            assert_eq!(EventParser::new().parse(&death), None);
+            assert_eq!(
+                with_steve_online(&line).parse(&death),
+                Some(ServerEvent::Death {
+                    player: "Steve".to_string(),
+                    message: "Steve was slain by Zombie".to_string(),
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn colour_codes_are_ignored() {
+        assert_eq!(
+            EventParser::new().parse("\x1b[0;32m[14:02:33 INFO]: Saved the game\x1b[m"),
+            Some(ServerEvent::Saved)
+        );
+    }
+}
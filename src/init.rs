@@ -12,11 +12,18 @@ use std::{
     process::Command,
 };
 
+use crate::java::installed_jdks;
+
 pub fn slapaman_init() -> Result<(), String> {
     ensure_slapaman_dir_exists().unwrap();
-    ensure_java_is_installed().unwrap();
     ensure_slapaman_server_list_exists().unwrap();
 
+    // a missing java shouldn't stop `slapaman java install` from fixing it
+    if let Err(e) = ensure_java_is_installed() {
+        println!("{}", e);
+        println!("[slapaman] run `slapaman java install <major>` to install a managed JDK");
+    }
+
     Ok(())
 }
 
@@ -42,7 +49,13 @@ fn ensure_slapaman_server_list_exists() -> Result<PathBuf> {
 
 fn ensure_java_is_installed() -> Result<(), String> {
     let min_version = 8;
-    let java_bin = locate_java(min_version).unwrap();
+
+    // instances can be pinned to a managed JDK, which is just as good as a system one
+    if !installed_jdks()?.is_empty() {
+        return Ok(());
+    }
+
+    let java_bin = locate_java(min_version).map_err(|e| e.to_string())?;
 
     if !java_bin.exists() {
         return Err(format!(
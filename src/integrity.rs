@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use age::Encryptor;
+use chrono::{SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{entry_name, walk_entries};
+use crate::backup::BACKUPS_DIR_NAME;
+use crate::catalog::{check_backup, list_backups, resolve_backup, visit_backup_files};
+use crate::crypto::{BackupKeys, BackupWriter};
+use crate::server::Server;
+
+// written next to each backup, e.g. world-20250101-120000.tar.gz.manifest.json
+pub const MANIFEST_SUFFIX: &str = ".manifest.json";
+
+// what a backup should contain, recorded when it's taken
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct IntegrityManifest {
+    pub slapaman_version: String,
+    pub server_version: String,
+    pub flavor: String,
+    pub created: String,
+    pub files: Vec<FileDigest>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct FileDigest {
+    // relative to the instance directory, with forward slashes
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+// how a backup compares to its manifest
+#[derive(serde_derive::Serialize, Clone, Debug, Default)]
+pub struct Verification {
+    pub files: usize,
+    pub missing: Vec<String>,
+    pub corrupted: Vec<String>,
+    pub extra: Vec<String>,
+    // backups from before manifests were written can only be checked for readability
+    pub has_manifest: bool,
+    // set when the backup couldn't be read to the end at all
+    pub error: Option<String>,
+}
+
+impl Verification {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+            && self.missing.is_empty()
+            && self.corrupted.is_empty()
+            && self.extra.is_empty()
+    }
+
+    pub fn describe(&self) -> String {
+        if let Some(error) = &self.error {
+            return error.clone();
+        }
+        let mut problems = Vec::new();
+        if !self.missing.is_empty() {
+            problems.push(format!("{} missing", self.missing.len()));
+        }
+        if !self.corrupted.is_empty() {
+            problems.push(format!("{} corrupted", self.corrupted.len()));
+        }
+        if !self.extra.is_empty() {
+            problems.push(format!("{} extra", self.extra.len()));
+        }
+        match (problems.is_empty(), self.has_manifest) {
+            (true, true) => format!("all {} files match the manifest", self.files),
+            (true, false) => "readable (no manifest to check against)".to_string(),
+            (false, _) => problems.join(", "),
+        }
+    }
+}
+
+pub fn manifest_path(backup: &Path) -> PathBuf {
+    let mut name = backup.as_os_str().to_os_string();
+    name.push(MANIFEST_SUFFIX);
+    PathBuf::from(name)
+}
+
+// hash `entries` (paths relative to `root`) as they are now and write the manifest for the backup
+// at `backup`, encrypted like the backup is since it lists every file in it
+pub fn write_manifest(
+    server: &Server,
+    root: &Path,
+    entries: &[String],
+    backup: &Path,
+    encryptor: Option<Encryptor>,
+) -> Result<(), String> {
+    let mut files = Vec::new();
+    for (path, relative) in walk_entries(root, entries)? {
+        if path.is_symlink() || !path.is_file() {
+            continue;
+        }
+        let mut reader = BufReader::new(
+            File::open(&path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?,
+        );
+        let (size, sha256) =
+            hash(&mut reader).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        files.push(FileDigest {
+            path: entry_name(&relative),
+            size,
+            sha256,
+        });
+    }
+
+    let manifest = IntegrityManifest {
+        slapaman_version: env!("CARGO_PKG_VERSION").to_string(),
+        server_version: server.version.clone(),
+        flavor: server.flavor.clone(),
+        created: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        files,
+    };
+
+    let path = manifest_path(backup);
+    let temp_path = path.with_extension("json.tmp");
+    let mut writer = BackupWriter::create(&temp_path, encryptor)?;
+    serde_json::to_writer_pretty(&mut writer, &manifest)
+        .map_err(|e| format!("failed to write backup manifest: {}", e))?;
+    writer
+        .finish()
+        .map_err(|e| format!("failed to write backup manifest: {}", e))?;
+    fs::rename(&temp_path, &path).map_err(|e| format!("failed to save backup manifest: {}", e))
+}
+
+pub fn load_manifest(
+    backup: &Path,
+    keys: &BackupKeys,
+) -> Result<Option<IntegrityManifest>, String> {
+    let path = manifest_path(backup);
+    if !path.exists() {
+        return Ok(None);
+    }
+    serde_json::from_reader(keys.open(&path)?)
+        .map(Some)
+        .map_err(|e| format!("failed to parse {}: {}", path.display(), e))
+}
+
+// re-hash everything in a backup and compare it to the backup's manifest; encrypted backups are
+// decrypted as they're read, so nothing decrypted is written to disk
+pub fn verify_backup(backup: &Path, keys: &BackupKeys) -> Verification {
+    let manifest = match load_manifest(backup, keys) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => {
+            return Verification {
+                error: check_backup(backup, keys).err(),
+                ..Default::default()
+            }
+        }
+        Err(e) => {
+            return Verification {
+                has_manifest: true,
+                error: Some(e),
+                ..Default::default()
+            }
+        }
+    };
+
+    let mut expected: HashMap<&str, &FileDigest> = manifest
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+    let mut verification = Verification {
+        has_manifest: true,
+        ..Default::default()
+    };
+    let result = visit_backup_files(backup, keys, |name, reader| {
+        verification.files += 1;
+        let (size, sha256) =
+            hash(reader).map_err(|e| format!("failed to read {} from the backup: {}", name, e))?;
+        match expected.remove(name) {
+            Some(file) if file.size == size && file.sha256 == sha256 => (),
+            Some(_) => verification.corrupted.push(name.to_string()),
+            None => verification.extra.push(name.to_string()),
+        }
+        Ok(())
+    });
+    verification.error = result.err();
+
+    let mut missing: Vec<String> = expected.into_keys().map(|name| name.to_string()).collect();
+    missing.sort();
+    verification.missing = missing;
+    verification
+}
+
+// `world-backup-verify`: check one backup (by default the newest) or all of them
+pub fn verify_world_backups(
+    name: &String,
+    backup: Option<&Path>,
+    all: bool,
+    key_file: Option<&Path>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let keys = BackupKeys::new(&server, key_file);
+    let backups_dir = server.path.join(name).join(BACKUPS_DIR_NAME);
+    let paths = match (all, backup) {
+        (true, _) => list_backups(&backups_dir)?
+            .into_iter()
+            .map(|backup| backup.path)
+            .collect(),
+        (false, backup) => vec![resolve_backup(
+            &backups_dir,
+            backup.unwrap_or(Path::new("latest")),
+        )?],
+    };
+
+    if paths.is_empty() {
+        println!("no backups to verify for {}", name);
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for path in &paths {
+        let file_name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let verification = verify_backup(path, &keys);
+        match verification.passed() {
+            true => println!("ok      {}: {}", file_name, verification.describe()),
+            false => {
+                failed += 1;
+                println!("FAILED  {}: {}", file_name, verification.describe());
+                for (label, paths) in [
+                    ("missing", &verification.missing),
+                    ("corrupted", &verification.corrupted),
+                    ("extra", &verification.extra),
+                ] {
+                    for path in paths {
+                        println!("        {} {}", label, path);
+                    }
+                }
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!(
+            "{} of {} backups failed verification",
+            failed,
+            paths.len()
+        )),
+    }
+}
+
+fn hash(reader: &mut dyn Read) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let size = io::copy(reader, &mut hasher)?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
@@ -0,0 +1,494 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use directories::ProjectDirs;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::net::http::get_request;
+use crate::server::{get_all_servers, update_server_by_name, Server};
+
+// any Adoptium-compatible API works here, including a local mirror for offline installs
+const DEFAULT_JDK_API: &str = "https://api.adoptium.net";
+const JDK_API_ENV: &str = "SLAPAMAN_JDK_API";
+const JDKS_DIR_NAME: &str = "jdks";
+const JDK_METADATA_FILE: &str = "slapaman-jdk.json";
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize)]
+struct JdkMetadata {
+    major: u32,
+    release_name: String,
+    archive_name: String,
+    checksum: String,
+    source: String,
+}
+
+// download, verify, and extract a JDK into slapaman's data directory
+pub async fn install_jdk(verbose: u8, major: u32, api: Option<String>) -> Result<PathBuf, String> {
+    install_jdk_into(verbose, major, &jdk_api_coerced(api), &jdks_dir()).await
+}
+
+// `install_jdk` from any Adoptium-compatible `api` into any JDKs directory
+async fn install_jdk_into(
+    verbose: u8,
+    major: u32,
+    api: &str,
+    jdks_dir: &Path,
+) -> Result<PathBuf, String> {
+    println!("[slapaman] installing JDK {}", major);
+
+    let install_dir = jdks_dir.join(major.to_string());
+    if install_dir.exists() {
+        return Err(format!(
+            "JDK {} is already installed: {}",
+            major,
+            install_dir.display()
+        ));
+    }
+
+    // ask the API for the latest build of the requested major version
+    let assets_url = format!(
+        "{}/v3/assets/latest/{}/hotspot?architecture={}&image_type=jdk&os={}&vendor=eclipse",
+        api.trim_end_matches('/'),
+        major,
+        adoptium_arch()?,
+        adoptium_os()?
+    );
+    let response = get_request(&assets_url).await?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "JDK API returned {} for {}",
+            response.status(),
+            assets_url
+        ));
+    }
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("failed to read response body: {}", e))?;
+    let assets: Value =
+        serde_json::from_str(&body).map_err(|e| format!("failed to parse JDK assets: {}", e))?;
+
+    let asset = assets
+        .as_array()
+        .and_then(|a| a.first())
+        .ok_or_else(|| format!("no JDK {} build available for this platform", major))?;
+    let package = &asset["binary"]["package"];
+    let link = package["link"]
+        .as_str()
+        .ok_or("JDK asset is missing a download link")?
+        .to_string();
+    let archive_name = package["name"]
+        .as_str()
+        .ok_or("JDK asset is missing an archive name")?
+        .to_string();
+    let checksum = package["checksum"]
+        .as_str()
+        .ok_or("JDK asset is missing a checksum")?
+        .to_lowercase();
+    let release_name = asset["release_name"]
+        .as_str()
+        .unwrap_or(&archive_name)
+        .to_string();
+
+    if verbose > 0 {
+        println!("[slapaman] downloading {} from {}", archive_name, link);
+    }
+
+    // download the archive
+    let archive = get_request(&link)
+        .await?
+        .bytes()
+        .await
+        .map_err(|e| format!("failed to read JDK archive: {}", e))?;
+
+    // verify the size (when given) and checksum
+    if let Some(size) = package["size"].as_u64() {
+        if archive.len() != size as usize {
+            return Err(format!(
+                "size mismatch: got {}, expected {}",
+                archive.len(),
+                size
+            ));
+        }
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(&archive);
+    let computed_hash = format!("{:x}", hasher.finalize());
+    if computed_hash != checksum {
+        return Err(format!(
+            "checksum mismatch: got {}, expected {}",
+            computed_hash, checksum
+        ));
+    }
+
+    // extract into a scratch directory so a failed install never looks installed
+    let staging_dir = jdks_dir.join(format!(".{}.partial", major));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .map_err(|e| format!("failed to clear staging directory: {}", e))?;
+    }
+    fs::create_dir_all(&staging_dir)
+        .map_err(|e| format!("failed to create staging directory: {}", e))?;
+
+    let extracted = extract_archive(&archive_name, &archive, &staging_dir).and_then(|_| {
+        find_java_home(&staging_dir, 4)
+            .ok_or_else(|| format!("archive does not contain a JDK: {}", archive_name))
+    });
+    let java_home = match extracted {
+        Ok(java_home) => java_home,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging_dir);
+            return Err(e);
+        }
+    };
+
+    fs::rename(&java_home, &install_dir)
+        .map_err(|e| format!("failed to move JDK into place: {}", e))?;
+    let _ = fs::remove_dir_all(&staging_dir);
+
+    let metadata = JdkMetadata {
+        major,
+        release_name,
+        archive_name,
+        checksum,
+        source: link,
+    };
+    let metadata_json = serde_json::to_string_pretty(&metadata)
+        .map_err(|e| format!("failed to serialize JDK metadata: {}", e))?;
+    fs::write(install_dir.join(JDK_METADATA_FILE), metadata_json)
+        .map_err(|e| format!("failed to write JDK metadata: {}", e))?;
+
+    println!(
+        "[slapaman] installed JDK {} ({}) at {}",
+        major,
+        metadata.release_name,
+        install_dir.display()
+    );
+
+    Ok(install_dir)
+}
+
+pub fn list_jdks() -> Result<(), String> {
+    let installed = installed_jdks()?;
+    if installed.is_empty() {
+        println!("[slapaman] no JDKs installed (try `slapaman java install 21`)");
+        return Ok(());
+    }
+
+    let servers = get_all_servers()?;
+    for (major, dir) in installed {
+        let release = read_jdk_metadata(&dir)
+            .map(|m| m.release_name)
+            .unwrap_or_else(|| "unknown release".to_string());
+        let pinned = servers
+            .iter()
+            .filter(|s| s.java == Some(major))
+            .map(|s| s.name.clone())
+            .collect::<Vec<String>>();
+
+        if pinned.is_empty() {
+            println!("{}: {} ({})", major, release, dir.display());
+        } else {
+            println!(
+                "{}: {} ({}) [used by: {}]",
+                major,
+                release,
+                dir.display(),
+                pinned.join(", ")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn remove_jdk(major: u32) -> Result<(), String> {
+    let install_dir = jdks_dir().join(major.to_string());
+    if !install_dir.exists() {
+        return Err(format!("JDK {} is not installed", major));
+    }
+
+    // refuse to pull the rug out from under a pinned instance
+    let pinned = get_all_servers()?
+        .into_iter()
+        .filter(|s| s.java == Some(major))
+        .map(|s| s.name)
+        .collect::<Vec<String>>();
+    if !pinned.is_empty() {
+        return Err(format!(
+            "JDK {} is pinned by: {} (unpin them first)",
+            major,
+            pinned.join(", ")
+        ));
+    }
+
+    fs::remove_dir_all(&install_dir).map_err(|e| format!("failed to remove JDK: {}", e))?;
+
+    Ok(())
+}
+
+// pin an instance to a managed JDK, or back to the system java when `major` is None
+pub fn pin_jdk(name: &String, major: Option<u32>) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+
+    if let Some(major) = major {
+        if java_binary_in(&jdks_dir().join(major.to_string())).is_none() {
+            return Err(format!(
+                "JDK {} is not installed (run `slapaman java install {}`)",
+                major, major
+            ));
+        }
+    }
+
+    server.java = major;
+    update_server_by_name(name, &server)
+}
+
+// change an instance's stored JVM settings, or print them when nothing is changed
+pub fn configure_jvm(
+    name: &String,
+    memory: Option<u32>,
+    args: Vec<String>,
+    clear_args: bool,
+) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+
+    if memory.is_none() && args.is_empty() && !clear_args {
+        println!(
+            "memory: {}",
+            server
+                .memory
+                .map(|m| format!("{}M", m))
+                .unwrap_or_else(|| "default (2048M)".to_string())
+        );
+        println!("java: {}", java_binary_for(&server)?.display());
+        println!("arguments: {}", server.jvm_args.join(" "));
+        return Ok(());
+    }
+
+    if memory.is_some() {
+        server.memory = memory;
+    }
+    if clear_args {
+        server.jvm_args.clear();
+    }
+    server.jvm_args.extend(args);
+    update_server_by_name(name, &server)
+}
+
+// the java binary a server instance should be launched with
+pub fn java_binary_for(server: &Server) -> Result<PathBuf, String> {
+    match server.java {
+        Some(major) => java_binary_in(&jdks_dir().join(major.to_string())).ok_or_else(|| {
+            format!(
+                "instance is pinned to JDK {}, which is not installed (run `slapaman java install {}`)",
+                major, major
+            )
+        }),
+        None => Ok(PathBuf::from("java")),
+    }
+}
+
+// every managed JDK, sorted by major version
+pub fn installed_jdks() -> Result<Vec<(u32, PathBuf)>, String> {
+    let dir = jdks_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut installed = Vec::new();
+    for entry in dir
+        .read_dir()
+        .map_err(|e| format!("failed to read JDKs directory: {}", e))?
+    {
+        let entry = entry.map_err(|e| format!("failed to read JDK entry: {}", e))?;
+        let path = entry.path();
+        let major = match entry.file_name().to_string_lossy().parse::<u32>() {
+            Ok(major) => major,
+            Err(_) => continue, // staging directories and strays
+        };
+        if java_binary_in(&path).is_some() {
+            installed.push((major, path));
+        }
+    }
+    installed.sort_by_key(|(major, _)| *major);
+
+    Ok(installed)
+}
+
+pub fn jdks_dir() -> PathBuf {
+    ProjectDirs::from("com", "wyomingwade", "slapaman")
+        .expect("could not determine a home directory")
+        .data_dir()
+        .join(JDKS_DIR_NAME)
+}
+
+fn java_binary_in(java_home: &Path) -> Option<PathBuf> {
+    let bin = java_home.join(if cfg!(windows) {
+        "bin\\java.exe"
+    } else {
+        "bin/java"
+    });
+    bin.exists().then_some(bin)
+}
+
+// archives nest the JDK one level down (two on macOS), so look for the bin/java
+fn find_java_home(dir: &Path, depth: u32) -> Option<PathBuf> {
+    if java_binary_in(dir).is_some() {
+        return Some(dir.to_path_buf());
+    }
+    if depth == 0 {
+        return None;
+    }
+
+    let mut children = dir
+        .read_dir()
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect::<Vec<PathBuf>>();
+    children.sort();
+
+    children
+        .iter()
+        .find_map(|child| find_java_home(child, depth - 1))
+}
+
+fn extract_archive(archive_name: &str, archive: &[u8], dest: &Path) -> Result<(), String> {
+    if archive_name.ends_with(".tar.gz") || archive_name.ends_with(".tgz") {
+        tar::Archive::new(GzDecoder::new(archive))
+            .unpack(dest)
+            .map_err(|e| format!("failed to extract {}: {}", archive_name, e))
+    } else if archive_name.ends_with(".zip") {
+        zip::ZipArchive::new(Cursor::new(archive))
+            .and_then(|mut zip| zip.extract(dest))
+            .map_err(|e| format!("failed to extract {}: {}", archive_name, e))
+    } else {
+        Err(format!("unsupported JDK archive format: {}", archive_name))
+    }
+}
+
+fn read_jdk_metadata(install_dir: &Path) -> Option<JdkMetadata> {
+    let contents = fs::read_to_string(install_dir.join(JDK_METADATA_FILE)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn jdk_api_coerced(api: Option<String>) -> String {
+    match api {
+        Some(api) => api,
+        None => env::var(JDK_API_ENV).unwrap_or_else(|_| DEFAULT_JDK_API.to_string()),
+    }
+}
+
+fn adoptium_os() -> Result<&'static str, String> {
+    match env::consts::OS {
+        "linux" => Ok("linux"),
+        "macos" => Ok("mac"),
+        "windows" => Ok("windows"),
+        os => Err(format!("no managed JDKs available for {}", os)),
+    }
+}
+
+fn adoptium_arch() -> Result<&'static str, String> {
+    match env::consts::ARCH {
+        "x86_64" => Ok("x64"),
+        "aarch64" => Ok("aarch64"),
+        "x86" => Ok("x32"),
+        "arm" => Ok("arm"),
+        arch => Err(format!("no managed JDKs available for {}", arch)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::thread;
+
+    // a .tar.gz laid out like Adoptium's, with the JDK one level down
+    fn jdk_archive() -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let java = b"#!/bin/sh\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(java.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "jdk-21.0.2+13/bin/java", &java[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    // answer the assets query and the download from a local server, returning its base URL
+    fn serve(archive: Vec<u8>, checksum: String) -> String {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", server.server_addr().to_ip().unwrap());
+        let link = format!("{}/download/jdk.tar.gz", base);
+        thread::spawn(move || {
+            for request in server.incoming_requests().take(2) {
+                let response = if request.url().starts_with("/v3/assets/latest/21/hotspot?") {
+                    let assets = serde_json::json!([{
+                        "release_name": "jdk-21.0.2+13",
+                        "binary": { "package": {
+                            "link": link,
+                            "name": "OpenJDK21U-jdk_x64_linux_hotspot_21.0.2_13.tar.gz",
+                            "checksum": checksum,
+                            "size": archive.len(),
+                        }}
+                    }]);
+                    tiny_http::Response::from_data(assets.to_string().into_bytes())
+                } else if request.url() == "/download/jdk.tar.gz" {
+                    tiny_http::Response::from_data(archive.clone())
+                } else {
+                    tiny_http::Response::from_data(Vec::new()).with_status_code(404)
+                };
+                let _ = request.respond(response);
+            }
+        });
+        base
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("slapaman-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn installs_from_a_local_archive_server() {
+        let archive = jdk_archive();
+        let checksum = format!("{:x}", Sha256::digest(&archive));
+        let base = serve(archive, checksum.clone());
+        let dir = scratch_dir("jdk-install");
+
+        let java_home = install_jdk_into(0, 21, &base, &dir).await.unwrap();
+
+        assert_eq!(java_home, dir.join("21"));
+        assert!(java_binary_in(&java_home).is_some());
+        let metadata = read_jdk_metadata(&java_home).unwrap();
+        assert_eq!(metadata.checksum, checksum);
+        assert_eq!(metadata.release_name, "jdk-21.0.2+13");
+        assert!(!dir.join(".21.partial").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_an_archive_that_fails_its_checksum() {
+        let base = serve(jdk_archive(), "0".repeat(64));
+        let dir = scratch_dir("jdk-checksum");
+
+        let error = install_jdk_into(0, 21, &base, &dir).await.unwrap_err();
+
+        assert!(error.starts_with("checksum mismatch"), "{}", error);
+        assert!(!dir.join("21").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,592 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use chrono::{
+    DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone,
+};
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use regex::Regex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use crate::server::Server;
+
+// where slapaman keeps its own copy of everything a server prints
+pub const LOGS_DIR_NAME: &str = "slapaman-logs";
+const MAX_LOG_SIZE: u64 = 10 * 1024 * 1024;
+const MAX_LOG_FILES: usize = 20;
+const FOLLOW_POLL_INTERVAL: StdDuration = StdDuration::from_millis(500);
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl LogLevel {
+    fn from_label(label: &str) -> Option<Self> {
+        match label {
+            "TRACE" => Some(LogLevel::Trace),
+            "DEBUG" => Some(LogLevel::Debug),
+            "INFO" => Some(LogLevel::Info),
+            "WARN" | "WARNING" => Some(LogLevel::Warn),
+            "ERROR" | "SEVERE" => Some(LogLevel::Error),
+            "FATAL" => Some(LogLevel::Fatal),
+            _ => None,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogSource {
+    /// output captured by slapaman while running the server
+    Captured,
+    /// the server's own logs/latest.log
+    Latest,
+    /// the server's gzipped log history followed by logs/latest.log
+    History,
+}
+
+// tees server output into timestamped files, starting a new file once the current one gets too big
+pub struct LogWriter {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogWriter {
+    pub fn create(server_dir: &Path) -> Result<Self, String> {
+        let dir = server_dir.join(LOGS_DIR_NAME);
+        fs::create_dir_all(&dir).map_err(|e| format!("failed to create logs directory: {}", e))?;
+
+        let file = open_new_log_file(&dir)?;
+        prune_log_files(&dir)?;
+
+        Ok(Self { dir, file, size: 0 })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> Result<(), String> {
+        if self.size >= MAX_LOG_SIZE {
+            self.file = open_new_log_file(&self.dir)?;
+            self.size = 0;
+            prune_log_files(&self.dir)?;
+        }
+
+        let stamped = format!(
+            "{} {}\n",
+            Local::now().to_rfc3339_opts(SecondsFormat::Millis, false),
+            line
+        );
+        self.file
+            .write_all(stamped.as_bytes())
+            .map_err(|e| format!("failed to write log file: {}", e))?;
+        self.size += stamped.len() as u64;
+
+        Ok(())
+    }
+}
+
+// follows the captured log files written after it was created, for reading a server's output
+// from outside the process that runs it
+pub struct CapturedTail {
+    dir: PathBuf,
+    seen: Vec<PathBuf>,
+    current: Option<PathBuf>,
+    position: u64,
+}
+
+impl CapturedTail {
+    pub fn new(server_dir: &Path) -> Result<Self, String> {
+        let dir = server_dir.join(LOGS_DIR_NAME);
+        let seen = captured_log_files(&dir)?;
+        Ok(Self {
+            dir,
+            seen,
+            current: None,
+            position: 0,
+        })
+    }
+
+    // like `new`, but starts with the newest captured file, returning up to its last `backlog`
+    // lines before following on from there
+    pub fn with_backlog(server_dir: &Path, backlog: usize) -> Result<(Self, Vec<String>), String> {
+        let mut tail = Self::new(server_dir)?;
+        tail.seen.pop();
+        let mut lines = tail.poll()?;
+        lines.drain(..lines.len().saturating_sub(backlog));
+        Ok((tail, lines))
+    }
+
+    // complete lines written since the last poll, without slapaman's timestamp prefix
+    pub fn poll(&mut self) -> Result<Vec<String>, String> {
+        let mut lines = Vec::new();
+        loop {
+            if let Some(path) = &self.current {
+                let mut file = open_log(path)?;
+                file.seek(SeekFrom::Start(self.position))
+                    .map_err(|e| format!("failed to seek log file: {}", e))?;
+                let mut reader = BufReader::new(file);
+                let mut buf = Vec::new();
+                loop {
+                    buf.clear();
+                    let read = reader
+                        .read_until(b'\n', &mut buf)
+                        .map_err(|e| format!("failed to read log file: {}", e))?;
+                    if read == 0 || buf.last() != Some(&b'\n') {
+                        break;
+                    }
+                    self.position += read as u64;
+                    let line = String::from_utf8_lossy(&buf);
+                    let line = line.trim_end_matches(['\r', '\n']);
+                    let message = match line.split_once(' ') {
+                        Some((_, message)) => message,
+                        None => line,
+                    };
+                    lines.push(message.to_string());
+                }
+            }
+
+            // move on to the next file once one appears (a rotation or a new run)
+            let next = captured_log_files(&self.dir)?
+                .into_iter()
+                .find(|path| !self.seen.contains(path));
+            match next {
+                Some(path) => {
+                    self.seen.push(path.clone());
+                    self.current = Some(path);
+                    self.position = 0;
+                }
+                None => break,
+            }
+        }
+
+        Ok(lines)
+    }
+}
+
+// the filters `slapaman logs` applies to every line
+pub struct LogFilter {
+    since: Option<DateTime<Local>>,
+    grep: Option<Regex>,
+    level: Option<LogLevel>,
+}
+
+impl LogFilter {
+    pub fn new(
+        since: Option<String>,
+        grep: Option<String>,
+        level: Option<LogLevel>,
+    ) -> Result<Self, String> {
+        let since = match since {
+            Some(s) => Some(parse_since(&s)?),
+            None => None,
+        };
+        let grep = match grep {
+            Some(pattern) => {
+                Some(Regex::new(&pattern).map_err(|e| format!("invalid --grep pattern: {}", e))?)
+            }
+            None => None,
+        };
+
+        Ok(Self { since, grep, level })
+    }
+
+    fn matches(&self, line: &str, time: Option<DateTime<Local>>, level: LogLevel) -> bool {
+        if let (Some(since), Some(time)) = (self.since, time) {
+            if time < since {
+                return false;
+            }
+        }
+        if let Some(min_level) = self.level {
+            if level < min_level {
+                return false;
+            }
+        }
+        if let Some(grep) = &self.grep {
+            if !grep.is_match(line) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// tracks timestamps and levels across lines, so stack traces and other continuation lines
+// are filtered along with the line that started them
+struct LineContext {
+    captured: bool,
+    date: NaiveDate,
+    time: Option<DateTime<Local>>,
+    level: LogLevel,
+    level_re: Regex,
+    time_re: Regex,
+}
+
+impl LineContext {
+    fn new(captured: bool, date: NaiveDate) -> Self {
+        Self {
+            captured,
+            date,
+            time: None,
+            level: LogLevel::Info,
+            level_re: Regex::new(r"[/ ](TRACE|DEBUG|INFO|WARN|WARNING|ERROR|SEVERE|FATAL)\]")
+                .unwrap(),
+            time_re: Regex::new(r"^\[(\d{2}:\d{2}:\d{2})").unwrap(),
+        }
+    }
+
+    fn observe(&mut self, line: &str) {
+        let body = if self.captured {
+            // captured lines start with slapaman's own RFC 3339 timestamp
+            match line.split_once(' ') {
+                Some((stamp, rest)) => {
+                    if let Ok(time) = DateTime::parse_from_rfc3339(stamp) {
+                        self.time = Some(time.with_timezone(&Local));
+                    }
+                    rest
+                }
+                None => line,
+            }
+        } else {
+            if let Some(caps) = self.time_re.captures(line) {
+                if let Ok(time) = NaiveTime::parse_from_str(&caps[1], "%H:%M:%S") {
+                    self.time = Local
+                        .from_local_datetime(&NaiveDateTime::new(self.date, time))
+                        .earliest();
+                }
+            }
+            line
+        };
+
+        // only the line header carries the level, so don't look too far into the message
+        let header = body.get(..body.len().min(64)).unwrap_or(body);
+        if let Some(caps) = self.level_re.captures(header) {
+            if let Some(level) = LogLevel::from_label(&caps[1]) {
+                self.level = level;
+            }
+        }
+    }
+}
+
+pub fn show_logs(
+    name: &String,
+    source: LogSource,
+    follow: bool,
+    filter: LogFilter,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+
+    if !server_dir.exists() {
+        return Err(format!("server instance does not exist: {}", name));
+    }
+
+    match source {
+        LogSource::Captured => {
+            let dir = server_dir.join(LOGS_DIR_NAME);
+            let files = captured_log_files(&dir)?;
+            if files.is_empty() && !follow {
+                return Err(format!("no captured logs for server instance: {}", name));
+            }
+
+            for path in &files {
+                let mut ctx = LineContext::new(true, file_date(path));
+                print_lines(BufReader::new(open_log(path)?), &mut ctx, &filter);
+            }
+
+            if follow {
+                follow_captured(&dir, files.last().cloned(), &filter)?;
+            }
+        }
+        LogSource::Latest | LogSource::History => {
+            let logs_dir = server_dir.join("logs");
+
+            if source == LogSource::History {
+                for path in minecraft_history_files(&logs_dir)? {
+                    let mut ctx = LineContext::new(false, history_file_date(&path));
+                    let reader = BufReader::new(GzDecoder::new(open_log(&path)?));
+                    print_lines(reader, &mut ctx, &filter);
+                }
+            }
+
+            let latest = logs_dir.join("latest.log");
+            if !latest.exists() && !follow {
+                return Err(format!("no latest.log for server instance: {}", name));
+            }
+
+            let mut ctx = LineContext::new(false, file_date(&latest));
+            let mut position = 0;
+            if latest.exists() {
+                position = print_from(&latest, 0, &mut ctx, &filter)?;
+            }
+
+            if follow {
+                loop {
+                    thread::sleep(FOLLOW_POLL_INTERVAL);
+                    let len = match fs::metadata(&latest) {
+                        Ok(metadata) => metadata.len(),
+                        Err(_) => continue,
+                    };
+                    // the server starts a fresh latest.log on every launch
+                    if len < position {
+                        position = 0;
+                        ctx = LineContext::new(false, Local::now().date_naive());
+                    }
+                    position = print_from(&latest, position, &mut ctx, &filter)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn follow_captured(dir: &Path, current: Option<PathBuf>, filter: &LogFilter) -> Result<(), String> {
+    let mut current = current;
+    let mut position = match &current {
+        Some(path) => fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+        None => 0,
+    };
+    let mut ctx = LineContext::new(true, Local::now().date_naive());
+
+    loop {
+        if let Some(path) = &current {
+            position = print_from(path, position, &mut ctx, filter)?;
+        }
+
+        // a rotation or a new run of the server means a newer file to switch to
+        let newest = captured_log_files(dir)?.pop();
+        if newest.is_some() && newest != current {
+            if let Some(path) = &current {
+                print_from(path, position, &mut ctx, filter)?;
+            }
+            current = newest;
+            position = 0;
+            continue;
+        }
+
+        thread::sleep(FOLLOW_POLL_INTERVAL);
+    }
+}
+
+// print every complete line after `position`, returning the position to resume from
+fn print_from(
+    path: &Path,
+    position: u64,
+    ctx: &mut LineContext,
+    filter: &LogFilter,
+) -> Result<u64, String> {
+    let mut file = open_log(path)?;
+    file.seek(SeekFrom::Start(position))
+        .map_err(|e| format!("failed to seek log file: {}", e))?;
+
+    let mut reader = BufReader::new(file);
+    let mut position = position;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|e| format!("failed to read log file: {}", e))?;
+        // leave partial lines for the next poll
+        if read == 0 || buf.last() != Some(&b'\n') {
+            break;
+        }
+        position += read as u64;
+
+        let line = String::from_utf8_lossy(&buf);
+        print_line(line.trim_end_matches(['\r', '\n']), ctx, filter);
+    }
+
+    Ok(position)
+}
+
+fn print_lines<R: Read>(reader: BufReader<R>, ctx: &mut LineContext, filter: &LogFilter) {
+    for line in reader.split(b'\n').map_while(Result::ok) {
+        let line = String::from_utf8_lossy(&line);
+        print_line(line.trim_end_matches('\r'), ctx, filter);
+    }
+}
+
+fn print_line(line: &str, ctx: &mut LineContext, filter: &LogFilter) {
+    ctx.observe(line);
+    if filter.matches(line, ctx.time, ctx.level) {
+        println!("{}", line);
+    }
+}
+
+fn open_new_log_file(dir: &Path) -> Result<File, String> {
+    let base_name = format!("server-{}", Local::now().format("%Y%m%d-%H%M%S"));
+    let mut path = dir.join(format!("{}.log", base_name));
+    let mut index = 1;
+    while path.exists() {
+        path = dir.join(format!("{}-{:02}.log", base_name, index));
+        index += 1;
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| format!("failed to create log file {}: {}", path.display(), e))
+}
+
+fn prune_log_files(dir: &Path) -> Result<(), String> {
+    let files = captured_log_files(dir)?;
+    if files.len() > MAX_LOG_FILES {
+        for path in &files[..files.len() - MAX_LOG_FILES] {
+            fs::remove_file(path).map_err(|e| format!("failed to remove old log file: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+// captured log files, oldest first (the names sort chronologically)
+fn captured_log_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    list_files(dir, |name| {
+        name.starts_with("server-") && name.ends_with(".log")
+    })
+}
+
+// Minecraft's rotated logs, named like 2025-01-31-2.log.gz
+fn minecraft_history_files(logs_dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = list_files(logs_dir, |name| name.ends_with(".log.gz"))?;
+    files.sort_by_key(|path| {
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let index = name
+            .trim_end_matches(".log.gz")
+            .rsplit('-')
+            .next()
+            .and_then(|i| i.parse::<u32>().ok())
+            .unwrap_or(0);
+        (history_file_date(path), index)
+    });
+    Ok(files)
+}
+
+fn list_files(dir: &Path, keep: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>, String> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in dir
+        .read_dir()
+        .map_err(|e| format!("failed to read logs directory: {}", e))?
+    {
+        let entry = entry.map_err(|e| format!("failed to read logs entry: {}", e))?;
+        if keep(&entry.file_name().to_string_lossy()) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+fn open_log(path: &Path) -> Result<File, String> {
+    File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))
+}
+
+fn history_file_date(path: &Path) -> NaiveDate {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.get(..10))
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+        .unwrap_or_else(|| file_date(path))
+}
+
+fn file_date(path: &Path) -> NaiveDate {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map(|t| DateTime::<Local>::from(t).date_naive())
+        .unwrap_or_else(|_| Local::now().date_naive())
+}
+
+// accepts a relative age like 30s, 15m, 2h, 7d or an absolute local time
+fn parse_since(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+
+    if !s.is_empty() && s.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!(
+            "--since {} needs a unit (expected e.g. 30m, 2h, 1d)",
+            s
+        ));
+    }
+    // only a letter can be a unit, so a time ending in a digit isn't mistaken for an age
+    if let Some(unit) = s.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        if let Ok(amount) = s[..s.len() - 1].parse::<i64>() {
+            let age = match unit {
+                's' => Duration::seconds(amount),
+                'm' => Duration::minutes(amount),
+                'h' => Duration::hours(amount),
+                'd' => Duration::days(amount),
+                _ => {
+                    return Err(format!(
+                        "invalid --since unit: {} (expected e.g. 30m, 2h, 1d)",
+                        unit
+                    ))
+                }
+            };
+            return Ok(Local::now() - age);
+        }
+    }
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Local));
+    }
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+            if let Some(time) = Local.from_local_datetime(&time).earliest() {
+                return Ok(time);
+            }
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        if let Some(time) = Local
+            .from_local_datetime(&date.and_time(NaiveTime::MIN))
+            .earliest()
+        {
+            return Ok(time);
+        }
+    }
+
+    Err(format!(
+        "invalid --since value: {} (expected an age like 30m, 2h, 1d or a time like 2025-01-31 18:00)",
+        s
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_needs_a_unit() {
+        let error = parse_since("15").unwrap_err();
+        assert!(error.contains("needs a unit"), "{}", error);
+        let error = parse_since("15w").unwrap_err();
+        assert!(error.contains("invalid --since unit: w"), "{}", error);
+    }
+
+    #[test]
+    fn since_takes_ages_and_times() {
+        let age = Local::now() - parse_since("2h").unwrap();
+        assert!((age - Duration::hours(2)).num_seconds().abs() < 5);
+
+        let time = parse_since("2025-01-31 18:00").unwrap();
+        assert_eq!(
+            time.naive_local(),
+            NaiveDateTime::parse_from_str("2025-01-31 18:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+        );
+        assert!(parse_since("yesterday").is_err());
+    }
+}
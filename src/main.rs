@@ -2,31 +2,91 @@
 // Copyright (c) 2025 Wyoming Wade
 
 use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
 
 pub mod flavors;
 pub mod net;
 
+pub mod api;
+pub mod archive;
 pub mod args;
 pub mod backup;
+pub mod catalog;
+pub mod console;
+pub mod control;
 pub mod create;
+pub mod crypto;
+pub mod daemon;
+pub mod destinations;
+pub mod diff;
+pub mod events;
 pub mod init;
+pub mod integrity;
+pub mod java;
+pub mod logs;
 pub mod memory;
+pub mod metrics;
+pub mod nbt;
+pub mod process;
+pub mod properties;
 pub mod remove;
+pub mod resources;
+pub mod retention;
 pub mod run;
+pub mod schedule;
+pub mod selection;
 pub mod server;
+pub mod snapshot;
+pub mod status;
+pub mod systemd;
+pub mod tokens;
 pub mod update;
 pub mod version;
+pub mod watchdog;
+pub mod webhooks;
 pub mod world;
 
-use args::{Cli, Commands};
-use backup::{create_world_backup, restore_world_backup};
+use api::serve_api;
+use args::{
+    Cli, Commands, DestinationCommands, JavaCommands, MetricsCommands, ScheduleCommands,
+    TaskCommands, TokenCommands, WebhookCommands,
+};
+use backup::{
+    configure_backups, create_world_backup, restore_world_backup, BackupConfigChanges,
+    RestoreOptions,
+};
+use catalog::{delete_world_backup, list_world_backups, show_world_backup};
+use console::stop_server;
+use control::{daemon_running, try_daemon, ControlCommand};
 use create::create_new_server;
+use crypto::Encryption;
+use daemon::{run_daemon, set_autostart};
+use destinations::{
+    add_destination, list_destinations, list_remote_world_backups, push_world_backup,
+    remove_destination, test_destination, DestinationOptions,
+};
+use diff::diff_world;
 use init::slapaman_init;
+use integrity::verify_world_backups;
+use java::{configure_jvm, install_jdk, list_jdks, pin_jdk, remove_jdk};
+use logs::{show_logs, LogFilter};
+use metrics::serve_metrics;
 use remove::remove_server;
-use run::run_server;
-use server::{copy_server, list_servers, move_server, rename_server};
+use resources::show_top;
+use retention::{prune_world_backups, RetentionPolicy};
+use run::{run_server_with_events, start_server_and_wait_ready};
+use schedule::{
+    add_scheduled_task, list_scheduled_tasks, remove_scheduled_task, run_scheduler, TaskAction,
+};
+use server::{copy_server, list_servers, move_server, rename_server, Server};
+use status::{ping_target, query_instance, show_status};
+use systemd::systemd;
+use tokens::{create_token, list_tokens, revoke_token};
 use update::{update_all_servers, update_server};
 use version::Version;
+use watchdog::{run_watchdog, WatchdogConfig};
+use webhooks::{add_webhook, list_webhooks, remove_webhook, test_webhooks};
 use world::set_world;
 
 #[tokio::main]
@@ -84,15 +144,132 @@ async fn main() {
             Ok(_) => println!("[slapaman] removed server instance: {}", name),
             Err(e) => println!("[slapaman] error removing server instance: {}", e),
         },
+        Commands::Run {
+            name,
+            memory,
+            wait_ready: true,
+            ready_timeout,
+            no_daemon,
+            ..
+        } => match start_server_and_wait_ready(
+            cli.verbose,
+            name.clone(),
+            memory,
+            Duration::from_secs(ready_timeout),
+            !no_daemon,
+        ) {
+            Ok(startup_secs) => println!(
+                "[slapaman] server instance is ready: {} (started in {}s)",
+                name, startup_secs
+            ),
+            Err(e) => {
+                println!("[slapaman] error starting server instance: {}", e);
+                // scripts waiting on the server rely on the exit status here
+                std::process::exit(1);
+            }
+        },
         Commands::Run {
             name,
             memory,
             quiet,
-        } => match run_server(cli.verbose, name.clone(), memory, Some(quiet)) {
-            Ok(_) => println!("[slapaman] successfully ran server instance: {}", name),
-            Err(e) => println!("[slapaman] error running server instance: {}", e),
+            events,
+            no_daemon: false,
+            ..
+        } if daemon_running() => {
+            if quiet || events {
+                println!("[slapaman] --quiet and --events don't apply to servers the daemon runs");
+            }
+            match try_daemon(ControlCommand::Start {
+                name: name.clone(),
+                memory,
+            }) {
+                Some(Ok(_)) => println!(
+                    "[slapaman] the daemon started server instance: {} (follow it with `slapaman logs {} -f`)",
+                    name, name
+                ),
+                Some(Err(e)) => {
+                    println!("[slapaman] error running server instance: {}", e);
+                    std::process::exit(1);
+                }
+                None => println!("[slapaman] error running server instance: the daemon stopped"),
+            }
+        }
+        Commands::Run {
+            name,
+            memory,
+            quiet,
+            events,
+            ..
+        } => {
+            let result =
+                run_server_with_events(cli.verbose, name.clone(), memory, Some(quiet), |event| {
+                    if events {
+                        if let Ok(json) = serde_json::to_string(event) {
+                            println!("{}", json);
+                        }
+                    }
+                });
+            match result {
+                Ok(_) => println!("[slapaman] successfully ran server instance: {}", name),
+                Err(e) => {
+                    println!("[slapaman] error running server instance: {}", e);
+                    // supervisors (systemd, the daemon) decide whether to restart from this
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Stop { name, timeout } => {
+            // the daemon has to know the stop is deliberate, or it may restart the server
+            let result = match try_daemon(ControlCommand::Stop {
+                name: name.clone(),
+                timeout,
+            }) {
+                Some(result) => result.map(|_| ()),
+                None => stop_server(&name, Duration::from_secs(timeout)),
+            };
+            match result {
+                Ok(_) => println!("[slapaman] successfully stopped server instance: {}", name),
+                Err(e) => println!("[slapaman] error stopping server instance: {}", e),
+            }
+        }
+        Commands::Jvm {
+            name,
+            memory,
+            args,
+            clear_args,
+        } => match configure_jvm(&name, memory, args, clear_args) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error configuring JVM settings: {}", e),
+        },
+        Commands::Daemon => match run_daemon() {
+            Ok(_) => println!("[slapaman] daemon stopped"),
+            Err(e) => println!("[slapaman] error running daemon: {}", e),
         },
-        Commands::List { detailed } => match list_servers(detailed) {
+        Commands::Autostart {
+            name,
+            enabled,
+            restart,
+        } => match set_autostart(&name, enabled, restart) {
+            Ok(_) => println!(
+                "[slapaman] {} autostart for server instance: {}",
+                if enabled { "enabled" } else { "disabled" },
+                name
+            ),
+            Err(e) => println!("[slapaman] error setting autostart: {}", e),
+        },
+        Commands::Systemd {
+            name,
+            action,
+            user,
+            run_as,
+        } => match systemd(&name, action, user, run_as.as_deref()) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error managing systemd unit: {}", e),
+        },
+        Commands::List {
+            detailed,
+            resources,
+        } => match list_servers(detailed, resources) {
             Ok(_) => println!("[slapaman] successfully listed server instances"),
             Err(e) => println!("[slapaman] error listing server instances: {}", e),
         },
@@ -100,18 +277,46 @@ async fn main() {
             name,
             version,
             flavor,
-        } => match update_server(&name, Version::from_string(version), flavor).await {
-            Ok(_) => println!("[slapaman] successfully updated server instance: {}", name),
-            Err(e) => println!("[slapaman] error updating server instance: {}", e),
-        },
+        } => {
+            // the daemon stops and restarts the server around the update if it's running
+            let result = match try_daemon(ControlCommand::Update {
+                name: name.clone(),
+                version: version.clone(),
+                flavor: flavor.clone(),
+            }) {
+                Some(result) => result.map(|_| ()),
+                None => update_server(&name, Version::from_string(version), flavor).await,
+            };
+            match result {
+                Ok(_) => println!("[slapaman] successfully updated server instance: {}", name),
+                Err(e) => println!("[slapaman] error updating server instance: {}", e),
+            }
+        }
         Commands::UpdateAll { version } => {
             match update_all_servers(Version::from_string(version)).await {
                 Ok(_) => println!("[slapaman] successfully updated all server instances"),
                 Err(e) => println!("[slapaman] error updating all server instances: {}", e),
             }
         }
-        Commands::WorldBackup { name, tag } => {
-            match create_world_backup(cli.verbose, name.clone(), tag) {
+        Commands::WorldBackup {
+            name,
+            tag,
+            format,
+            scope,
+        } => {
+            let result = match try_daemon(ControlCommand::WorldBackup {
+                name: name.clone(),
+                tag: tag.clone(),
+                format,
+                scope,
+            }) {
+                Some(result) => result.and_then(|data| {
+                    serde_json::from_value::<PathBuf>(data["path"].clone())
+                        .map_err(|e| format!("unexpected reply from the daemon: {}", e))
+                }),
+                None => create_world_backup(cli.verbose, name.clone(), tag, format, scope),
+            };
+            match result {
                 Ok(path) => println!(
                     "[slapaman] created world backup for server instance: {} -> {}",
                     name,
@@ -120,23 +325,390 @@ async fn main() {
                 Err(e) => println!("[slapaman] error creating world backup: {}", e),
             }
         }
-        Commands::WorldRestore { name, backup } => {
-            match restore_world_backup(cli.verbose, name.clone(), &backup) {
-                Ok(_) => println!(
-                    "[slapaman] restored world backup for server instance: {}",
-                    name
-                ),
-                Err(e) => println!("[slapaman] error restoring world backup: {}", e),
+        Commands::WorldBackupConfig {
+            name,
+            format,
+            scope,
+            keep_last,
+            keep_daily,
+            keep_weekly,
+            keep_monthly,
+            max_size,
+            protect_tagged,
+            clear_retention,
+            region_sectors,
+            announce,
+            encrypt,
+            key_file,
+        } => {
+            // retention flags update the stored policy (or, after --clear-retention, an empty
+            // one), leaving the other rules as they were
+            let changes_retention = keep_last.is_some()
+                || keep_daily.is_some()
+                || keep_weekly.is_some()
+                || keep_monthly.is_some()
+                || max_size.is_some()
+                || protect_tagged.is_some();
+            let retention = match clear_retention || changes_retention {
+                false => Ok(None),
+                true => Server::load_by_name(&name).map(|server| {
+                    let current = match clear_retention {
+                        true => RetentionPolicy::default(),
+                        false => server.backup.retention,
+                    };
+                    Some(RetentionPolicy {
+                        keep_last: keep_last.or(current.keep_last),
+                        keep_daily: keep_daily.or(current.keep_daily),
+                        keep_weekly: keep_weekly.or(current.keep_weekly),
+                        keep_monthly: keep_monthly.or(current.keep_monthly),
+                        max_total_size: max_size.or(current.max_total_size),
+                        protect_tagged: protect_tagged.unwrap_or(current.protect_tagged),
+                    })
+                }),
+            };
+            let encryption = encrypt.as_deref().map(Encryption::parse).transpose();
+            let changes = retention.and_then(|retention| {
+                Ok(BackupConfigChanges {
+                    format,
+                    scope,
+                    retention,
+                    sectors: region_sectors,
+                    announce,
+                    encryption: encryption?,
+                    key_file,
+                })
+            });
+            match changes.and_then(|changes| configure_backups(&name, changes)) {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error configuring world backups: {}", e),
+            }
+        }
+        Commands::WorldPrune { name, dry_run } => match prune_world_backups(&name, dry_run) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error pruning world backups: {}", e),
+        },
+        Commands::WorldBackups {
+            name,
+            no_verify,
+            from,
+            json,
+            key_file,
+        } => match match from {
+            Some(from) => list_remote_world_backups(&name, &from, json),
+            None => list_world_backups(&name, !no_verify, json, key_file.as_deref()),
+        } {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error listing world backups: {}", e),
+        },
+        Commands::WorldBackupShow {
+            name,
+            backup,
+            json,
+            key_file,
+        } => match show_world_backup(&name, &backup, json, key_file.as_deref()) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error showing world backup: {}", e),
+        },
+        Commands::WorldBackupDelete { name, backup } => match delete_world_backup(&name, &backup) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error deleting world backup: {}", e),
+        },
+        Commands::WorldDiff {
+            name,
+            backup,
+            other,
+            json,
+            key_file,
+        } => match diff_world(
+            &name,
+            &backup,
+            &other,
+            cli.verbose,
+            json,
+            key_file.as_deref(),
+        ) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error comparing world backups: {}", e),
+        },
+        Commands::WorldBackupVerify {
+            name,
+            backup,
+            all,
+            key_file,
+        } => match verify_world_backups(&name, backup.as_deref(), all, key_file.as_deref()) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error verifying world backups: {}", e),
+        },
+        Commands::WorldBackupPush { name, backup, to } => {
+            match push_world_backup(&name, backup.as_deref(), to.as_deref()) {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error uploading world backup: {}", e),
             }
         }
-        Commands::WorldSet { name, world_path } => {
-            match set_world(cli.verbose, name.clone(), &world_path) {
+        Commands::WorldBackupDestination { name, command } => match command {
+            DestinationCommands::Add {
+                destination,
+                url,
+                endpoint,
+                region,
+                access_key,
+                secret_key,
+                identity,
+            } => {
+                let options = DestinationOptions {
+                    endpoint,
+                    region,
+                    access_key,
+                    secret_key,
+                    identity,
+                };
+                match add_destination(&name, &destination, &url, options) {
+                    Ok(description) => println!(
+                        "[slapaman] added backup destination {} to {} ({})",
+                        destination, name, description
+                    ),
+                    Err(e) => println!("[slapaman] error adding backup destination: {}", e),
+                }
+            }
+            DestinationCommands::List => match list_destinations(&name) {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error listing backup destinations: {}", e),
+            },
+            DestinationCommands::Remove { destination } => {
+                match remove_destination(&name, &destination) {
+                    Ok(_) => println!(
+                        "[slapaman] removed backup destination {} from {}",
+                        destination, name
+                    ),
+                    Err(e) => println!("[slapaman] error removing backup destination: {}", e),
+                }
+            }
+            DestinationCommands::Test { destination } => {
+                match test_destination(&name, &destination) {
+                    Ok(_) => (),
+                    Err(e) => println!("[slapaman] error reaching backup destination: {}", e),
+                }
+            }
+        },
+        Commands::WorldRestore {
+            name,
+            backup,
+            force,
+            from,
+            no_snapshot,
+            key_file,
+            only,
+        } => match restore_world_backup(
+            cli.verbose,
+            name.clone(),
+            &backup,
+            &RestoreOptions {
+                force,
+                from,
+                snapshot: !no_snapshot,
+                key_file,
+                only,
+            },
+        ) {
+            Ok(_) => println!(
+                "[slapaman] restored world backup for server instance: {}",
+                name
+            ),
+            Err(e) => println!("[slapaman] error restoring world backup: {}", e),
+        },
+        Commands::WorldSet {
+            name,
+            world_path,
+            no_snapshot,
+        } => match set_world(cli.verbose, name.clone(), &world_path, !no_snapshot) {
+            Ok(_) => println!(
+                "[slapaman] successfully set world for server instance: {}",
+                name
+            ),
+            Err(e) => println!("[slapaman] error setting world for server instance: {}", e),
+        },
+        Commands::Logs {
+            name,
+            source,
+            follow,
+            since,
+            grep,
+            level,
+        } => match LogFilter::new(since, grep, level)
+            .and_then(|filter| show_logs(&name, source, follow, filter))
+        {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error reading logs: {}", e),
+        },
+        Commands::Ping {
+            target,
+            timeout,
+            json,
+        } => match ping_target(&target, Duration::from_secs(timeout), json) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error pinging {}: {}", target, e),
+        },
+        Commands::Query {
+            name,
+            basic,
+            timeout,
+            json,
+        } => match query_instance(&name, basic, Duration::from_secs(timeout), json) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error querying {}: {}", name, e),
+        },
+        Commands::Status {
+            name,
+            timeout,
+            json,
+        } => match show_status(name, Duration::from_secs(timeout), json) {
+            Ok(_) => (),
+            Err(e) => println!("[slapaman] error showing status: {}", e),
+        },
+        Commands::Watchdog {
+            names,
+            probe,
+            interval,
+            timeout,
+            failures,
+            grace,
+            stale_log,
+            once,
+        } => {
+            let config = WatchdogConfig {
+                probe,
+                interval: Duration::from_secs(interval),
+                timeout: Duration::from_secs(timeout),
+                max_failures: failures.max(1),
+                grace: Duration::from_secs(grace),
+                stale_log: stale_log.map(Duration::from_secs),
+            };
+            match run_watchdog(names, config, once) {
+                Ok(_) => println!("[slapaman] watchdog finished"),
+                Err(e) => println!("[slapaman] error running watchdog: {}", e),
+            }
+        }
+        Commands::Top { interval, once } => {
+            match show_top(Duration::from_secs(interval.max(1)), once) {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error showing resource usage: {}", e),
+            }
+        }
+        Commands::Schedule { name, command } => match command {
+            ScheduleCommands::Add { cron, task } => {
+                let action = match task {
+                    TaskCommands::Backup { tag } => TaskAction::Backup { tag },
+                    TaskCommands::Restart { warn } => TaskAction::Restart { warnings: warn },
+                    TaskCommands::Command { command } => TaskAction::Command {
+                        command: command.join(" "),
+                    },
+                    TaskCommands::UpdateCheck => TaskAction::UpdateCheck,
+                };
+                match add_scheduled_task(&name, &cron, action) {
+                    Ok(id) => println!("[slapaman] added scheduled task {} to {}", id, name),
+                    Err(e) => println!("[slapaman] error adding scheduled task: {}", e),
+                }
+            }
+            ScheduleCommands::List => match list_scheduled_tasks(&name) {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error listing scheduled tasks: {}", e),
+            },
+            ScheduleCommands::Remove { id } => match remove_scheduled_task(&name, id) {
+                Ok(_) => println!("[slapaman] removed scheduled task {} from {}", id, name),
+                Err(e) => println!("[slapaman] error removing scheduled task: {}", e),
+            },
+        },
+        Commands::Scheduler { names } => match run_scheduler(names) {
+            Ok(_) => println!("[slapaman] scheduler finished"),
+            Err(e) => println!("[slapaman] error running scheduler: {}", e),
+        },
+        Commands::Metrics { command } => match command {
+            MetricsCommands::Serve { listen, timeout } => {
+                match serve_metrics(&listen, Duration::from_secs(timeout)) {
+                    Ok(_) => println!("[slapaman] metrics server stopped"),
+                    Err(e) => println!("[slapaman] error serving metrics: {}", e),
+                }
+            }
+        },
+        Commands::Serve { listen } => match serve_api(&listen) {
+            Ok(_) => println!("[slapaman] API server stopped"),
+            Err(e) => println!("[slapaman] error serving the API: {}", e),
+        },
+        Commands::Token { command } => match command {
+            TokenCommands::Create { label, scope } => match create_token(&label, scope) {
+                Ok((id, secret)) => {
+                    println!(
+                        "[slapaman] created {} API token {} ({})",
+                        scope.name(),
+                        id,
+                        label
+                    );
+                    println!("[slapaman] this is the only time the token is shown:");
+                    println!("{}", secret);
+                }
+                Err(e) => println!("[slapaman] error creating API token: {}", e),
+            },
+            TokenCommands::List => match list_tokens() {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error listing API tokens: {}", e),
+            },
+            TokenCommands::Revoke { id } => match revoke_token(id) {
+                Ok(_) => println!("[slapaman] revoked API token {}", id),
+                Err(e) => println!("[slapaman] error revoking API token: {}", e),
+            },
+        },
+        Commands::Webhook { command } => match command {
+            WebhookCommands::Add {
+                url,
+                instance,
+                format,
+                events,
+            } => match add_webhook(instance.as_ref(), &url, format, events) {
+                Ok(id) => println!("[slapaman] added webhook {}", id),
+                Err(e) => println!("[slapaman] error adding webhook: {}", e),
+            },
+            WebhookCommands::List => match list_webhooks() {
+                Ok(_) => (),
+                Err(e) => println!("[slapaman] error listing webhooks: {}", e),
+            },
+            WebhookCommands::Remove { id } => match remove_webhook(id) {
+                Ok(_) => println!("[slapaman] removed webhook {}", id),
+                Err(e) => println!("[slapaman] error removing webhook: {}", e),
+            },
+            WebhookCommands::Test { id } => match test_webhooks(id) {
+                Ok(_) => println!("[slapaman] webhook test succeeded"),
+                Err(e) => println!("[slapaman] error testing webhooks: {}", e),
+            },
+        },
+        Commands::Java { command } => match command {
+            JavaCommands::Install { major, api } => {
+                match install_jdk(cli.verbose, major, api).await {
+                    Ok(_) => println!("[slapaman] successfully installed JDK {}", major),
+                    Err(e) => println!("[slapaman] error installing JDK {}: {}", major, e),
+                }
+            }
+            JavaCommands::List => match list_jdks() {
+                Ok(_) => println!("[slapaman] successfully listed JDKs"),
+                Err(e) => println!("[slapaman] error listing JDKs: {}", e),
+            },
+            JavaCommands::Remove { major } => match remove_jdk(major) {
+                Ok(_) => println!("[slapaman] removed JDK {}", major),
+                Err(e) => println!("[slapaman] error removing JDK {}: {}", major, e),
+            },
+            JavaCommands::Pin { name, major } => match pin_jdk(&name, Some(major)) {
                 Ok(_) => println!(
-                    "[slapaman] successfully set world for server instance: {}",
+                    "[slapaman] pinned server instance {} to JDK {}",
+                    name, major
+                ),
+                Err(e) => println!("[slapaman] error pinning JDK: {}", e),
+            },
+            JavaCommands::Unpin { name } => match pin_jdk(&name, None) {
+                Ok(_) => println!(
+                    "[slapaman] server instance {} now uses the system java",
                     name
                 ),
-                Err(e) => println!("[slapaman] error setting world for server instance: {}", e),
-            }
-        }
+                Err(e) => println!("[slapaman] error unpinning JDK: {}", e),
+            },
+        },
     }
 }
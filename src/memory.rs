@@ -3,10 +3,7 @@
 
 pub fn memory_value_coerced(memory: Option<u32>) -> u32 {
     // leaving memory blank will default to 2048 MB
-    match memory {
-        Some(m) => m,
-        None => 2048,
-    }
+    memory.unwrap_or(2048)
 }
 
 pub fn parse_mem(s: &str) -> Result<u32, String> {
@@ -17,3 +14,25 @@ pub fn parse_mem(s: &str) -> Result<u32, String> {
         v => v.parse::<u32>().map_err(|e| e.to_string()),
     }
 }
+
+// a size in bytes like 512M, 20G or 1T (powers of 1024), for disk limits
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let lower = s.trim().to_ascii_lowercase();
+    let lower = lower
+        .strip_suffix("ib")
+        .or(lower.strip_suffix('b'))
+        .unwrap_or(&lower);
+    let (digits, multiplier) = match lower.chars().last() {
+        Some('k') => (&lower[..lower.len() - 1], 1u64 << 10),
+        Some('m') => (&lower[..lower.len() - 1], 1 << 20),
+        Some('g') => (&lower[..lower.len() - 1], 1 << 30),
+        Some('t') => (&lower[..lower.len() - 1], 1 << 40),
+        _ => (lower, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("invalid size: {} (expected e.g. 500M or 20G)", s))
+}
@@ -0,0 +1,402 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fmt::Write;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime};
+
+use regex::Regex;
+use tiny_http::{Header, Method, Response};
+
+use crate::backup::{latest_backup, BACKUPS_DIR_NAME};
+use crate::net::ping::ping;
+use crate::net::rcon::RconClient;
+use crate::properties::{rcon_address, server_address};
+use crate::resources::{dir_size, ProcessSample};
+use crate::run::running_pid;
+use crate::server::{get_all_servers, Server};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const TPS_WINDOWS: [&str; 3] = ["1m", "5m", "15m"];
+const MSPT_WINDOWS: [&str; 3] = ["5s", "10s", "1m"];
+
+static NUMBER_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\d+(?:\.\d+)?").unwrap());
+// avg/min/max, of which we keep the average
+static TICK_TIMES_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(\d+(?:\.\d+)?)/\d+(?:\.\d+)?/\d+(?:\.\d+)?").unwrap());
+static FORMATTING_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"§.").unwrap());
+
+// everything we could find out about one instance during a scrape
+struct InstanceMetrics {
+    labels: String,
+    up: bool,
+    process: Option<ProcessSample>,
+    players: Option<(i64, i64, Option<f64>)>,
+    tps: Vec<f64>,
+    mspt: Vec<f64>,
+    restarts: u64,
+    last_backup: Option<(f64, u64)>,
+    backups_bytes: u64,
+}
+
+// serve OpenMetrics for every instance until interrupted
+pub fn serve_metrics(listen: &str, timeout: Duration) -> Result<(), String> {
+    let server = tiny_http::Server::http(listen)
+        .map_err(|e| format!("failed to listen on {}: {}", listen, e))?;
+    println!("[slapaman] serving metrics on http://{}/metrics", listen);
+
+    for request in server.incoming_requests() {
+        let response = match (request.method(), request.url()) {
+            (Method::Get, "/metrics") => match render_metrics(timeout) {
+                Ok(body) => Response::from_string(body).with_header(
+                    Header::from_bytes("Content-Type", CONTENT_TYPE)
+                        .expect("static header is valid"),
+                ),
+                Err(e) => Response::from_string(format!("error collecting metrics: {}\n", e))
+                    .with_status_code(500),
+            },
+            (Method::Get, "/") => Response::from_string("slapaman metrics: see /metrics\n"),
+            (Method::Get, _) => Response::from_string("not found\n").with_status_code(404),
+            _ => Response::from_string("method not allowed\n").with_status_code(405),
+        };
+        if let Err(e) = request.respond(response) {
+            println!("[slapaman] failed to send metrics response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn render_metrics(timeout: Duration) -> Result<String, String> {
+    let instances: Vec<InstanceMetrics> = get_all_servers()?
+        .iter()
+        .map(|server| collect_instance(server, timeout))
+        .collect();
+
+    let mut out = String::new();
+    family(
+        &mut out,
+        "slapaman_up",
+        "gauge",
+        "whether the instance's server process is running",
+        instances
+            .iter()
+            .map(|i| (i.labels.clone(), i.up as u8 as f64))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_process_cpu_seconds",
+        "counter",
+        "CPU time used by the server process",
+        process_samples(&instances, |p| p.cpu_seconds()),
+    );
+    family(
+        &mut out,
+        "slapaman_process_resident_memory_bytes",
+        "gauge",
+        "resident memory of the server process",
+        process_samples(&instances, |p| p.rss_bytes as f64),
+    );
+    family(
+        &mut out,
+        "slapaman_process_max_heap_bytes",
+        "gauge",
+        "maximum Java heap size (-Xmx) of the server process",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.process.as_ref()?.xmx_bytes? as f64)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_process_threads",
+        "gauge",
+        "threads in the server process",
+        process_samples(&instances, |p| p.threads as f64),
+    );
+    family(
+        &mut out,
+        "slapaman_process_uptime_seconds",
+        "gauge",
+        "how long the server process has been running",
+        process_samples(&instances, |p| p.uptime_secs as f64),
+    );
+    family(
+        &mut out,
+        "slapaman_players_online",
+        "gauge",
+        "players online, from Server List Ping",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.players?.0 as f64)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_players_max",
+        "gauge",
+        "player limit, from Server List Ping",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.players?.1 as f64)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_ping_latency_seconds",
+        "gauge",
+        "Server List Ping round trip time",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.players?.2? / 1000.0)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_tps",
+        "gauge",
+        "ticks per second averaged over a window (Paper, over RCON)",
+        windowed(&instances, |i| &i.tps, &TPS_WINDOWS),
+    );
+    family(
+        &mut out,
+        "slapaman_mspt",
+        "gauge",
+        "average milliseconds per tick over a window (Paper, over RCON)",
+        windowed(&instances, |i| &i.mspt, &MSPT_WINDOWS),
+    );
+    family(
+        &mut out,
+        "slapaman_restarts",
+        "counter",
+        "times slapaman has restarted the instance",
+        instances
+            .iter()
+            .map(|i| (i.labels.clone(), i.restarts as f64))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_last_backup_age_seconds",
+        "gauge",
+        "time since the newest world backup was written",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.last_backup?.0)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_last_backup_size_bytes",
+        "gauge",
+        "size of the newest world backup",
+        instances
+            .iter()
+            .filter_map(|i| Some((i.labels.clone(), i.last_backup?.1 as f64)))
+            .collect(),
+    );
+    family(
+        &mut out,
+        "slapaman_backups_size_bytes",
+        "gauge",
+        "total size of the instance's backups directory",
+        instances
+            .iter()
+            .map(|i| (i.labels.clone(), i.backups_bytes as f64))
+            .collect(),
+    );
+    out.push_str("# EOF\n");
+
+    Ok(out)
+}
+
+fn collect_instance(server: &Server, timeout: Duration) -> InstanceMetrics {
+    let server_dir = server.path.join(&server.name);
+    let pid = running_pid(&server_dir);
+    let process = pid.and_then(|pid| ProcessSample::read(pid).ok());
+
+    // only talk to servers that are running, so stopped ones don't cost a timeout each scrape
+    let players = match pid {
+        Some(_) => server_address(&server_dir)
+            .and_then(|(host, port)| ping(&host, port, timeout))
+            .map(|r| {
+                (
+                    r.online_players,
+                    r.max_players,
+                    r.latency_ms.map(|ms| ms as f64),
+                )
+            })
+            .ok(),
+        None => None,
+    };
+    let (tps, mspt) = match (pid, server.flavor.as_str()) {
+        (Some(_), "paper") => paper_tick_stats(server, timeout),
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    let last_backup = latest_backup(&server_dir).map(|(path, modified)| {
+        let age = SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default()
+            .as_secs_f64();
+        (age, dir_size(&path))
+    });
+
+    InstanceMetrics {
+        labels: format!(
+            "name=\"{}\",flavor=\"{}\",version=\"{}\"",
+            escape_label(&server.name),
+            escape_label(&server.flavor),
+            escape_label(&server.version)
+        ),
+        up: pid.is_some(),
+        process,
+        players,
+        tps,
+        mspt,
+        restarts: server.restarts,
+        last_backup,
+        backups_bytes: dir_size(&server_dir.join(BACKUPS_DIR_NAME)),
+    }
+}
+
+// Paper answers `tps` and `mspt` on the console; without RCON there's no way to ask
+fn paper_tick_stats(server: &Server, timeout: Duration) -> (Vec<f64>, Vec<f64>) {
+    let server_dir = server.path.join(&server.name);
+    let mut client = match rcon_address(&server_dir) {
+        Ok(Some((host, port, password))) => {
+            match RconClient::connect(&host, port, &password, timeout) {
+                Ok(client) => client,
+                Err(_) => return (Vec::new(), Vec::new()),
+            }
+        }
+        _ => return (Vec::new(), Vec::new()),
+    };
+
+    let tps = client
+        .command("tps")
+        .map(|reply| parse_tps(&reply))
+        .unwrap_or_default();
+    let mspt = client
+        .command("mspt")
+        .map(|reply| parse_mspt(&reply))
+        .unwrap_or_default();
+    (tps, mspt)
+}
+
+// "TPS from last 1m, 5m, 15m: 20.0, *20.0, 19.87"
+fn parse_tps(reply: &str) -> Vec<f64> {
+    let reply = strip_formatting(reply);
+    let values = reply.rsplit_once(':').map(|(_, v)| v).unwrap_or_default();
+    NUMBER_RE
+        .find_iter(values)
+        .filter_map(|m| m.as_str().parse().ok())
+        .take(TPS_WINDOWS.len())
+        .collect()
+}
+
+// "Server tick times (avg/min/max) from last 5s, 10s, 1m:\n1.2/0.8/3.4, 1.1/0.7/3.5, 1.3/0.6/4.0"
+fn parse_mspt(reply: &str) -> Vec<f64> {
+    let reply = strip_formatting(reply);
+    let values = reply.rsplit_once(':').map(|(_, v)| v).unwrap_or_default();
+    TICK_TIMES_RE
+        .captures_iter(values)
+        .filter_map(|c| c[1].parse().ok())
+        .take(MSPT_WINDOWS.len())
+        .collect()
+}
+
+// drop § color codes
+fn strip_formatting(text: &str) -> String {
+    FORMATTING_RE.replace_all(text, "").to_string()
+}
+
+fn process_samples(
+    instances: &[InstanceMetrics],
+    value: impl Fn(&ProcessSample) -> f64,
+) -> Vec<(String, f64)> {
+    instances
+        .iter()
+        .filter_map(|i| Some((i.labels.clone(), value(i.process.as_ref()?))))
+        .collect()
+}
+
+fn windowed(
+    instances: &[InstanceMetrics],
+    values: impl Fn(&InstanceMetrics) -> &Vec<f64>,
+    windows: &[&str],
+) -> Vec<(String, f64)> {
+    instances
+        .iter()
+        .flat_map(|i| {
+            values(i)
+                .iter()
+                .zip(windows)
+                .map(|(value, window)| (format!("{},window=\"{}\"", i.labels, window), *value))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// write one metric family; counters get the _total suffix on their samples
+fn family(out: &mut String, name: &str, kind: &str, help: &str, samples: Vec<(String, f64)>) {
+    if samples.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let suffix = if kind == "counter" { "_total" } else { "" };
+    for (labels, value) in samples {
+        let _ = writeln!(out, "{}{}{{{}}} {}", name, suffix, labels, value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tps_from_spigot_and_paper() {
+        assert_eq!(
+            parse_tps("TPS from last 1m, 5m, 15m: 20.0, 19.98, 19.87"),
+            vec![20.0, 19.98, 19.87]
+        );
+        // Paper colours each value and stars those it capped at 20
+        assert_eq!(
+            parse_tps("§6TPS from last 1m, 5m, 15m: §e17.5, §a*20.0, §c12.3"),
+            vec![17.5, 20.0, 12.3]
+        );
+        assert!(parse_tps("Unknown command. Type \"/help\" for help.").is_empty());
+    }
+
+    #[test]
+    fn mspt_keeps_the_average_of_each_window() {
+        assert_eq!(
+            parse_mspt(
+                "Server tick times (avg/min/max) from last 5s, 10s, 1m:\n1.2/0.8/3.4, 1.1/0.7/3.5, 1.3/0.6/4.0"
+            ),
+            vec![1.2, 1.1, 1.3]
+        );
+        assert_eq!(
+            parse_mspt(
+                "§6Server tick times §e(§7avg§e/§7min§e/§7max§e)§6 from last 5s§7,§6 10s§7,§6 1m§e:\n§6◴ §a12.5§7/§a3.1§7/§c61.0§e, §a9.8§7/§a2.9§7/§c61.0§e, §a10.0§7/§a2.7§7/§c75.2"
+            ),
+            vec![12.5, 9.8, 10.0]
+        );
+        assert!(parse_mspt("").is_empty());
+    }
+
+    #[test]
+    fn formatting_codes_are_dropped() {
+        assert_eq!(strip_formatting("§l§6Bold §rgold"), "Bold gold");
+        assert_eq!(strip_formatting("no codes"), "no codes");
+    }
+}
@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+// level.dat is tiny; anything bigger than this isn't one
+const MAX_LEVEL_DAT_SIZE: u64 = 16 * 1024 * 1024;
+// deeper nesting than this only shows up in files crafted to blow the stack
+const MAX_DEPTH: usize = 512;
+
+// one value of Minecraft's Named Binary Tag format
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i32> {
+        match self {
+            Tag::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+// the game version a world was last saved with, as level.dat records it
+#[derive(serde_derive::Serialize, Clone, Debug)]
+pub struct WorldVersion {
+    // e.g. "1.21.4"; worlds from before 1.9 don't record it
+    pub name: Option<String>,
+    pub data_version: Option<i32>,
+}
+
+impl WorldVersion {
+    pub fn describe(&self) -> String {
+        match (&self.name, self.data_version) {
+            (Some(name), _) => name.clone(),
+            (None, Some(data_version)) => format!("data version {}", data_version),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+}
+
+// read the version out of a (gzipped) level.dat
+pub fn read_world_version(level_dat: impl Read) -> Result<WorldVersion, String> {
+    let mut bytes = Vec::new();
+    GzDecoder::new(level_dat)
+        .take(MAX_LEVEL_DAT_SIZE)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("failed to decompress level.dat: {}", e))?;
+
+    let root = parse(&bytes)?;
+    let data = root.get("Data").ok_or("level.dat has no Data tag")?;
+    Ok(WorldVersion {
+        name: data
+            .get("Version")
+            .and_then(|version| version.get("Name"))
+            .and_then(|name| name.as_str())
+            .map(|name| name.to_string()),
+        data_version: data.get("DataVersion").and_then(|v| v.as_int()),
+    })
+}
+
+// parse an uncompressed NBT document, returning its root tag
+pub fn parse(bytes: &[u8]) -> Result<Tag, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    let kind = reader.u8()?;
+    if kind != 10 {
+        return Err("NBT root is not a compound".to_string());
+    }
+    reader.string()?;
+    reader.payload(kind, 0)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, length: usize) -> Result<&[u8], String> {
+        let end = self
+            .position
+            .checked_add(length)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or("NBT data ends early")?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i16(&mut self) -> Result<i16, String> {
+        Ok(i16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn length(&mut self) -> Result<usize, String> {
+        usize::try_from(self.i32()?).map_err(|_| "negative NBT length".to_string())
+    }
+
+    // NBT strings are "modified UTF-8", which is plain UTF-8 for anything a world name holds
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.i16()? as u16 as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).to_string())
+    }
+
+    fn payload(&mut self, kind: u8, depth: usize) -> Result<Tag, String> {
+        if depth > MAX_DEPTH {
+            return Err("NBT is nested too deeply".to_string());
+        }
+
+        Ok(match kind {
+            1 => Tag::Byte(self.u8()? as i8),
+            2 => Tag::Short(self.i16()?),
+            3 => Tag::Int(self.i32()?),
+            4 => Tag::Long(self.i64()?),
+            5 => Tag::Float(f32::from_bits(self.i32()? as u32)),
+            6 => Tag::Double(f64::from_bits(self.i64()? as u64)),
+            7 => {
+                let length = self.length()?;
+                Tag::ByteArray(self.take(length)?.iter().map(|b| *b as i8).collect())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item_kind = self.u8()?;
+                let length = self.length()?;
+                let mut items = Vec::new();
+                for _ in 0..length {
+                    items.push(self.payload(item_kind, depth + 1)?);
+                }
+                Tag::List(items)
+            }
+            10 => {
+                let mut map = HashMap::new();
+                loop {
+                    let item_kind = self.u8()?;
+                    if item_kind == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    map.insert(name, self.payload(item_kind, depth + 1)?);
+                }
+                Tag::Compound(map)
+            }
+            11 => {
+                let length = self.length()?;
+                let mut values = Vec::new();
+                for _ in 0..length {
+                    values.push(self.i32()?);
+                }
+                Tag::IntArray(values)
+            }
+            12 => {
+                let length = self.length()?;
+                let mut values = Vec::new();
+                for _ in 0..length {
+                    values.push(self.i64()?);
+                }
+                Tag::LongArray(values)
+            }
+            _ => return Err(format!("unknown NBT tag type {}", kind)),
+        })
+    }
+}
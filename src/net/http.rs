@@ -15,3 +15,26 @@ pub async fn get_request(url: &String) -> Result<Response, String> {
 
     Ok(response)
 }
+
+pub async fn post_json(
+    url: &str,
+    body: &serde_json::Value,
+    timeout: std::time::Duration,
+) -> Result<Response, String> {
+    // a POST request wrapper that sends a JSON body with the same user agent header
+    let client = Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("failed to build HTTP client: {}", e))?;
+    let response = client
+        .post(url)
+        .header("User-Agent", "slapaman/0.1.0 (GitHub: @wyomingwade)")
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        // webhook URLs hold secrets, so keep them out of the error
+        .map_err(|e| format!("failed to send POST request: {}", e.without_url()))?;
+
+    Ok(response)
+}
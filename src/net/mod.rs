@@ -2,3 +2,6 @@
 // Copyright (c) 2025 Wyoming Wade
 
 pub mod http;
+pub mod ping;
+pub mod query;
+pub mod rcon;
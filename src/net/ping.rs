@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use chrono::Utc;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+// "-1" asks the server to report its own protocol version instead of judging ours
+const PING_PROTOCOL_VERSION: i32 = -1;
+const MAX_PACKET_LENGTH: i32 = 2 * 1024 * 1024;
+
+#[derive(serde_derive::Serialize, Clone, Debug)]
+pub struct PingPlayer {
+    pub name: String,
+    pub id: String,
+}
+
+#[derive(serde_derive::Serialize, Clone, Debug)]
+pub struct PingResponse {
+    pub version: String,
+    pub protocol: i64,
+    pub motd: String,
+    pub online_players: i64,
+    pub max_players: i64,
+    pub sample: Vec<PingPlayer>,
+    // None when the server didn't answer the ping that measures it
+    pub latency_ms: Option<u64>,
+    // answered with the pre-1.7 0xFE ping
+    pub legacy: bool,
+}
+
+// Server List Ping: try the modern protocol first, then fall back to the legacy 0xFE ping
+pub fn ping(host: &str, port: u16, timeout: Duration) -> Result<PingResponse, String> {
+    match ping_modern(host, port, timeout) {
+        Ok(response) => Ok(response),
+        Err(modern_err) => ping_legacy(host, port, timeout)
+            .map_err(|legacy_err| format!("{} (legacy ping: {})", modern_err, legacy_err)),
+    }
+}
+
+fn ping_modern(host: &str, port: u16, timeout: Duration) -> Result<PingResponse, String> {
+    let mut stream = connect(host, port, timeout)?;
+
+    // handshake, asking for the status state
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, 0x00);
+    write_varint(&mut handshake, PING_PROTOCOL_VERSION);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    write_varint(&mut handshake, 1);
+    send_packet(&mut stream, &handshake)?;
+
+    // status request
+    send_packet(&mut stream, &[0x00])?;
+    let response = read_packet(&mut stream)?;
+    let mut cursor = response.as_slice();
+    if read_varint(&mut cursor)? != 0x00 {
+        return Err("unexpected packet in reply to status request".to_string());
+    }
+    let mut response = parse_status(&read_string(&mut cursor)?)?;
+
+    // ping/pong for latency; some servers hang up instead of answering, which is fine
+    let mut ping = vec![0x01];
+    let payload = Utc::now().timestamp_millis();
+    ping.extend_from_slice(&payload.to_be_bytes());
+    let sent = Instant::now();
+    response.latency_ms =
+        match send_packet(&mut stream, &ping).and_then(|_| read_packet(&mut stream)) {
+            Ok(_) => Some(sent.elapsed().as_millis() as u64),
+            Err(_) => None,
+        };
+    Ok(response)
+}
+
+// the JSON a server answers a status request with
+fn parse_status(json: &str) -> Result<PingResponse, String> {
+    let status: Value =
+        serde_json::from_str(json).map_err(|e| format!("invalid status JSON: {}", e))?;
+    let sample = status["players"]["sample"]
+        .as_array()
+        .map(|players| {
+            players
+                .iter()
+                .map(|p| PingPlayer {
+                    name: p["name"].as_str().unwrap_or_default().to_string(),
+                    id: p["id"].as_str().unwrap_or_default().to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(PingResponse {
+        version: status["version"]["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        protocol: status["version"]["protocol"].as_i64().unwrap_or(0),
+        motd: strip_formatting(&flatten_text(&status["description"])),
+        online_players: status["players"]["online"].as_i64().unwrap_or(0),
+        max_players: status["players"]["max"].as_i64().unwrap_or(0),
+        sample,
+        latency_ms: None,
+        legacy: false,
+    })
+}
+
+fn ping_legacy(host: &str, port: u16, timeout: Duration) -> Result<PingResponse, String> {
+    let mut stream = connect(host, port, timeout)?;
+
+    let sent = Instant::now();
+    stream
+        .write_all(&[0xFE, 0x01])
+        .map_err(|e| format!("failed to send legacy ping: {}", e))?;
+
+    let mut header = [0u8; 3];
+    stream
+        .read_exact(&mut header)
+        .map_err(|e| format!("failed to read legacy ping: {}", e))?;
+    let latency_ms = Some(sent.elapsed().as_millis() as u64);
+    if header[0] != 0xFF {
+        return Err("unexpected reply to legacy ping".to_string());
+    }
+
+    // the reply is a kick message in UTF-16BE
+    let length = u16::from_be_bytes([header[1], header[2]]) as usize;
+    let mut raw = vec![0u8; length * 2];
+    stream
+        .read_exact(&mut raw)
+        .map_err(|e| format!("failed to read legacy ping: {}", e))?;
+    let mut response = parse_legacy(&raw)?;
+    response.latency_ms = latency_ms;
+    Ok(response)
+}
+
+// the kick message a pre-1.7 server answers 0xFE with, as UTF-16BE
+fn parse_legacy(raw: &[u8]) -> Result<PingResponse, String> {
+    let units = raw
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect::<Vec<u16>>();
+    let text = String::from_utf16_lossy(&units);
+
+    // 1.4 - 1.6: §1\0protocol\0version\0motd\0online\0max
+    if let Some(rest) = text.strip_prefix("\u{a7}1\0") {
+        let fields = rest.split('\0').collect::<Vec<&str>>();
+        if fields.len() >= 5 {
+            return Ok(PingResponse {
+                version: fields[1].to_string(),
+                protocol: fields[0].parse().unwrap_or(0),
+                motd: strip_formatting(fields[2]),
+                online_players: fields[3].parse().unwrap_or(0),
+                max_players: fields[4].parse().unwrap_or(0),
+                sample: Vec::new(),
+                latency_ms: None,
+                legacy: true,
+            });
+        }
+    }
+
+    // beta 1.8 - 1.3: motd§online§max
+    let fields = text.rsplitn(3, '\u{a7}').collect::<Vec<&str>>();
+    if fields.len() == 3 {
+        return Ok(PingResponse {
+            version: String::new(),
+            protocol: 0,
+            motd: fields[2].to_string(),
+            online_players: fields[1].parse().unwrap_or(0),
+            max_players: fields[0].parse().unwrap_or(0),
+            sample: Vec::new(),
+            latency_ms: None,
+            legacy: true,
+        });
+    }
+
+    Err("unrecognized legacy ping reply".to_string())
+}
+
+fn connect(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, String> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("failed to resolve {}:{}: {}", host, port, e))?
+        .next()
+        .ok_or_else(|| format!("failed to resolve {}:{}", host, port))?;
+    let stream = TcpStream::connect_timeout(&addr, timeout)
+        .map_err(|e| format!("failed to connect to {}:{}: {}", host, port, e))?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| format!("failed to configure connection: {}", e))?;
+    Ok(stream)
+}
+
+fn send_packet(stream: &mut TcpStream, data: &[u8]) -> Result<(), String> {
+    let mut packet = Vec::with_capacity(data.len() + 5);
+    write_varint(&mut packet, data.len() as i32);
+    packet.extend_from_slice(data);
+    stream
+        .write_all(&packet)
+        .map_err(|e| format!("failed to send packet: {}", e))
+}
+
+fn read_packet(stream: &mut TcpStream) -> Result<Vec<u8>, String> {
+    let length = read_varint(stream)?;
+    if !(0..=MAX_PACKET_LENGTH).contains(&length) {
+        return Err(format!("invalid packet length: {}", length));
+    }
+    let mut data = vec![0u8; length as usize];
+    stream
+        .read_exact(&mut data)
+        .map_err(|e| format!("failed to read packet: {}", e))?;
+    Ok(data)
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+        buf.push((value & 0x7F | 0x80) as u8);
+        value >>= 7;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<i32, String> {
+    let mut value = 0u32;
+    for position in 0..5 {
+        let mut byte = [0u8; 1];
+        reader
+            .read_exact(&mut byte)
+            .map_err(|e| format!("failed to read VarInt: {}", e))?;
+        value |= ((byte[0] & 0x7F) as u32) << (7 * position);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err("VarInt is too long".to_string())
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as i32);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn read_string(cursor: &mut &[u8]) -> Result<String, String> {
+    let length = read_varint(cursor)? as usize;
+    if length > cursor.len() {
+        return Err("string runs past the end of the packet".to_string());
+    }
+    let (value, rest) = cursor.split_at(length);
+    *cursor = rest;
+    Ok(String::from_utf8_lossy(value).to_string())
+}
+
+// MOTDs are either plain strings or chat components with nested "extra" parts
+fn flatten_text(component: &Value) -> String {
+    match component {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts.iter().map(flatten_text).collect(),
+        Value::Object(_) => {
+            let mut text = component["text"].as_str().unwrap_or_default().to_string();
+            if let Some(extra) = component["extra"].as_array() {
+                text.extend(extra.iter().map(flatten_text));
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+// drop § formatting codes
+fn strip_formatting(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{a7}' {
+            chars.next();
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(value: i32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, value);
+        buf
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        // the examples from the protocol documentation
+        for (value, encoded) in [
+            (0, vec![0x00]),
+            (1, vec![0x01]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (25565, vec![0xdd, 0xc7, 0x01]),
+            (2097151, vec![0xff, 0xff, 0x7f]),
+            (i32::MAX, vec![0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-1, vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+            (i32::MIN, vec![0x80, 0x80, 0x80, 0x80, 0x08]),
+        ] {
+            assert_eq!(varint(value), encoded, "{}", value);
+            assert_eq!(read_varint(&mut encoded.as_slice()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn varints_reject_malformed_input() {
+        assert!(read_varint(&mut [0xffu8; 6].as_slice()).is_err());
+        assert!(read_varint(&mut [0x80u8, 0x80].as_slice()).is_err());
+        assert!(read_varint(&mut [].as_slice()).is_err());
+    }
+
+    #[test]
+    fn strings_round_trip_and_stay_in_the_packet() {
+        let mut buf = Vec::new();
+        write_string(&mut buf, "play.example.com");
+        buf.push(0x2a);
+        let mut cursor = buf.as_slice();
+        assert_eq!(read_string(&mut cursor).unwrap(), "play.example.com");
+        assert_eq!(cursor, [0x2a]);
+
+        let mut long = varint(10);
+        long.extend_from_slice(b"short");
+        assert!(read_string(&mut long.as_slice()).is_err());
+        // a negative length must not be read as a huge one
+        let negative = varint(-1);
+        assert!(read_string(&mut negative.as_slice()).is_err());
+    }
+
+    #[test]
+    fn status_json_with_a_chat_component_motd() {
+        let response = parse_status(
+            r#"{
+                "version": {"name": "Paper 1.21.1", "protocol": 767},
+                "players": {
+                    "max": 20,
+                    "online": 2,
+                    "sample": [
+                        {"name": "Steve", "id": "069a79f4-44e9-4726-a5be-fca90e38aaf5"},
+                        {"name": "Alex", "id": "ec561538-f3fd-461d-aff5-086b22154bce"}
+                    ]
+                },
+                "description": {"text": "", "extra": [
+                    {"text": "A ", "color": "gold"},
+                    "§lMinecraft",
+                    {"text": " Server", "extra": [{"text": "!"}]}
+                ]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(response.version, "Paper 1.21.1");
+        assert_eq!(response.protocol, 767);
+        assert_eq!(response.motd, "A Minecraft Server!");
+        assert_eq!((response.online_players, response.max_players), (2, 20));
+        assert_eq!(response.sample.len(), 2);
+        assert_eq!(response.sample[1].name, "Alex");
+        assert!(!response.legacy);
+    }
+
+    #[test]
+    fn status_json_with_missing_fields() {
+        let response = parse_status(r#"{"description": "§aHello"}"#).unwrap();
+        assert_eq!(response.motd, "Hello");
+        assert_eq!(response.version, "");
+        assert_eq!(response.max_players, 0);
+        assert!(response.sample.is_empty());
+        assert!(parse_status("{\"version\":").is_err());
+    }
+
+    #[test]
+    fn legacy_replies_from_either_era() {
+        // 1.4 - 1.6
+        let response = parse_legacy(&utf16(
+            "\u{a7}1\x0078\x001.6.4\x00\u{a7}6A Server\x003\x0020",
+        ))
+        .unwrap();
+        assert_eq!(response.protocol, 78);
+        assert_eq!(response.version, "1.6.4");
+        assert_eq!(response.motd, "A Server");
+        assert_eq!((response.online_players, response.max_players), (3, 20));
+        assert!(response.legacy);
+
+        // beta 1.8 - 1.3, where the motd may itself contain §
+        let response = parse_legacy(&utf16("A \u{a7}Server\u{a7}3\u{a7}20")).unwrap();
+        assert_eq!(response.motd, "A \u{a7}Server");
+        assert_eq!((response.online_players, response.max_players), (3, 20));
+    }
+
+    #[test]
+    fn legacy_replies_that_are_not_pings() {
+        assert!(parse_legacy(&utf16("Outdated client!")).is_err());
+        assert!(parse_legacy(&utf16("\u{a7}1\x0078\x001.6.4")).is_err());
+        // odd bytes and unpaired surrogates don't panic
+        assert!(parse_legacy(&[0xd8, 0x00, 0x00]).is_err());
+    }
+}
@@ -0,0 +1,324 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+const TYPE_HANDSHAKE: u8 = 0x09;
+const TYPE_STAT: u8 = 0x00;
+// the server only echoes back the low nibble of each byte
+const SESSION_MASK: i32 = 0x0F0F0F0F;
+
+#[derive(serde_derive::Serialize, Clone, Debug, Default)]
+pub struct QueryResponse {
+    pub motd: String,
+    pub game_type: String,
+    pub game_id: String,
+    pub version: String,
+    pub server_mod: String,
+    pub plugins: Vec<String>,
+    pub map: String,
+    pub online_players: i64,
+    pub max_players: i64,
+    pub host_ip: String,
+    pub host_port: u16,
+    // only filled in by a full stat
+    pub players: Vec<String>,
+    pub full: bool,
+}
+
+// GameSpy4 query: handshake for a challenge token, then a basic or full stat
+pub fn query(
+    host: &str,
+    port: u16,
+    full: bool,
+    timeout: Duration,
+) -> Result<QueryResponse, String> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(|e| format!("failed to resolve {}:{}: {}", host, port, e))?
+        .next()
+        .ok_or_else(|| format!("failed to resolve {}:{}", host, port))?;
+    // the socket has to be of the same family as the server's address to reach it
+    let local: SocketAddr = match addr {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).map_err(|e| format!("failed to open UDP socket: {}", e))?;
+    socket
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| format!("failed to configure UDP socket: {}", e))?;
+    socket
+        .connect(addr)
+        .map_err(|e| format!("failed to connect to {}:{}: {}", host, port, e))?;
+
+    let session_id = (std::process::id() as i32) & SESSION_MASK;
+
+    // handshake
+    let mut request = Vec::from(MAGIC);
+    request.push(TYPE_HANDSHAKE);
+    request.extend_from_slice(&session_id.to_be_bytes());
+    let reply = exchange(&socket, &request, TYPE_HANDSHAKE, session_id)?;
+    let token = read_cstring(&mut reply.as_slice())?
+        .parse::<i32>()
+        .map_err(|_| "invalid challenge token".to_string())?;
+
+    // stat
+    let mut request = Vec::from(MAGIC);
+    request.push(TYPE_STAT);
+    request.extend_from_slice(&session_id.to_be_bytes());
+    request.extend_from_slice(&token.to_be_bytes());
+    if full {
+        request.extend_from_slice(&[0, 0, 0, 0]);
+    }
+    let reply = exchange(&socket, &request, TYPE_STAT, session_id)?;
+
+    match full {
+        true => parse_full_stat(&reply),
+        false => parse_basic_stat(&reply),
+    }
+}
+
+// send a request and return the payload of the matching reply
+fn exchange(
+    socket: &UdpSocket,
+    request: &[u8],
+    packet_type: u8,
+    session_id: i32,
+) -> Result<Vec<u8>, String> {
+    socket
+        .send(request)
+        .map_err(|e| format!("failed to send query: {}", e))?;
+
+    let mut buf = [0u8; 65536];
+    let len = socket
+        .recv(&mut buf)
+        .map_err(|e| format!("no reply to query (is enable-query on?): {}", e))?;
+    if len < 5 || buf[0] != packet_type || buf[1..5] != session_id.to_be_bytes() {
+        return Err("unexpected reply to query".to_string());
+    }
+
+    Ok(buf[5..len].to_vec())
+}
+
+fn parse_basic_stat(reply: &[u8]) -> Result<QueryResponse, String> {
+    let mut cursor = reply;
+    let motd = read_cstring(&mut cursor)?;
+    let game_type = read_cstring(&mut cursor)?;
+    let map = read_cstring(&mut cursor)?;
+    let online_players = read_cstring(&mut cursor)?.parse().unwrap_or(0);
+    let max_players = read_cstring(&mut cursor)?.parse().unwrap_or(0);
+    if cursor.len() < 2 {
+        return Err("basic stat is truncated".to_string());
+    }
+    // the one little-endian field in the protocol
+    let host_port = u16::from_le_bytes([cursor[0], cursor[1]]);
+    cursor = &cursor[2..];
+    let host_ip = read_cstring(&mut cursor)?;
+
+    Ok(QueryResponse {
+        motd,
+        game_type,
+        map,
+        online_players,
+        max_players,
+        host_ip,
+        host_port,
+        ..Default::default()
+    })
+}
+
+fn parse_full_stat(reply: &[u8]) -> Result<QueryResponse, String> {
+    // "splitnum\0\x80\0" pads the start of the key/value section
+    let mut cursor = reply
+        .get(11..)
+        .ok_or_else(|| "full stat is truncated".to_string())?;
+
+    let mut values = HashMap::new();
+    loop {
+        let key = read_cstring(&mut cursor)?;
+        if key.is_empty() {
+            break;
+        }
+        let value = read_cstring(&mut cursor)?;
+        values.insert(key, value);
+    }
+
+    // "\x01player_\0\0" pads the start of the player section
+    cursor = cursor.get(10..).unwrap_or_default();
+    let mut players = Vec::new();
+    while !cursor.is_empty() {
+        let player = read_cstring(&mut cursor)?;
+        if player.is_empty() {
+            break;
+        }
+        players.push(player);
+    }
+
+    let value = |key: &str| values.get(key).cloned().unwrap_or_default();
+
+    // "Paper on 1.21.1: SomePlugin 1.0; OtherPlugin 2.3" (empty on vanilla)
+    let (server_mod, plugins) = match value("plugins").split_once(':') {
+        Some((server_mod, plugins)) => (
+            server_mod.trim().to_string(),
+            plugins
+                .split(';')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        ),
+        None => (value("plugins").trim().to_string(), Vec::new()),
+    };
+
+    Ok(QueryResponse {
+        motd: value("hostname"),
+        game_type: value("gametype"),
+        game_id: value("game_id"),
+        version: value("version"),
+        server_mod,
+        plugins,
+        map: value("map"),
+        online_players: value("numplayers").parse().unwrap_or(0),
+        max_players: value("maxplayers").parse().unwrap_or(0),
+        host_ip: value("hostip"),
+        host_port: value("hostport").parse().unwrap_or(0),
+        players,
+        full: true,
+    })
+}
+
+fn read_cstring(cursor: &mut &[u8]) -> Result<String, String> {
+    let end = cursor
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| "unterminated string in query reply".to_string())?;
+    // query strings are Latin-1 on the wire
+    let value = cursor[..end].iter().map(|b| *b as char).collect();
+    *cursor = &cursor[end + 1..];
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // a full stat payload as Paper sends it, after the type and session id
+    fn full_stat(plugins: &str, players: &[&str]) -> Vec<u8> {
+        let mut reply = b"splitnum\0\x80\0".to_vec();
+        for (key, value) in [
+            ("hostname", "A Minecraft Server"),
+            ("gametype", "SMP"),
+            ("game_id", "MINECRAFT"),
+            ("version", "1.21.1"),
+            ("plugins", plugins),
+            ("map", "world"),
+            ("numplayers", "2"),
+            ("maxplayers", "20"),
+            ("hostport", "25565"),
+            ("hostip", "0.0.0.0"),
+        ] {
+            reply.extend_from_slice(key.as_bytes());
+            reply.push(0);
+            reply.extend_from_slice(value.as_bytes());
+            reply.push(0);
+        }
+        reply.extend_from_slice(b"\0\x01player_\0\0");
+        for player in players {
+            reply.extend_from_slice(player.as_bytes());
+            reply.push(0);
+        }
+        reply.push(0);
+        reply
+    }
+
+    #[test]
+    fn full_stat_reads_values_plugins_and_players() {
+        let reply = full_stat(
+            "Paper on 1.21.1-R0.1-SNAPSHOT: LuckPerms 5.4.137; WorldEdit 7.3.6",
+            &["Steve", "Alex"],
+        );
+        let response = parse_full_stat(&reply).unwrap();
+        assert!(response.full);
+        assert_eq!(response.motd, "A Minecraft Server");
+        assert_eq!(response.game_type, "SMP");
+        assert_eq!(response.game_id, "MINECRAFT");
+        assert_eq!(response.version, "1.21.1");
+        assert_eq!(response.map, "world");
+        assert_eq!(response.server_mod, "Paper on 1.21.1-R0.1-SNAPSHOT");
+        assert_eq!(response.plugins, ["LuckPerms 5.4.137", "WorldEdit 7.3.6"]);
+        assert_eq!((response.online_players, response.max_players), (2, 20));
+        assert_eq!(
+            (response.host_ip.as_str(), response.host_port),
+            ("0.0.0.0", 25565)
+        );
+        assert_eq!(response.players, ["Steve", "Alex"]);
+    }
+
+    #[test]
+    fn full_stat_from_vanilla_has_no_plugins_or_players() {
+        let response = parse_full_stat(&full_stat("", &[])).unwrap();
+        assert_eq!(response.server_mod, "");
+        assert!(response.plugins.is_empty());
+        assert!(response.players.is_empty());
+    }
+
+    #[test]
+    fn full_stat_rejects_truncated_replies() {
+        let reply = full_stat("", &["Steve"]);
+        assert!(parse_full_stat(&reply[..5]).is_err());
+        // cut off in the middle of a value
+        assert!(parse_full_stat(&reply[..20]).is_err());
+        // cut off in the middle of a player's name
+        assert!(parse_full_stat(&reply[..reply.len() - 3]).is_err());
+    }
+
+    #[test]
+    fn basic_stat_reads_the_little_endian_port() {
+        let reply = b"A Minecraft Server\0SMP\0world\x002\x0020\0\xdd\x63127.0.0.1\0";
+        let response = parse_basic_stat(reply).unwrap();
+        assert_eq!(response.motd, "A Minecraft Server");
+        assert_eq!((response.online_players, response.max_players), (2, 20));
+        assert_eq!(response.host_port, 25565);
+        assert_eq!(response.host_ip, "127.0.0.1");
+        assert!(!response.full);
+        assert!(parse_basic_stat(b"motd\0SMP\0world\x002\x0020\0\xdd").is_err());
+    }
+
+    // answer one handshake and one stat request the way a server does
+    fn serve_query(socket: UdpSocket) {
+        let mut buf = [0u8; 1500];
+        for packet_type in [TYPE_HANDSHAKE, TYPE_STAT] {
+            let (len, peer) = socket.recv_from(&mut buf).unwrap();
+            assert_eq!(buf[..3], [MAGIC[0], MAGIC[1], packet_type]);
+            let mut reply = vec![packet_type];
+            reply.extend_from_slice(&buf[3..7]);
+            match packet_type {
+                TYPE_HANDSHAKE => reply.extend_from_slice(b"9513307\0"),
+                _ => {
+                    assert_eq!(buf[7..11], 9513307i32.to_be_bytes());
+                    assert_eq!(len, 15);
+                    reply.extend_from_slice(&full_stat("", &["Steve"]));
+                }
+            }
+            socket.send_to(&reply, peer).unwrap();
+        }
+    }
+
+    #[test]
+    fn queries_servers_over_either_address_family() {
+        for host in ["127.0.0.1", "::1"] {
+            let Ok(server) = UdpSocket::bind((host, 0)) else {
+                // no IPv6 loopback here
+                continue;
+            };
+            let port = server.local_addr().unwrap().port();
+            let serving = thread::spawn(move || serve_query(server));
+            let response = query(host, port, true, Duration::from_secs(5)).unwrap();
+            serving.join().unwrap();
+            assert_eq!(response.players, ["Steve"]);
+        }
+    }
+}
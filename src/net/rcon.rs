@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const TYPE_LOGIN: i32 = 3;
+const TYPE_COMMAND: i32 = 2;
+const MAX_PACKET_LENGTH: i32 = 4096 + 10;
+
+// a client for the Source RCON protocol that Minecraft speaks when enable-rcon=true
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl RconClient {
+    pub fn connect(
+        host: &str,
+        port: u16,
+        password: &str,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let addr = (host, port)
+            .to_socket_addrs()
+            .map_err(|e| format!("failed to resolve {}:{}: {}", host, port, e))?
+            .next()
+            .ok_or_else(|| format!("failed to resolve {}:{}", host, port))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout)
+            .map_err(|e| format!("failed to connect to RCON at {}:{}: {}", host, port, e))?;
+        stream
+            .set_read_timeout(Some(timeout))
+            .and_then(|_| stream.set_write_timeout(Some(timeout)))
+            .map_err(|e| format!("failed to configure RCON connection: {}", e))?;
+
+        let mut client = Self { stream, next_id: 1 };
+
+        // a failed login is answered with a request id of -1
+        let id = client.send(TYPE_LOGIN, password)?;
+        let (reply_id, _) = client.receive()?;
+        if reply_id == -1 || reply_id != id {
+            return Err("RCON login failed (check rcon.password)".to_string());
+        }
+
+        Ok(client)
+    }
+
+    pub fn command(&mut self, command: &str) -> Result<String, String> {
+        let id = self.send(TYPE_COMMAND, command)?;
+        let (reply_id, body) = self.receive()?;
+        if reply_id != id {
+            return Err("unexpected RCON reply".to_string());
+        }
+        Ok(body)
+    }
+
+    fn send(&mut self, packet_type: i32, body: &str) -> Result<i32, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut packet = Vec::with_capacity(body.len() + 14);
+        packet.extend_from_slice(&((body.len() + 10) as i32).to_le_bytes());
+        packet.extend_from_slice(&id.to_le_bytes());
+        packet.extend_from_slice(&packet_type.to_le_bytes());
+        packet.extend_from_slice(body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        self.stream
+            .write_all(&packet)
+            .map_err(|e| format!("failed to send RCON packet: {}", e))?;
+        Ok(id)
+    }
+
+    fn receive(&mut self) -> Result<(i32, String), String> {
+        let mut header = [0u8; 12];
+        self.stream
+            .read_exact(&mut header)
+            .map_err(|e| format!("failed to read RCON reply: {}", e))?;
+        let length = i32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let id = i32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if !(10..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(format!("invalid RCON packet length: {}", length));
+        }
+
+        // the id and type are part of the length, the body ends in two nulls
+        let mut body = vec![0u8; length as usize - 8];
+        self.stream
+            .read_exact(&mut body)
+            .map_err(|e| format!("failed to read RCON reply: {}", e))?;
+        body.truncate(body.len() - 2);
+
+        Ok((id, String::from_utf8_lossy(&body).to_string()))
+    }
+}
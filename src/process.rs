@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub fn pid_alive(pid: u32) -> bool {
+    if cfg!(target_os = "linux") {
+        return Path::new("/proc").join(pid.to_string()).exists();
+    }
+
+    signal(pid, "0")
+}
+
+// ask a process to exit, and kill it if it hasn't after `grace`
+pub fn terminate(pid: u32, grace: Duration) -> Result<(), String> {
+    if !pid_alive(pid) {
+        return Ok(());
+    }
+
+    if cfg!(windows) {
+        let status = Command::new("taskkill")
+            .args(["/F", "/PID", &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| format!("failed to run taskkill: {}", e))?;
+        return match status.success() {
+            true => Ok(()),
+            false => Err(format!("failed to kill process {}", pid)),
+        };
+    }
+
+    signal(pid, "TERM");
+    let started = Instant::now();
+    while started.elapsed() < grace {
+        if !pid_alive(pid) {
+            return Ok(());
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+
+    signal(pid, "KILL");
+    thread::sleep(Duration::from_millis(500));
+    match pid_alive(pid) {
+        true => Err(format!("process {} survived SIGKILL", pid)),
+        false => Ok(()),
+    }
+}
+
+// send a signal with kill(1), e.g. "TERM", "KILL", "QUIT", or "0" to check for a process
+pub fn signal(pid: u32, signal: &str) -> bool {
+    Command::new("kill")
+        .arg(format!("-{}", signal))
+        .arg(pid.to_string())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|s| s.success())
+        .unwrap_or(false)
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const PROPERTIES_FILE: &str = "server.properties";
+const DEFAULT_SERVER_PORT: u16 = 25565;
+const DEFAULT_RCON_PORT: u16 = 25575;
+
+// read an instance's server.properties (missing file = no properties yet)
+pub fn read_properties(server_dir: &Path) -> Result<HashMap<String, String>, String> {
+    let path = server_dir.join(PROPERTIES_FILE);
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+    let mut properties = HashMap::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            properties.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+
+    Ok(properties)
+}
+
+// the address players (and slapaman) should connect to
+pub fn server_address(server_dir: &Path) -> Result<(String, u16), String> {
+    let properties = read_properties(server_dir)?;
+    let port = properties
+        .get("server-port")
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_SERVER_PORT);
+
+    Ok((connect_host(&properties, "server-ip"), port))
+}
+
+// the address of the query listener, or None when enable-query is off
+pub fn query_address(server_dir: &Path) -> Result<Option<(String, u16)>, String> {
+    let properties = read_properties(server_dir)?;
+    if properties.get("enable-query").map(|v| v.as_str()) != Some("true") {
+        return Ok(None);
+    }
+
+    let port = properties
+        .get("query.port")
+        .or_else(|| properties.get("server-port"))
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_SERVER_PORT);
+
+    Ok(Some((connect_host(&properties, "server-ip"), port)))
+}
+
+// the address and password for RCON, or None when enable-rcon is off or has no password
+pub fn rcon_address(server_dir: &Path) -> Result<Option<(String, u16, String)>, String> {
+    let properties = read_properties(server_dir)?;
+    if properties.get("enable-rcon").map(|v| v.as_str()) != Some("true") {
+        return Ok(None);
+    }
+
+    let password = properties.get("rcon.password").cloned().unwrap_or_default();
+    if password.is_empty() {
+        return Ok(None);
+    }
+    let port = properties
+        .get("rcon.port")
+        .and_then(|p| p.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_RCON_PORT);
+
+    Ok(Some((
+        connect_host(&properties, "server-ip"),
+        port,
+        password,
+    )))
+}
+
+// a bind address of "" or 0.0.0.0 means every interface, so connect over loopback
+pub fn connect_host(properties: &HashMap<String, String>, key: &str) -> String {
+    match properties.get(key).map(|h| h.as_str()) {
+        None | Some("") | Some("0.0.0.0") | Some("::") => "127.0.0.1".to_string(),
+        Some(host) => host.to_string(),
+    }
+}
+
+// parse host, host:port, or [ipv6]:port
+pub fn parse_address(address: &str) -> Result<(String, u16), String> {
+    let address = address.trim();
+    if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or_else(|| format!("invalid address: {}", address))?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port
+                .parse::<u16>()
+                .map_err(|_| format!("invalid port: {}", port))?,
+            None => DEFAULT_SERVER_PORT,
+        };
+        return Ok((host.to_string(), port));
+    }
+
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => Ok((
+            host.to_string(),
+            port.parse::<u16>()
+                .map_err(|_| format!("invalid port: {}", port))?,
+        )),
+        _ => Ok((address.to_string(), DEFAULT_SERVER_PORT)),
+    }
+}
+
+// set keys in an instance's server.properties, keeping the rest of the file (comments, order)
+// as it was; keys that aren't there yet are appended
+pub fn set_properties(server_dir: &Path, changes: &[(String, String)]) -> Result<(), String> {
+    for (key, value) in changes {
+        if key.is_empty() || key.contains(['=', ':', '\n', '\r']) || key.trim() != key {
+            return Err(format!("invalid property name: {:?}", key));
+        }
+        if value.contains(['\n', '\r']) {
+            return Err(format!(
+                "invalid value for {}: values must be one line",
+                key
+            ));
+        }
+    }
+
+    let path = server_dir.join(PROPERTIES_FILE);
+    let contents = match path.exists() {
+        true => fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?,
+        false => String::new(),
+    };
+
+    let mut pending: Vec<&(String, String)> = changes.iter().collect();
+    let mut lines: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        let key = match trimmed.split_once('=') {
+            Some((key, _)) if !trimmed.starts_with('#') && !trimmed.starts_with('!') => key.trim(),
+            _ => "",
+        };
+        match pending.iter().position(|(k, _)| k == key) {
+            Some(i) => {
+                let (key, value) = pending.remove(i);
+                lines.push(format!("{}={}", key, value));
+            }
+            None => lines.push(line.to_string()),
+        }
+    }
+    for (key, value) in pending {
+        lines.push(format!("{}={}", key, value));
+    }
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    let temp_path = path.with_extension("properties.tmp");
+    fs::write(&temp_path, output)
+        .map_err(|e| format!("failed to write {}: {}", temp_path.display(), e))?;
+    fs::rename(&temp_path, &path).map_err(|e| format!("failed to save {}: {}", path.display(), e))
+}
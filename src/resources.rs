@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backup::BACKUPS_DIR_NAME;
+use crate::properties::read_properties;
+use crate::run::running_pid;
+use crate::server::{get_all_servers, Server};
+
+const DISK_USAGE_REFRESH: Duration = Duration::from_secs(60);
+
+// one reading of a process's counters from /proc
+#[derive(Clone, Debug)]
+pub struct ProcessSample {
+    pub pid: u32,
+    pub cpu_ticks: u64,
+    pub rss_bytes: u64,
+    pub xmx_bytes: Option<u64>,
+    pub threads: u64,
+    pub open_fds: Option<u64>,
+    pub read_bytes: Option<u64>,
+    pub write_bytes: Option<u64>,
+    pub uptime_secs: u64,
+    taken: Instant,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DiskUsage {
+    pub instance_bytes: u64,
+    pub world_bytes: u64,
+    pub backups_bytes: u64,
+}
+
+impl ProcessSample {
+    pub fn read(pid: u32) -> Result<Self, String> {
+        if !cfg!(target_os = "linux") {
+            return Err("resource usage is only available on Linux".to_string());
+        }
+
+        let proc_dir = Path::new("/proc").join(pid.to_string());
+        let stat = fs::read_to_string(proc_dir.join("stat"))
+            .map_err(|e| format!("failed to read stats for process {}: {}", pid, e))?;
+
+        // the command name can contain spaces, so count fields from after it
+        let fields = stat
+            .rsplit_once(')')
+            .map(|(_, rest)| rest.split_whitespace().collect::<Vec<&str>>())
+            .unwrap_or_default();
+        let field = |n: usize| {
+            fields
+                .get(n - 3)
+                .and_then(|f| f.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let cpu_ticks = field(14) + field(15);
+        let threads = field(20);
+        let start_ticks = field(22);
+
+        let status = fs::read_to_string(proc_dir.join("status")).unwrap_or_default();
+        let rss_bytes = status_value(&status, "VmRSS:").unwrap_or(0) * 1024;
+
+        let io = fs::read_to_string(proc_dir.join("io")).ok();
+        let read_bytes = io.as_deref().and_then(|io| status_value(io, "read_bytes:"));
+        let write_bytes = io
+            .as_deref()
+            .and_then(|io| status_value(io, "write_bytes:"));
+
+        let open_fds = fs::read_dir(proc_dir.join("fd"))
+            .map(|entries| entries.count() as u64)
+            .ok();
+
+        let cmdline = fs::read(proc_dir.join("cmdline")).unwrap_or_default();
+        let xmx_bytes = cmdline
+            .split(|b| *b == 0)
+            .filter_map(|arg| std::str::from_utf8(arg).ok())
+            .find_map(|arg| arg.strip_prefix("-Xmx"))
+            .and_then(parse_java_size);
+
+        let system_uptime = fs::read_to_string("/proc/uptime")
+            .ok()
+            .and_then(|u| u.split_whitespace().next()?.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        let uptime_secs = (system_uptime - start_ticks as f64 / clock_ticks() as f64).max(0.0);
+
+        Ok(Self {
+            pid,
+            cpu_ticks,
+            rss_bytes,
+            xmx_bytes,
+            threads,
+            open_fds,
+            read_bytes,
+            write_bytes,
+            uptime_secs: uptime_secs as u64,
+            taken: Instant::now(),
+        })
+    }
+
+    pub fn cpu_seconds(&self) -> f64 {
+        self.cpu_ticks as f64 / clock_ticks() as f64
+    }
+
+    // CPU usage between an earlier sample and this one (100% = one core)
+    pub fn cpu_percent(&self, earlier: &ProcessSample) -> f64 {
+        let elapsed = self.taken.duration_since(earlier.taken).as_secs_f64();
+        if elapsed <= 0.0 || self.pid != earlier.pid {
+            return 0.0;
+        }
+        let ticks = self.cpu_ticks.saturating_sub(earlier.cpu_ticks) as f64;
+        ticks / clock_ticks() as f64 / elapsed * 100.0
+    }
+}
+
+impl DiskUsage {
+    pub fn measure(server: &Server) -> Self {
+        let server_dir = server.path.join(&server.name);
+        let level_name = read_properties(&server_dir)
+            .ok()
+            .and_then(|p| p.get("level-name").cloned())
+            .filter(|l| !l.is_empty())
+            .unwrap_or_else(|| "world".to_string());
+
+        Self {
+            instance_bytes: dir_size(&server_dir),
+            world_bytes: dir_size(&server_dir.join(level_name)),
+            backups_bytes: dir_size(&server_dir.join(BACKUPS_DIR_NAME)),
+        }
+    }
+}
+
+// a live, refreshing table of every running instance
+pub fn show_top(interval: Duration, once: bool) -> Result<(), String> {
+    let mut previous: Vec<(String, ProcessSample)> = Vec::new();
+    let mut disk: Vec<(String, DiskUsage)> = Vec::new();
+    let mut disk_measured: Option<Instant> = None;
+
+    loop {
+        let servers = get_all_servers()?;
+
+        if disk_measured.is_none_or(|t| t.elapsed() > DISK_USAGE_REFRESH) {
+            disk = servers
+                .iter()
+                .map(|s| (s.name.clone(), DiskUsage::measure(s)))
+                .collect();
+            disk_measured = Some(Instant::now());
+        }
+
+        // the first round needs a baseline to measure CPU usage against
+        if previous.is_empty() {
+            previous = sample_servers(&servers);
+            thread::sleep(Duration::from_millis(500));
+        }
+        let current = sample_servers(&servers);
+
+        let mut out = String::new();
+        if !once {
+            out.push_str("\x1b[2J\x1b[H");
+        }
+        out.push_str(&format!(
+            "{:<20} {:>8} {:>7} {:>21} {:>7} {:>6} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}\n",
+            "NAME",
+            "PID",
+            "CPU%",
+            "RSS / XMX",
+            "THREADS",
+            "FDS",
+            "READ",
+            "WRITTEN",
+            "UPTIME",
+            "INSTANCE",
+            "WORLD",
+            "BACKUPS"
+        ));
+        for server in &servers {
+            let usage = disk
+                .iter()
+                .find(|(name, _)| *name == server.name)
+                .map(|(_, usage)| usage.clone())
+                .unwrap_or_default();
+            let disk_columns = format!(
+                "{:>10} {:>10} {:>10}",
+                format_bytes(usage.instance_bytes),
+                format_bytes(usage.world_bytes),
+                format_bytes(usage.backups_bytes)
+            );
+
+            let sample = current.iter().find(|(name, _)| *name == server.name);
+            match sample {
+                Some((_, sample)) => {
+                    let cpu = previous
+                        .iter()
+                        .find(|(name, _)| *name == server.name)
+                        .map(|(_, earlier)| sample.cpu_percent(earlier))
+                        .unwrap_or(0.0);
+                    out.push_str(&format!(
+                        "{:<20} {:>8} {:>7.1} {:>21} {:>7} {:>6} {:>10} {:>10} {:>10} {}\n",
+                        server.name,
+                        sample.pid,
+                        cpu,
+                        format_memory(sample),
+                        sample.threads,
+                        optional(sample.open_fds, |n| n.to_string()),
+                        optional(sample.read_bytes, format_bytes),
+                        optional(sample.write_bytes, format_bytes),
+                        format_duration(sample.uptime_secs),
+                        disk_columns
+                    ));
+                }
+                None => out.push_str(&format!(
+                    "{:<20} {:>8} {:>7} {:>21} {:>7} {:>6} {:>10} {:>10} {:>10} {}\n",
+                    server.name, "stopped", "-", "-", "-", "-", "-", "-", "-", disk_columns
+                )),
+            }
+        }
+
+        print!("{}", out);
+        let _ = std::io::stdout().flush();
+
+        if once {
+            return Ok(());
+        }
+        previous = current;
+        thread::sleep(interval);
+    }
+}
+
+// one-line resource summary per instance for `list --resources`
+pub fn resource_summaries(servers: &[Server]) -> Vec<String> {
+    let earlier = sample_servers(servers);
+    if !earlier.is_empty() {
+        thread::sleep(Duration::from_millis(250));
+    }
+    let current = sample_servers(servers);
+
+    servers
+        .iter()
+        .map(|server| {
+            let usage = DiskUsage::measure(server);
+            let disk = format!(
+                "disk {} (world {}, backups {})",
+                format_bytes(usage.instance_bytes),
+                format_bytes(usage.world_bytes),
+                format_bytes(usage.backups_bytes)
+            );
+
+            match current.iter().find(|(name, _)| *name == server.name) {
+                Some((_, sample)) => {
+                    let cpu = earlier
+                        .iter()
+                        .find(|(name, _)| *name == server.name)
+                        .map(|(_, e)| sample.cpu_percent(e))
+                        .unwrap_or(0.0);
+                    format!(
+                        "pid {}, cpu {:.1}%, mem {}, {} threads, up {}, {}",
+                        sample.pid,
+                        cpu,
+                        format_memory(sample),
+                        sample.threads,
+                        format_duration(sample.uptime_secs),
+                        disk
+                    )
+                }
+                None => format!("stopped, {}", disk),
+            }
+        })
+        .collect()
+}
+
+fn sample_servers(servers: &[Server]) -> Vec<(String, ProcessSample)> {
+    servers
+        .iter()
+        .filter_map(|server| {
+            let pid = running_pid(&server.path.join(&server.name))?;
+            let sample = ProcessSample::read(pid).ok()?;
+            Some((server.name.clone(), sample))
+        })
+        .collect()
+}
+
+pub fn dir_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| dir_size(&e.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+fn format_memory(sample: &ProcessSample) -> String {
+    match sample.xmx_bytes {
+        Some(xmx) => format!("{} / {}", format_bytes(sample.rss_bytes), format_bytes(xmx)),
+        None => format_bytes(sample.rss_bytes),
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m{:02}s", minutes, secs % 60),
+        (0, _) => format!("{}h{:02}m", hours, minutes),
+        _ => format!("{}d{:02}h", days, hours),
+    }
+}
+
+fn optional<T>(value: Option<T>, format: impl Fn(T) -> String) -> String {
+    value.map(format).unwrap_or_else(|| "-".to_string())
+}
+
+// a JVM size like 2048M, 4g or 524288k, in bytes
+fn parse_java_size(value: &str) -> Option<u64> {
+    let value = value.to_ascii_lowercase();
+    let (digits, multiplier) = match value.chars().last()? {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        't' => (&value[..value.len() - 1], 1024 * 1024 * 1024 * 1024),
+        _ => (value.as_str(), 1),
+    };
+    digits.parse::<u64>().ok().map(|n| n * multiplier)
+}
+
+// "VmRSS:   123456 kB" style lines from /proc
+fn status_value(text: &str, key: &str) -> Option<u64> {
+    text.lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
+// USER_HZ, which /proc reports CPU time in; it can't change while we run, so ask once
+fn clock_ticks() -> u64 {
+    static CLOCK_TICKS: OnceLock<u64> = OnceLock::new();
+    *CLOCK_TICKS.get_or_init(|| {
+        #[cfg(unix)]
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        #[cfg(not(unix))]
+        let ticks = 0;
+        u64::try_from(ticks)
+            .ok()
+            .filter(|ticks| *ticks > 0)
+            .unwrap_or(100)
+    })
+}
@@ -0,0 +1,446 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime, Utc};
+
+use crate::backup::BACKUPS_DIR_NAME;
+use crate::catalog::{list_backups, BackupInfo};
+use crate::integrity::manifest_path;
+use crate::resources::format_bytes;
+use crate::server::Server;
+use crate::snapshot::collect_garbage;
+
+// which backups `world-prune` keeps; a backup is kept if any rule wants it, and with no keep
+// rules at all every backup is kept (only the size limit applies)
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    // the newest N backups
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_last: Option<u32>,
+    // the newest backup of each day, for this many days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_daily: Option<u32>,
+    // the newest backup of each (ISO) week, for this many weeks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_weekly: Option<u32>,
+    // the newest backup of each month, for this many months
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_monthly: Option<u32>,
+    // prune the oldest unprotected backups until they all fit in this many bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_total_size: Option<u64>,
+    // never prune backups that were given a --tag
+    #[serde(default)]
+    pub protect_tagged: bool,
+}
+
+impl RetentionPolicy {
+    fn has_keep_rules(&self) -> bool {
+        self.keep_last.is_some()
+            || self.keep_daily.is_some()
+            || self.keep_weekly.is_some()
+            || self.keep_monthly.is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.has_keep_rules() && self.max_total_size.is_none()
+    }
+
+    pub fn describe(&self) -> String {
+        if self.is_empty() {
+            return "keep everything".to_string();
+        }
+
+        let mut rules = Vec::new();
+        if let Some(n) = self.keep_last {
+            rules.push(format!("last {}", n));
+        }
+        if let Some(n) = self.keep_daily {
+            rules.push(format!("daily for {} days", n));
+        }
+        if let Some(n) = self.keep_weekly {
+            rules.push(format!("weekly for {} weeks", n));
+        }
+        if let Some(n) = self.keep_monthly {
+            rules.push(format!("monthly for {} months", n));
+        }
+        if let Some(size) = self.max_total_size {
+            rules.push(format!("at most {} in total", format_bytes(size)));
+        }
+        if self.protect_tagged {
+            rules.push("tagged backups protected".to_string());
+        }
+        rules.join(", ")
+    }
+}
+
+// what pruning decided for one backup
+pub struct PruneDecision {
+    pub path: PathBuf,
+    pub size: u64,
+    // why the backup is kept, or None when it's pruned
+    pub kept_by: Option<String>,
+}
+
+// apply an instance's retention policy to its backups, returning what was (or, with `dry_run`,
+// would be) pruned
+pub fn prune_backups(name: &String, dry_run: bool) -> Result<Vec<PruneDecision>, String> {
+    let server = Server::load_by_name(name)?;
+    let backups_dir = server.path.join(name).join(BACKUPS_DIR_NAME);
+    let decisions = plan_pruning(&backups_dir, &server.backup.retention)?;
+
+    if !dry_run {
+        for decision in decisions.iter().filter(|d| d.kept_by.is_none()) {
+            let result = match decision.path.is_dir() {
+                true => fs::remove_dir_all(&decision.path),
+                false => fs::remove_file(&decision.path),
+            };
+            result.map_err(|e| format!("failed to remove {}: {}", decision.path.display(), e))?;
+            let _ = fs::remove_file(manifest_path(&decision.path));
+        }
+
+        // chunks only the pruned incremental backups used can go too
+        let (removed, freed) = collect_garbage(&backups_dir)?;
+        if removed > 0 {
+            println!(
+                "[slapaman] removed {} unreferenced chunks ({})",
+                removed,
+                format_bytes(freed)
+            );
+        }
+    }
+
+    Ok(decisions)
+}
+
+// `world-prune`: prune and report each backup's fate
+pub fn prune_world_backups(name: &String, dry_run: bool) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    println!(
+        "[slapaman] retention policy for {}: {}",
+        name,
+        server.backup.retention.describe()
+    );
+
+    let decisions = prune_backups(name, dry_run)?;
+    if decisions.is_empty() {
+        println!("no backups to prune for {}", name);
+        return Ok(());
+    }
+
+    let mut freed = 0;
+    let mut pruned = 0;
+    for decision in &decisions {
+        let file_name = decision
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match &decision.kept_by {
+            Some(reason) => println!("keep   {} ({})", file_name, reason),
+            None => {
+                println!(
+                    "{} {}",
+                    if dry_run { "would prune" } else { "prune " },
+                    file_name
+                );
+                freed += decision.size;
+                pruned += 1;
+            }
+        }
+    }
+
+    println!(
+        "[slapaman] {} {} of {} backups, {}",
+        if dry_run { "would prune" } else { "pruned" },
+        pruned,
+        decisions.len(),
+        match dry_run {
+            true => format!("freeing {}", format_bytes(freed)),
+            false => format!("freed {}", format_bytes(freed)),
+        }
+    );
+
+    Ok(())
+}
+
+fn plan_pruning(
+    backups_dir: &Path,
+    policy: &RetentionPolicy,
+) -> Result<Vec<PruneDecision>, String> {
+    let backups = list_backups(backups_dir)?;
+    Ok(decide_pruning(backups, policy, Utc::now().naive_utc()))
+}
+
+// what `policy` does to `backups` (oldest first, as `list_backups` gives them) at `now`, newest
+// first
+fn decide_pruning(
+    mut backups: Vec<BackupInfo>,
+    policy: &RetentionPolicy,
+    now: NaiveDateTime,
+) -> Vec<PruneDecision> {
+    backups.reverse();
+
+    let mut reasons: Vec<Option<String>> = vec![None; backups.len()];
+
+    if !policy.has_keep_rules() {
+        for reason in reasons.iter_mut() {
+            *reason = Some("no keep rules".to_string());
+        }
+    }
+    if let Some(n) = policy.keep_last {
+        for reason in reasons.iter_mut().take(n as usize) {
+            reason.get_or_insert_with(|| "last".to_string());
+        }
+    }
+    keep_newest_per_period(&backups, &mut reasons, policy.keep_daily, "daily", |t| {
+        let age = (now.date() - t.date()).num_days();
+        (age, t.date().num_days_from_ce() as i64)
+    });
+    keep_newest_per_period(&backups, &mut reasons, policy.keep_weekly, "weekly", |t| {
+        let week_start = |d: chrono::NaiveDate| {
+            d.num_days_from_ce() as i64 - d.weekday().num_days_from_monday() as i64
+        };
+        let age = (week_start(now.date()) - week_start(t.date())) / 7;
+        (age, week_start(t.date()))
+    });
+    keep_newest_per_period(
+        &backups,
+        &mut reasons,
+        policy.keep_monthly,
+        "monthly",
+        |t| {
+            let month = |d: &NaiveDateTime| d.year() as i64 * 12 + d.month0() as i64;
+            (month(&now) - month(t), month(t))
+        },
+    );
+    if policy.protect_tagged {
+        for (backup, reason) in backups.iter().zip(reasons.iter_mut()) {
+            if backup.tag.is_some() {
+                *reason = Some("tagged".to_string());
+            }
+        }
+    }
+    // never leave an instance without any backup at all
+    if let Some(reason) = reasons.first_mut() {
+        reason.get_or_insert_with(|| "newest".to_string());
+    }
+
+    // then trim the oldest kept backups until everything fits, sparing tagged ones if protected
+    // and always the newest
+    if let Some(limit) = policy.max_total_size {
+        let mut total: u64 = backups
+            .iter()
+            .zip(&reasons)
+            .filter(|(_, reason)| reason.is_some())
+            .map(|(backup, _)| backup.size)
+            .sum();
+        for i in (1..backups.len()).rev() {
+            if total <= limit {
+                break;
+            }
+            if reasons[i].is_none() || (policy.protect_tagged && backups[i].tag.is_some()) {
+                continue;
+            }
+            reasons[i] = None;
+            total -= backups[i].size;
+        }
+    }
+
+    backups
+        .into_iter()
+        .zip(reasons)
+        .map(|(backup, kept_by)| PruneDecision {
+            path: backup.path,
+            size: backup.size,
+            kept_by,
+        })
+        .collect()
+}
+
+// keep the newest backup in each of the `periods` most recent periods; `period_of` gives a
+// backup's age in periods and a key identifying its period
+fn keep_newest_per_period(
+    backups: &[BackupInfo],
+    reasons: &mut [Option<String>],
+    periods: Option<u32>,
+    label: &str,
+    period_of: impl Fn(&NaiveDateTime) -> (i64, i64),
+) {
+    let periods = match periods {
+        Some(periods) => periods as i64,
+        None => return,
+    };
+
+    let mut seen = HashSet::new();
+    for (backup, reason) in backups.iter().zip(reasons.iter_mut()) {
+        let (age, key) = period_of(&backup.taken);
+        if age < periods && seen.insert(key) {
+            reason.get_or_insert_with(|| label.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::BackupFormat;
+    use crate::catalog::parse_backup_id;
+
+    // a backup named the way `world-backup` names them, oldest first like `list_backups`
+    fn backups(ids: &[&str]) -> Vec<BackupInfo> {
+        ids.iter()
+            .map(|id| {
+                let (taken, tag) = parse_backup_id(id).unwrap();
+                BackupInfo {
+                    index: 0,
+                    id: id.to_string(),
+                    path: PathBuf::from(id),
+                    taken,
+                    tag,
+                    size: 1,
+                    format: BackupFormat::Dir,
+                    encrypted: false,
+                }
+            })
+            .collect()
+    }
+
+    fn at(timestamp: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(timestamp, "%Y%m%d-%H%M%S").unwrap()
+    }
+
+    // the ids `policy` keeps and why, newest first
+    fn kept(ids: &[&str], policy: &RetentionPolicy, now: &str) -> Vec<(String, String)> {
+        decide_pruning(backups(ids), policy, at(now))
+            .into_iter()
+            .filter_map(|d| Some((d.path.to_string_lossy().to_string(), d.kept_by?)))
+            .collect()
+    }
+
+    fn expected(kept: &[(&str, &str)]) -> Vec<(String, String)> {
+        kept.iter()
+            .map(|(id, reason)| (id.to_string(), reason.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn daily_keeps_the_newest_backup_of_each_recent_day() {
+        let policy = RetentionPolicy {
+            keep_daily: Some(3),
+            ..Default::default()
+        };
+        let ids = [
+            "world-20250306-100000",
+            "world-20250308-100000",
+            "world-20250309-010000",
+            "world-20250309-230000",
+            "world-20250310-020000",
+            "world-20250310-080000",
+        ];
+        assert_eq!(
+            kept(&ids, &policy, "20250310-120000"),
+            expected(&[
+                ("world-20250310-080000", "daily"),
+                ("world-20250309-230000", "daily"),
+                ("world-20250308-100000", "daily"),
+            ])
+        );
+    }
+
+    #[test]
+    fn weekly_buckets_start_on_monday() {
+        let policy = RetentionPolicy {
+            keep_weekly: Some(3),
+            ..Default::default()
+        };
+        // 2025-03-10 and 2025-03-03 are Mondays
+        let ids = [
+            "world-20250220-120000",
+            "world-20250302-120000",
+            "world-20250303-120000",
+            "world-20250309-120000",
+            "world-20250310-120000",
+            "world-20250311-120000",
+        ];
+        assert_eq!(
+            kept(&ids, &policy, "20250312-120000"),
+            expected(&[
+                ("world-20250311-120000", "weekly"),
+                ("world-20250309-120000", "weekly"),
+                ("world-20250302-120000", "weekly"),
+            ])
+        );
+    }
+
+    #[test]
+    fn monthly_counts_months_across_the_new_year() {
+        let policy = RetentionPolicy {
+            keep_monthly: Some(3),
+            ..Default::default()
+        };
+        let ids = [
+            "world-20241030-120000",
+            "world-20241115-120000",
+            "world-20241201-120000",
+            "world-20241231-120000",
+            "world-20250102-120000",
+        ];
+        assert_eq!(
+            kept(&ids, &policy, "20250115-120000"),
+            expected(&[
+                ("world-20250102-120000", "monthly"),
+                ("world-20241231-120000", "monthly"),
+                ("world-20241115-120000", "monthly"),
+            ])
+        );
+    }
+
+    #[test]
+    fn rules_combine_and_the_newest_is_always_kept() {
+        let policy = RetentionPolicy {
+            keep_daily: Some(1),
+            keep_monthly: Some(2),
+            ..Default::default()
+        };
+        let ids = [
+            "world-20250110-120000",
+            "world-20250201-120000",
+            "world-20250220-120000",
+        ];
+        // the newest is days old, so only the monthly rule (and the never-empty rule) apply
+        assert_eq!(
+            kept(&ids, &policy, "20250301-120000"),
+            expected(&[("world-20250220-120000", "monthly")])
+        );
+        assert_eq!(
+            kept(&ids, &policy, "20250601-120000"),
+            expected(&[("world-20250220-120000", "newest")])
+        );
+    }
+
+    #[test]
+    fn two_digit_tags_are_protected_and_collision_suffixes_are_not() {
+        let policy = RetentionPolicy {
+            keep_last: Some(1),
+            protect_tagged: true,
+            ..Default::default()
+        };
+        let ids = [
+            "world-20250101-000000-42",
+            "world-20250101-000000.1",
+            "world-20250102-000000",
+        ];
+        assert_eq!(
+            kept(&ids, &policy, "20250103-000000"),
+            expected(&[
+                ("world-20250102-000000", "last"),
+                ("world-20250101-000000-42", "tagged"),
+            ])
+        );
+    }
+}
@@ -1,9 +1,34 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Wyoming Wade
 
-use std::process::{Command, Stdio};
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::server::Server;
+use serde_json::{json, Value};
+
+use crate::console::{attach_console, detach_console};
+use crate::control::{try_daemon, ControlCommand};
+use crate::events::{EventParser, ServerEvent};
+use crate::java::java_binary_for;
+use crate::logs::{CapturedTail, LogWriter};
+use crate::memory::memory_value_coerced;
+use crate::process::pid_alive;
+use crate::server::{record_restart, Server};
+use crate::systemd::{active_unit, restart_unit};
+use crate::webhooks::{notify, notify_in_background, Notification, WebhookEvent};
+
+pub const PID_FILE_NAME: &str = "slapaman.pid";
+// left by whatever signals a server to stop on purpose, so its `slapaman run` reports a stop
+// rather than a crash
+const STOP_REASON_FILE_NAME: &str = "slapaman.stopping";
 
 pub fn run_server(
     // slapaman params
@@ -12,6 +37,31 @@ pub fn run_server(
     name: String,
     memory: Option<u32>,
     quiet: Option<bool>,
+) -> Result<(), String> {
+    // this is only used for the first run while creating an instance, which just generates its
+    // files, so it doesn't notify webhooks
+    run_instance(name, memory, quiet, false, |_| ())
+}
+
+// run a server in the foreground, handing every event it reports to `on_event`
+pub fn run_server_with_events<F: FnMut(&ServerEvent)>(
+    // slapaman params
+    _verbose: u8,
+    // command args
+    name: String,
+    memory: Option<u32>,
+    quiet: Option<bool>,
+    on_event: F,
+) -> Result<(), String> {
+    run_instance(name, memory, quiet, true, on_event)
+}
+
+fn run_instance<F: FnMut(&ServerEvent)>(
+    name: String,
+    memory: Option<u32>,
+    quiet: Option<bool>,
+    notify_webhooks: bool,
+    mut on_event: F,
 ) -> Result<(), String> {
     println!("[slapaman] starting server: {}", &name);
 
@@ -35,40 +85,132 @@ pub fn run_server(
     }
 
     let server_jar = server_dir.join("server.jar");
-    let memory = memory.unwrap_or(2048); // use the provided memory or default to 2048
+    let java_bin = java_binary_for(&server)?;
+    // use the provided memory, then the instance's stored setting, or default to 2048
+    let memory = memory_value_coerced(memory.or(server.memory));
     let run_quietly = runtime_quiet_coerced(quiet);
 
+    // everything the server prints gets captured, whether or not it's shown
+    let mut log_writer = LogWriter::create(&server_dir)?;
+
     // run the server
-    let mut child = match run_quietly {
+    let mut child = Command::new(&java_bin)
+        .arg(format!("-Xmx{}M", memory))
+        .arg(format!("-Xms{}M", memory))
+        .args(&server.jvm_args)
+        .arg("-jar")
+        .arg(server_jar)
+        .arg("-nogui")
+        .current_dir(&server_dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run server");
+
+    // a reason left over from an earlier run doesn't apply to this one
+    let _ = fs::remove_file(server_dir.join(STOP_REASON_FILE_NAME));
+
+    // let other slapaman commands know which JVM belongs to this instance
+    let pid_file = server_dir.join(PID_FILE_NAME);
+    if let Err(e) = fs::write(&pid_file, child.id().to_string()) {
+        println!("[slapaman] failed to write pid file: {}", e);
+    }
+
+    attach_console(&server_dir, child.stdin.take().unwrap());
+
+    if notify_webhooks {
+        notify_in_background(Notification::new(
+            WebhookEvent::Started,
+            &name,
+            format!("{} is starting", name),
+            json!({ "pid": child.id(), "version": server.version, "flavor": server.flavor }),
+        ));
+    }
+
+    // funnel both output streams through one channel so lines are logged in order
+    let (tx, rx) = mpsc::channel();
+    forward_lines(child.stdout.take().unwrap(), false, tx.clone());
+    forward_lines(child.stderr.take().unwrap(), true, tx);
+
+    let mut parser = EventParser::new();
+    let mut startup_failure = None;
+    let mut log_failed = false;
+    for (is_stderr, line) in rx {
         // when running quietly, we don't want any output
-        true => Command::new("java")
-            .arg(format!("-Xmx{}M", memory))
-            .arg(format!("-Xms{}M", memory))
-            .arg("-jar")
-            .arg(server_jar)
-            .arg("-nogui")
-            .current_dir(server_dir)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-            .expect("failed to run server"),
-        // when running verbosely, we want to see the output
-        false => Command::new("java")
-            .arg(format!("-Xmx{}M", memory))
-            .arg(format!("-Xms{}M", memory))
-            .arg("-jar")
-            .arg(server_jar)
-            .arg("-nogui")
-            .current_dir(server_dir)
-            .spawn()
-            .expect("failed to run server"),
-    };
+        if !run_quietly {
+            match is_stderr {
+                true => eprintln!("{}", line),
+                false => println!("{}", line),
+            }
+        }
+
+        // losing the log shouldn't take the server down with it
+        if let Err(e) = log_writer.write_line(&line) {
+            if !log_failed {
+                println!("[slapaman] {}", e);
+                log_failed = true;
+            }
+        }
+
+        if let Some(event) = parser.parse(&line) {
+            if event.is_startup_failure() {
+                startup_failure = Some(event.clone());
+            }
+            if notify_webhooks {
+                if let Some(notification) = event_notification(&name, &event) {
+                    notify_in_background(notification);
+                }
+            }
+            on_event(&event);
+        }
+    }
 
     // Wait for the server to finish
     let status = child.wait().expect("failed to wait for server");
+    let _ = fs::remove_file(&pid_file);
+    detach_console(&server_dir);
+    let stop_reason = take_stop_reason(&server_dir);
+
+    if let (false, Some(reason)) = (status.success(), &stop_reason) {
+        if notify_webhooks {
+            notify(Notification::new(
+                WebhookEvent::Stopped,
+                &name,
+                format!("{} was stopped: {}", name, reason),
+                json!({ "exit_code": status.code(), "reason": reason }),
+            ));
+        }
+        println!("[slapaman] server was stopped: {} ({})", name, reason);
+        return Ok(());
+    }
 
     if !status.success() {
-        return Err(format!("server exited with error code: {}", status));
+        let error = match startup_failure {
+            Some(ServerEvent::EulaNotAccepted) => "the EULA has not been agreed to".to_string(),
+            Some(ServerEvent::PortBindFailed { message }) => {
+                format!("server could not bind to its port: {}", message)
+            }
+            _ => format!("server exited with error code: {}", status),
+        };
+        if notify_webhooks {
+            notify(Notification::new(
+                WebhookEvent::Crashed,
+                &name,
+                format!("{} went down: {}", name, error),
+                json!({ "exit_code": status.code(), "error": error }),
+            ));
+        }
+        return Err(error);
+    }
+
+    if notify_webhooks {
+        notify(Notification::new(
+            WebhookEvent::Stopped,
+            &name,
+            format!("{} stopped", name),
+            json!({ "exit_code": status.code() }),
+        ));
     }
 
     println!("[slapaman] server finished running: {}", name);
@@ -76,9 +218,207 @@ pub fn run_server(
     Ok(())
 }
 
-fn runtime_quiet_coerced(quiet: Option<bool>) -> bool {
-    match quiet {
-        Some(true) => true,
-        _ => false,
+// record why a running server is about to be sent a signal
+pub fn mark_stop_reason(server_dir: &Path, reason: &str) {
+    if let Err(e) = fs::write(server_dir.join(STOP_REASON_FILE_NAME), reason) {
+        println!(
+            "[slapaman] failed to record why the server is stopping: {}",
+            e
+        );
+    }
+}
+
+fn take_stop_reason(server_dir: &Path) -> Option<String> {
+    let path = server_dir.join(STOP_REASON_FILE_NAME);
+    let reason = fs::read_to_string(&path).ok()?;
+    let _ = fs::remove_file(&path);
+    Some(reason.trim().to_string())
+}
+
+// restart an instance through whatever supervises it, so the new JVM stays in its hands: the
+// instance's systemd unit when that's running it, otherwise the daemon; None when neither does and
+// the caller has to restart it itself
+pub fn restart_supervised(name: &String) -> Option<Result<(), String>> {
+    if let Some(user) = active_unit(name) {
+        return Some(restart_unit(name, user).and_then(|_| record_restart(name)));
+    }
+    try_daemon(ControlCommand::Restart { name: name.clone() }).map(|result| result.map(|_| ()))
+}
+
+// start a server in the background and wait until it reports that it's ready
+pub fn start_server_and_wait_ready(
+    // slapaman params
+    verbose: u8,
+    // command args
+    name: String,
+    memory: Option<u32>,
+    timeout: Duration,
+    use_daemon: bool,
+) -> Result<f64, String> {
+    let server = Server::load_by_name(&name)?;
+    let server_dir = server.path.join(&name);
+    if !server_dir.exists() {
+        return Err(format!("server instance does not exist: {}", &name));
+    }
+
+    // the background slapaman captures the output, so follow its log files for events
+    let mut tail = CapturedTail::new(&server_dir)?;
+
+    // a running daemon takes the server over, so it can supervise it
+    let daemon = match use_daemon {
+        true => try_daemon(ControlCommand::Start {
+            name: name.clone(),
+            memory,
+        }),
+        false => None,
+    };
+    let mut child = match daemon {
+        Some(result) => {
+            result?;
+            None
+        }
+        None => Some(spawn_background_server(&name, memory)?),
+    };
+
+    let mut parser = EventParser::new();
+    let mut seen_running = false;
+    let started = Instant::now();
+    loop {
+        // check for an exit before reading, so the server's final words are never missed
+        let exited = match &mut child {
+            Some(child) => child
+                .try_wait()
+                .map_err(|e| format!("failed to check on server: {}", e))?
+                .map(|status| status.to_string()),
+            // without a child of our own, the pid file coming and going is the exit signal
+            None => match running_pid(&server_dir) {
+                Some(_) => {
+                    seen_running = true;
+                    None
+                }
+                None if seen_running => Some("the server stopped".to_string()),
+                None => None,
+            },
+        };
+
+        for line in tail.poll()? {
+            let event = match parser.parse(&line) {
+                Some(event) => event,
+                None => continue,
+            };
+            if verbose > 0 {
+                println!("[slapaman] {:?}", event);
+            }
+            match event {
+                ServerEvent::Ready { startup_secs } => return Ok(startup_secs),
+                ServerEvent::EulaNotAccepted => {
+                    return Err("the EULA has not been agreed to".to_string())
+                }
+                ServerEvent::PortBindFailed { message } => {
+                    return Err(format!("server could not bind to its port: {}", message))
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(status) = exited {
+            return Err(format!("server exited before it was ready: {}", status));
+        }
+        if started.elapsed() > timeout {
+            return Err(format!(
+                "server was not ready after {}s (it is still starting in the background)",
+                timeout.as_secs()
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+// the pid of the instance's JVM, if slapaman started it and it's still alive
+pub fn running_pid(server_dir: &Path) -> Option<u32> {
+    let pid = fs::read_to_string(server_dir.join(PID_FILE_NAME))
+        .ok()?
+        .trim()
+        .parse::<u32>()
+        .ok()?;
+
+    // a stale pid file is left behind when slapaman itself gets killed
+    if !pid_alive(pid) {
+        return None;
+    }
+
+    Some(pid)
+}
+
+// hand a server off to a detached `slapaman run`, which owns it from then on
+pub fn spawn_background_server(name: &str, memory: Option<u32>) -> Result<Child, String> {
+    let mut command =
+        Command::new(env::current_exe().map_err(|e| format!("failed to locate slapaman: {}", e))?);
+    // the daemon hands servers off to this too, so it must not loop back to the daemon
+    command
+        .arg("run")
+        .arg(name)
+        .arg("--quiet")
+        .arg("--no-daemon");
+    if let Some(memory) = memory {
+        command.arg("--memory").arg(format!("{}M", memory));
     }
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // keep the server alive when the terminal that started it goes away
+    #[cfg(unix)]
+    command.process_group(0);
+
+    command
+        .spawn()
+        .map_err(|e| format!("failed to start server in the background: {}", e))
+}
+
+// the webhook notification for a console event, for the events webhooks can subscribe to
+fn event_notification(name: &str, event: &ServerEvent) -> Option<Notification> {
+    let (kind, message) = match event {
+        ServerEvent::Ready { startup_secs } => (
+            WebhookEvent::Ready,
+            format!("{} is up (started in {}s)", name, startup_secs),
+        ),
+        ServerEvent::PlayerJoin { player, .. } => (
+            WebhookEvent::PlayerJoin,
+            format!("{} joined {}", player, name),
+        ),
+        ServerEvent::PlayerLeave { player, .. } => (
+            WebhookEvent::PlayerLeave,
+            format!("{} left {}", player, name),
+        ),
+        _ => return None,
+    };
+    let details = serde_json::to_value(event).unwrap_or(Value::Null);
+    Some(Notification::new(kind, name, message, details))
+}
+
+fn runtime_quiet_coerced(quiet: Option<bool>) -> bool {
+    matches!(quiet, Some(true))
+}
+
+fn forward_lines<R: Read + Send + 'static>(stream: R, is_stderr: bool, tx: Sender<(bool, String)>) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let line = String::from_utf8_lossy(&buf)
+                        .trim_end_matches(['\r', '\n'])
+                        .to_string();
+                    if tx.send((is_stderr, line)).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
 }
@@ -0,0 +1,320 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashMap;
+use std::fmt;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use croner::Cron;
+
+use crate::backup::create_world_backup;
+use crate::console::{send_console_command, stop_server};
+use crate::run::{restart_supervised, running_pid, spawn_background_server};
+use crate::server::{get_all_servers, record_restart, update_server_by_name, Server};
+use crate::update::check_for_update;
+
+// how often the runner wakes up to look for due tasks
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const RESTART_STOP_TIMEOUT: Duration = Duration::from_secs(90);
+
+// one entry in an instance's schedule, stored in the registry
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct ScheduledTask {
+    pub id: u32,
+    // a standard five-field cron expression, evaluated in local time
+    pub cron: String,
+    #[serde(flatten)]
+    pub action: TaskAction,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+#[serde(tag = "task", rename_all = "snake_case")]
+pub enum TaskAction {
+    Backup { tag: Option<String> },
+    // warnings are broadcast this many seconds before the restart
+    Restart { warnings: Vec<u64> },
+    Command { command: String },
+    UpdateCheck,
+}
+
+impl fmt::Display for TaskAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskAction::Backup { tag: Some(tag) } => write!(f, "backup (tag {})", tag),
+            TaskAction::Backup { tag: None } => write!(f, "backup"),
+            TaskAction::Restart { warnings } => {
+                let warnings: Vec<String> = warnings.iter().map(|w| format!("{}s", w)).collect();
+                match warnings.is_empty() {
+                    true => write!(f, "restart"),
+                    false => write!(f, "restart (warnings at {})", warnings.join(", ")),
+                }
+            }
+            TaskAction::Command { command } => write!(f, "command: {}", command),
+            TaskAction::UpdateCheck => write!(f, "update check"),
+        }
+    }
+}
+
+pub fn add_scheduled_task(
+    name: &String,
+    cron: &str,
+    mut action: TaskAction,
+) -> Result<u32, String> {
+    let mut server = Server::load_by_name(name)?;
+    parse_cron(cron)?;
+
+    if let TaskAction::Restart { warnings } = &mut action {
+        warnings.sort_unstable_by(|a, b| b.cmp(a));
+        warnings.dedup();
+    }
+
+    let id = server.schedule.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+    server.schedule.push(ScheduledTask {
+        id,
+        cron: cron.trim().to_string(),
+        action,
+    });
+    update_server_by_name(name, &server)?;
+
+    Ok(id)
+}
+
+pub fn remove_scheduled_task(name: &String, id: u32) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+    let before = server.schedule.len();
+    server.schedule.retain(|t| t.id != id);
+    if server.schedule.len() == before {
+        return Err(format!("no scheduled task with id {} for {}", id, name));
+    }
+    update_server_by_name(name, &server)
+}
+
+pub fn list_scheduled_tasks(name: &String) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    if server.schedule.is_empty() {
+        println!("no scheduled tasks for {}", name);
+        return Ok(());
+    }
+
+    let now = Local::now();
+    for task in &server.schedule {
+        let next = parse_cron(&task.cron)
+            .and_then(|cron| next_run(&cron, &now))
+            .map(|next| next.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|e| e);
+        println!(
+            "{}: [{}] {} (next: {})",
+            task.id, task.cron, task.action, next
+        );
+    }
+
+    Ok(())
+}
+
+// keeps track of when each task is next due and which ones are still running
+pub struct Scheduler {
+    // keyed by instance, task id and cron expression, so edits to a task take effect right away
+    next_runs: HashMap<(String, u32, String), DateTime<Local>>,
+    running: HashMap<(String, u32), JoinHandle<()>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            next_runs: HashMap::new(),
+            running: HashMap::new(),
+        }
+    }
+
+    // start every task that has come due, each on its own thread through `run`
+    pub fn tick<F>(&mut self, servers: &[Server], run: F)
+    where
+        F: Fn(&String, &TaskAction) -> Result<(), String> + Clone + Send + 'static,
+    {
+        let now = Local::now();
+        for server in servers {
+            for task in &server.schedule {
+                let cron = match parse_cron(&task.cron) {
+                    Ok(cron) => cron,
+                    Err(_) => continue,
+                };
+                let key = (server.name.clone(), task.id, task.cron.clone());
+                let due = match self.next_runs.get(&key) {
+                    Some(due) => *due,
+                    None => {
+                        if let Ok(next) = next_run(&cron, &now) {
+                            self.next_runs.insert(key, next);
+                        }
+                        continue;
+                    }
+                };
+                if due > now {
+                    continue;
+                }
+                if let Ok(next) = next_run(&cron, &now) {
+                    self.next_runs.insert(key, next);
+                }
+
+                // a slow task (e.g. a restart countdown) shouldn't be started twice
+                let task_key = (server.name.clone(), task.id);
+                if self
+                    .running
+                    .get(&task_key)
+                    .is_some_and(|h| !h.is_finished())
+                {
+                    println!(
+                        "[slapaman] scheduler: {} task {} is still running, skipping",
+                        server.name, task.id
+                    );
+                    continue;
+                }
+
+                println!(
+                    "[slapaman] scheduler: running {} task {}: {}",
+                    server.name, task.id, task.action
+                );
+                let name = server.name.clone();
+                let action = task.action.clone();
+                let run = run.clone();
+                self.running.insert(
+                    task_key,
+                    thread::spawn(move || {
+                        if let Err(e) = run(&name, &action) {
+                            println!("[slapaman] scheduler: {} task failed: {}", name, e);
+                        }
+                    }),
+                );
+            }
+        }
+
+        self.running.retain(|_, handle| !handle.is_finished());
+    }
+}
+
+// run scheduled tasks as they come due, until interrupted
+pub fn run_scheduler(names: Vec<String>) -> Result<(), String> {
+    let mut scheduler = Scheduler::new();
+
+    println!("[slapaman] scheduler started");
+    loop {
+        // reload every round, so renamed instances and new tasks are picked up
+        let servers = match names.is_empty() {
+            true => get_all_servers()?,
+            false => names
+                .iter()
+                .map(Server::load_by_name)
+                .collect::<Result<Vec<Server>, String>>()?,
+        };
+
+        scheduler.tick(&servers, run_task);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+pub fn run_task(name: &String, action: &TaskAction) -> Result<(), String> {
+    match action {
+        TaskAction::Backup { tag } => {
+            let path = create_world_backup(0, name.clone(), tag.clone(), None, None)?;
+            println!(
+                "[slapaman] scheduler: backed up {} to {}",
+                name,
+                path.display()
+            );
+            Ok(())
+        }
+        TaskAction::Restart { warnings } => restart_with_countdown(name, warnings),
+        TaskAction::Command { command } => {
+            let server = Server::load_by_name(name)?;
+            send_console_command(&server.path.join(name), command)
+        }
+        TaskAction::UpdateCheck => {
+            let server = Server::load_by_name(name)?;
+            let runtime = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to start async runtime: {}", e))?;
+            match runtime.block_on(check_for_update(&server))? {
+                Some(latest) => println!(
+                    "[slapaman] scheduler: update available for {}: {} -> {} (run `slapaman update {}`)",
+                    name, server.version, latest, name
+                ),
+                None => println!(
+                    "[slapaman] scheduler: {} is up to date ({})",
+                    name, server.version
+                ),
+            }
+            Ok(())
+        }
+    }
+}
+
+// warn players at each point in `warnings` (seconds before the restart), then stop and start
+pub fn restart_with_countdown(name: &String, warnings: &[u64]) -> Result<(), String> {
+    broadcast_countdown(name, warnings)?;
+
+    // a supervised server is restarted by its supervisor, so the new JVM stays under it
+    if let Some(result) = restart_supervised(name) {
+        return result;
+    }
+
+    stop_server(name, RESTART_STOP_TIMEOUT)?;
+    // the old `slapaman run` needs a moment to clean up its pid file
+    thread::sleep(Duration::from_secs(1));
+    let mut child = spawn_background_server(name, None)?;
+    // reap the background run whenever it exits
+    thread::spawn(move || {
+        let _ = child.wait();
+    });
+    record_restart(name)
+}
+
+// announce an upcoming restart in-game, returning once the last warning's time is up
+pub fn broadcast_countdown(name: &String, warnings: &[u64]) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+    if running_pid(&server_dir).is_none() {
+        return Err("server is not running, so there is nothing to restart".to_string());
+    }
+
+    for (i, seconds) in warnings.iter().enumerate() {
+        let message = format!("say Server restarting in {}", describe_seconds(*seconds));
+        if let Err(e) = send_console_command(&server_dir, &message) {
+            println!(
+                "[slapaman] scheduler: failed to warn players on {}: {}",
+                name, e
+            );
+        }
+        let until_next = seconds - warnings.get(i + 1).copied().unwrap_or(0);
+        thread::sleep(Duration::from_secs(until_next));
+    }
+
+    Ok(())
+}
+
+fn parse_cron(expression: &str) -> Result<Cron, String> {
+    Cron::new(expression.trim())
+        .parse()
+        .map_err(|e| format!("invalid cron expression \"{}\": {:?}", expression, e))
+}
+
+fn next_run(cron: &Cron, after: &DateTime<Local>) -> Result<DateTime<Local>, String> {
+    cron.find_next_occurrence(after, false)
+        .map_err(|e| format!("no upcoming run: {:?}", e))
+}
+
+fn describe_seconds(seconds: u64) -> String {
+    match seconds {
+        s if s >= 60 && s % 60 == 0 => match s / 60 {
+            1 => "1 minute".to_string(),
+            m => format!("{} minutes", m),
+        },
+        1 => "1 second".to_string(),
+        s => format!("{} seconds", s),
+    }
+}
@@ -0,0 +1,538 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::backup::INSTANCE_FILES;
+use crate::catalog::visit_backup_files;
+use crate::crypto::BackupKeys;
+use crate::snapshot::safe_join;
+use crate::world::{dimension_dir, Dimension};
+
+// the folders of a dimension holding r.X.Z.mca files: blocks, entities and points of interest
+pub const REGION_FOLDERS: [&str; 3] = ["region", "entities", "poi"];
+// blocks per region file along each axis
+const REGION_BLOCKS: i32 = 512;
+// where the server records the UUIDs of players who have joined
+const USER_CACHE: &str = "usercache.json";
+
+// a piece of a world `world-restore --only` takes out of a backup
+#[derive(Clone, Debug, PartialEq)]
+pub enum RestoreFilter {
+    // a dimension's blocks, entities and points of interest
+    Dimension(Dimension),
+    // the region files covering a rectangle of region coordinates, inclusive
+    Regions {
+        dimension: Dimension,
+        x: (i32, i32),
+        z: (i32, i32),
+    },
+    // a player's inventory, position, stats and advancements, by UUID or name
+    Player(String),
+    // a file or folder inside a dimension's folder, such as level.dat or data/raids.dat
+    File {
+        dimension: Dimension,
+        path: String,
+    },
+}
+
+impl RestoreFilter {
+    // parse `--only`: a dimension (overworld, nether, end), region:X,Z, area:X1,Z1,X2,Z2 (block
+    // coordinates), player:UUID-or-name or file:PATH; regions, areas and files are in the
+    // overworld unless followed by @nether or @end
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if let Some(dimension) = Dimension::parse(value) {
+            return Ok(RestoreFilter::Dimension(dimension));
+        }
+
+        let (kind, rest) = value.split_once(':').ok_or_else(|| {
+            format!(
+                "unknown filter: {} (expected overworld, nether, end, region:X,Z, area:X1,Z1,X2,Z2, player:UUID or file:PATH)",
+                value
+            )
+        })?;
+        if kind == "player" {
+            return match rest.trim().is_empty() {
+                true => Err("player: needs a UUID or name".to_string()),
+                false => Ok(RestoreFilter::Player(rest.trim().to_string())),
+            };
+        }
+        let (rest, dimension) = match rest.rsplit_once('@') {
+            Some((rest, dimension)) => (
+                rest,
+                Dimension::parse(dimension)
+                    .ok_or_else(|| format!("unknown dimension: {}", dimension))?,
+            ),
+            None => (rest, Dimension::Overworld),
+        };
+
+        match kind {
+            "dimension" => Dimension::parse(rest)
+                .map(RestoreFilter::Dimension)
+                .ok_or_else(|| format!("unknown dimension: {}", rest)),
+            "region" => match parse_coordinates(rest)?[..] {
+                [x, z] => Ok(RestoreFilter::Regions {
+                    dimension,
+                    x: (x, x),
+                    z: (z, z),
+                }),
+                _ => Err(format!(
+                    "region: takes region coordinates X,Z, not {}",
+                    rest
+                )),
+            },
+            "area" => match parse_coordinates(rest)?[..] {
+                [x1, z1, x2, z2] => Ok(RestoreFilter::Regions {
+                    dimension,
+                    x: (
+                        x1.min(x2).div_euclid(REGION_BLOCKS),
+                        x1.max(x2).div_euclid(REGION_BLOCKS),
+                    ),
+                    z: (
+                        z1.min(z2).div_euclid(REGION_BLOCKS),
+                        z1.max(z2).div_euclid(REGION_BLOCKS),
+                    ),
+                }),
+                _ => Err(format!(
+                    "area: takes two block coordinate corners X1,Z1,X2,Z2, not {}",
+                    rest
+                )),
+            },
+            "file" => {
+                let path = rest.trim().trim_matches('/').replace('\\', "/");
+                if path.is_empty() || path.split('/').any(|part| part == ".." || part == ".") {
+                    return Err(format!("file: needs a path inside the world, not {}", rest));
+                }
+                Ok(RestoreFilter::File { dimension, path })
+            }
+            _ => Err(format!("unknown filter: {}", value)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            RestoreFilter::Dimension(dimension) => format!("the {}", dimension.name()),
+            RestoreFilter::Regions { dimension, x, z } if x.0 == x.1 && z.0 == z.1 => {
+                format!("region {},{} in the {}", x.0, z.0, dimension.name())
+            }
+            RestoreFilter::Regions { dimension, x, z } => format!(
+                "regions {},{} to {},{} in the {}",
+                x.0,
+                z.0,
+                x.1,
+                z.1,
+                dimension.name()
+            ),
+            RestoreFilter::Player(player) => format!("player {}", player),
+            RestoreFilter::File { dimension, path } => {
+                format!("{} in the {}", path, dimension.name())
+            }
+        }
+    }
+}
+
+// what a filter matches, as paths relative to the instance directory
+enum Rule {
+    Exact(String),
+    Under(String),
+    Regions {
+        dir: String,
+        x: (i32, i32),
+        z: (i32, i32),
+    },
+}
+
+impl Rule {
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            Rule::Exact(exact) => path == exact,
+            Rule::Under(dir) => path
+                .strip_prefix(dir.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')),
+            Rule::Regions { dir, x, z } => path
+                .strip_prefix(dir.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+                .and_then(|rest| rest.split_once('/'))
+                .filter(|(folder, _)| REGION_FOLDERS.contains(folder))
+                .and_then(|(_, file)| parse_region_name(file))
+                .is_some_and(|(rx, rz)| x.0 <= rx && rx <= x.1 && z.0 <= rz && rz <= z.1),
+        }
+    }
+}
+
+// the files a set of filters picks out of a backup, and how many each filter got
+pub struct Selection {
+    rules: Vec<(usize, Rule)>,
+    pub filters: Vec<RestoreFilter>,
+    pub counts: Vec<usize>,
+}
+
+impl Selection {
+    pub fn new(
+        filters: &[RestoreFilter],
+        server_dir: &Path,
+        world_dirs: &[String],
+    ) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (i, filter) in filters.iter().enumerate() {
+            match filter {
+                RestoreFilter::Dimension(dimension) => {
+                    let dir = dimension_dir(world_dirs, *dimension);
+                    for folder in REGION_FOLDERS {
+                        rules.push((i, Rule::Under(format!("{}/{}", dir, folder))));
+                    }
+                }
+                RestoreFilter::Regions { dimension, x, z } => rules.push((
+                    i,
+                    Rule::Regions {
+                        dir: dimension_dir(world_dirs, *dimension),
+                        x: *x,
+                        z: *z,
+                    },
+                )),
+                // player data is kept with the overworld, whichever dimension they're in
+                RestoreFilter::Player(player) => {
+                    let uuid = resolve_player(server_dir, player)?;
+                    for file in [
+                        format!("playerdata/{}.dat", uuid),
+                        format!("playerdata/{}.dat_old", uuid),
+                        format!("stats/{}.json", uuid),
+                        format!("advancements/{}.json", uuid),
+                    ] {
+                        rules.push((i, Rule::Exact(format!("{}/{}", world_dirs[0], file))));
+                    }
+                }
+                RestoreFilter::File { dimension, path } => rules.push((
+                    i,
+                    Rule::Under(format!(
+                        "{}/{}",
+                        dimension_dir(world_dirs, *dimension),
+                        path
+                    )),
+                )),
+            }
+        }
+
+        Ok(Self {
+            rules,
+            filters: filters.to_vec(),
+            counts: vec![0; filters.len()],
+        })
+    }
+
+    // whether a file (relative to the instance directory) is wanted, counting it for every filter
+    // it matches
+    pub fn matches(&mut self, path: &str) -> bool {
+        let mut matched = Vec::new();
+        for (i, rule) in &self.rules {
+            if rule.matches(path) && !matched.contains(i) {
+                matched.push(*i);
+            }
+        }
+        for i in &matched {
+            self.counts[*i] += 1;
+        }
+        !matched.is_empty()
+    }
+}
+
+// copy the files `selection` picks out of a backup into `staging_dir`, at their paths relative to
+// the instance directory, and return those paths; files are streamed out of the backup one at a
+// time, so nothing else gets unpacked
+pub fn extract_selection(
+    backup: &Path,
+    keys: &BackupKeys,
+    selection: &mut Selection,
+    world_dirs: &[String],
+    staging_dir: &Path,
+) -> Result<Vec<String>, String> {
+    let mut extracted = Vec::new();
+    visit_backup_files(backup, keys, |name, reader| {
+        let path = instance_path(name, world_dirs);
+        if !selection.matches(&path) {
+            return Ok(());
+        }
+
+        let target = safe_join(staging_dir, &path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+        let file = File::create(&target)
+            .map_err(|e| format!("failed to create {}: {}", target.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        io::copy(reader, &mut writer)
+            .and_then(|_| writer.flush())
+            .map_err(|e| format!("failed to extract {}: {}", name, e))?;
+        extracted.push(path);
+        Ok(())
+    })?;
+    Ok(extracted)
+}
+
+// where a file in a backup belongs, relative to the instance directory; backups from before
+// dimensions were backed up together hold just the overworld's contents, so their files go under
+// its folder
+pub fn instance_path(name: &str, world_dirs: &[String]) -> String {
+    match name.split('/').next() {
+        Some(first)
+            if world_dirs.iter().any(|dir| dir == first) || INSTANCE_FILES.contains(&first) =>
+        {
+            name.to_string()
+        }
+        _ => format!("{}/{}", world_dirs[0], name),
+    }
+}
+
+// the dimension, folder (region, entities or poi) and coordinates of a region file, given its path
+// relative to the instance directory
+pub fn region_location(path: &str, world_dirs: &[String]) -> Option<(Dimension, String, i32, i32)> {
+    [Dimension::Overworld, Dimension::Nether, Dimension::End]
+        .into_iter()
+        .find_map(|dimension| {
+            let rest = path.strip_prefix(&format!("{}/", dimension_dir(world_dirs, dimension)))?;
+            let (folder, file) = rest.split_once('/')?;
+            if !REGION_FOLDERS.contains(&folder) {
+                return None;
+            }
+            let (x, z) = parse_region_name(file)?;
+            Some((dimension, folder.to_string(), x, z))
+        })
+}
+
+// the coordinates in a region file's name, e.g. r.-1.2.mca
+pub fn parse_region_name(name: &str) -> Option<(i32, i32)> {
+    let coordinates = name.strip_prefix("r.")?.strip_suffix(".mca")?;
+    let (x, z) = coordinates.split_once('.')?;
+    Some((x.parse().ok()?, z.parse().ok()?))
+}
+
+fn parse_coordinates(value: &str) -> Result<Vec<i32>, String> {
+    value
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse::<i32>()
+                .map_err(|_| format!("not a coordinate: {}", n.trim()))
+        })
+        .collect()
+}
+
+// a player's UUID in the dashed form the game names their files with; names are looked up in the
+// instance's usercache.json, so only players who have joined can be given by name
+fn resolve_player(server_dir: &Path, player: &str) -> Result<String, String> {
+    let hex: String = player.chars().filter(|c| *c != '-').collect();
+    if hex.len() == 32 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        let hex = hex.to_lowercase();
+        return Ok(format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32]
+        ));
+    }
+
+    let cache = fs::read_to_string(server_dir.join(USER_CACHE))
+        .map_err(|e| format!("failed to read {}: {}", USER_CACHE, e))?;
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&cache)
+        .map_err(|e| format!("failed to parse {}: {}", USER_CACHE, e))?;
+    entries
+        .iter()
+        .find(|entry| {
+            entry["name"]
+                .as_str()
+                .is_some_and(|name| name.eq_ignore_ascii_case(player))
+        })
+        .and_then(|entry| entry["uuid"].as_str())
+        .map(|uuid| uuid.to_lowercase())
+        .ok_or_else(|| {
+            format!(
+                "no player named {} in {}; give their UUID instead",
+                player, USER_CACHE
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_dirs() -> Vec<String> {
+        ["world", "world_nether", "world_the_end"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn selection(filters: &[&str]) -> Selection {
+        let filters: Vec<RestoreFilter> = filters
+            .iter()
+            .map(|f| RestoreFilter::parse(f).unwrap())
+            .collect();
+        Selection::new(&filters, Path::new("/nonexistent"), &world_dirs()).unwrap()
+    }
+
+    #[test]
+    fn filters_parse_into_dimensions_regions_players_and_files() {
+        assert_eq!(
+            RestoreFilter::parse("nether"),
+            Ok(RestoreFilter::Dimension(Dimension::Nether))
+        );
+        assert_eq!(
+            RestoreFilter::parse("dimension:the_end"),
+            Ok(RestoreFilter::Dimension(Dimension::End))
+        );
+        assert_eq!(
+            RestoreFilter::parse("region:-1, 2"),
+            Ok(RestoreFilter::Regions {
+                dimension: Dimension::Overworld,
+                x: (-1, -1),
+                z: (2, 2),
+            })
+        );
+        assert_eq!(
+            RestoreFilter::parse("player: Notch "),
+            Ok(RestoreFilter::Player("Notch".to_string()))
+        );
+        assert_eq!(
+            RestoreFilter::parse("file:/data/raids.dat"),
+            Ok(RestoreFilter::File {
+                dimension: Dimension::Overworld,
+                path: "data/raids.dat".to_string(),
+            })
+        );
+        for bad in [
+            "nowhere",
+            "region:1",
+            "region:1,x",
+            "area:1,2,3",
+            "player:",
+            "chunk:1,2",
+            "region:1,2@moon",
+        ] {
+            assert!(RestoreFilter::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn areas_round_negative_blocks_down_to_their_region() {
+        // block -1 is in region -1 and block -512 still is, but -513 is in region -2
+        assert_eq!(
+            RestoreFilter::parse("area:-1,-512,511,-513"),
+            Ok(RestoreFilter::Regions {
+                dimension: Dimension::Overworld,
+                x: (-1, 0),
+                z: (-2, -1),
+            })
+        );
+        assert_eq!(
+            RestoreFilter::parse("area:512,0,1023,0"),
+            Ok(RestoreFilter::Regions {
+                dimension: Dimension::Overworld,
+                x: (1, 1),
+                z: (0, 0),
+            })
+        );
+    }
+
+    #[test]
+    fn dimension_suffixes_pick_the_dimension() {
+        assert_eq!(
+            RestoreFilter::parse("region:0,0@nether"),
+            Ok(RestoreFilter::Regions {
+                dimension: Dimension::Nether,
+                x: (0, 0),
+                z: (0, 0),
+            })
+        );
+        assert_eq!(
+            RestoreFilter::parse("file:data/raids_end.dat@end"),
+            Ok(RestoreFilter::File {
+                dimension: Dimension::End,
+                path: "data/raids_end.dat".to_string(),
+            })
+        );
+
+        let mut selection = selection(&["region:0,0@nether", "file:level.dat@end"]);
+        assert!(selection.matches("world_nether/DIM-1/region/r.0.0.mca"));
+        assert!(!selection.matches("world/region/r.0.0.mca"));
+        assert!(selection.matches("world_the_end/DIM1/level.dat"));
+        assert!(!selection.matches("world/level.dat"));
+    }
+
+    #[test]
+    fn file_filters_refuse_paths_outside_the_world() {
+        for bad in [
+            "file:",
+            "file:/",
+            "file:../server.properties",
+            "file:data/../../ops.json",
+            "file:..\\ops.json",
+            "file:./level.dat",
+        ] {
+            assert!(RestoreFilter::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn rules_match_their_files_and_count_them() {
+        let mut selection = selection(&[
+            "area:0,0,600,100",
+            "overworld",
+            "player:069a79f4-44e9-4726-a5be-fca90e38aaf5",
+            "file:data",
+        ]);
+        assert!(selection.matches("world/region/r.1.0.mca"));
+        assert!(selection.matches("world/entities/r.0.0.mca"));
+        assert!(selection.matches("world/poi/r.-5.3.mca"));
+        assert!(selection.matches("world/playerdata/069a79f4-44e9-4726-a5be-fca90e38aaf5.dat"));
+        assert!(selection.matches("world/data/raids.dat"));
+        assert!(selection.matches("world/data"));
+        assert!(!selection.matches("world/database.dat"));
+        assert!(!selection.matches("world/regions/r.0.0.mca"));
+        assert!(!selection.matches("world_nether/DIM-1/region/r.0.0.mca"));
+        assert!(!selection.matches("world/level.dat"));
+        assert_eq!(selection.counts, vec![2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn older_single_world_backups_land_in_the_overworld() {
+        let dirs = world_dirs();
+        assert_eq!(
+            instance_path("region/r.0.0.mca", &dirs),
+            "world/region/r.0.0.mca"
+        );
+        assert_eq!(instance_path("level.dat", &dirs), "world/level.dat");
+        assert_eq!(
+            instance_path("world_nether/DIM-1/region/r.0.0.mca", &dirs),
+            "world_nether/DIM-1/region/r.0.0.mca"
+        );
+        assert_eq!(
+            instance_path("server.properties", &dirs),
+            "server.properties"
+        );
+    }
+
+    #[test]
+    fn region_names_give_their_coordinates() {
+        assert_eq!(parse_region_name("r.0.0.mca"), Some((0, 0)));
+        assert_eq!(parse_region_name("r.-1.12.mca"), Some((-1, 12)));
+        for bad in [
+            "r.0.mca",
+            "r.0.0.mcc",
+            "r.a.0.mca",
+            "0.0.mca",
+            "r.0.0.0.mca",
+            "r.99999999999.0.mca",
+        ] {
+            assert_eq!(parse_region_name(bad), None, "{}", bad);
+        }
+        assert_eq!(
+            region_location("world_nether/DIM-1/entities/r.2.-3.mca", &world_dirs()),
+            Some((Dimension::Nether, "entities".to_string(), 2, -3))
+        );
+    }
+}
@@ -7,8 +7,15 @@ use fs_extra::dir::CopyOptions;
 use serde_json::Value;
 use std::fs;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::PathBuf;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup::BackupConfig;
+use crate::control::{try_daemon, ControlCommand, InstanceState};
+use crate::daemon::RestartPolicy;
+use crate::resources::resource_summaries;
+use crate::schedule::ScheduledTask;
+use crate::webhooks::Webhook;
 
 #[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone)]
 pub struct Server {
@@ -25,15 +32,40 @@ pub struct Server {
     pub ops: Value,
     pub permissions: Value,
     pub server_properties: Value,
+    // slapaman-managed JDK (by major version) to launch with instead of the system java
+    #[serde(default)]
+    pub java: Option<u32>,
+    // how many times slapaman has restarted this instance (e.g. from the watchdog)
+    #[serde(default)]
+    pub restarts: u64,
+    // JVM settings `slapaman run` uses when none are given on the command line
+    #[serde(default)]
+    pub memory: Option<u32>,
+    #[serde(default)]
+    pub jvm_args: Vec<String>,
+    // recurring tasks run by `slapaman scheduler`
+    #[serde(default)]
+    pub schedule: Vec<ScheduledTask>,
+    // whether `slapaman daemon` starts and supervises this instance
+    #[serde(default)]
+    pub autostart: bool,
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    // webhooks notified about this instance only, on top of the global ones
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    // how `world-backup` stores this instance's backups
+    #[serde(default)]
+    pub backup: BackupConfig,
 }
 
 impl Server {
-    pub fn new(name: &String, path: &PathBuf, version: &String, flavor: &String) -> Self {
+    pub fn new(name: &str, path: &Path, version: &str, flavor: &str) -> Self {
         Self {
-            name: name.clone(),
-            path: path.clone(),
-            version: version.clone(),
-            flavor: flavor.clone(),
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            version: version.to_string(),
+            flavor: flavor.to_string(),
             banned_ips: Value::Null,
             banned_players: Value::Null,
             eula: false,
@@ -41,6 +73,15 @@ impl Server {
             ops: Value::Null,
             permissions: Value::Null,
             server_properties: Value::Null,
+            java: None,
+            restarts: 0,
+            memory: None,
+            jvm_args: Vec::new(),
+            schedule: Vec::new(),
+            autostart: false,
+            restart_policy: RestartPolicy::default(),
+            webhooks: Vec::new(),
+            backup: BackupConfig::default(),
         }
     }
 
@@ -76,15 +117,31 @@ fn load_servers_list(servers_list: &PathBuf) -> Result<Vec<Server>, String> {
 }
 
 fn save_servers_list(servers_list: &PathBuf, servers: Vec<Server>) -> Result<(), String> {
+    // write a temporary file and rename it over the list, so readers (like the daemon) never see
+    // a half-written list
+    let temp_list = servers_list.with_extension("lock.tmp");
     let file =
-        File::create(servers_list).map_err(|e| format!("failed to create servers list: {}", e))?;
-    let writer = BufWriter::new(file);
-    serde_json::to_writer(writer, &servers)
+        File::create(&temp_list).map_err(|e| format!("failed to create servers list: {}", e))?;
+    // backup destinations can hold S3 secret keys, so the list is for the owner's eyes only
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("failed to restrict servers list: {}", e))?;
+    }
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &servers)
         .map_err(|e| format!("failed to write servers list: {}", e))?;
+    writer
+        .flush()
+        .map_err(|e| format!("failed to write servers list: {}", e))?;
+    drop(writer);
+    fs::rename(&temp_list, servers_list)
+        .map_err(|e| format!("failed to save servers list: {}", e))?;
     Ok(())
 }
 
-pub fn list_servers(detailed: bool) -> Result<(), String> {
+pub fn list_servers(detailed: bool, resources: bool) -> Result<(), String> {
     let servers_list = ProjectDirs::from("com", "wyomingwade", "slapaman")
         .expect("could not determine a home directory")
         .data_dir()
@@ -92,6 +149,24 @@ pub fn list_servers(detailed: bool) -> Result<(), String> {
 
     let servers = load_servers_list(&servers_list).unwrap_or_default();
 
+    // with --resources, follow each server with its CPU, memory and disk usage
+    if resources {
+        let summaries = resource_summaries(&servers);
+        for (server, summary) in servers.iter().zip(summaries) {
+            match detailed {
+                false => println!("{}: {}", server.name, summary),
+                true => println!(
+                    "{}: {} ({}) - {}",
+                    server.name,
+                    server.path.display(),
+                    server.version,
+                    summary
+                ),
+            }
+        }
+        return Ok(());
+    }
+
     match detailed {
         // when running quietly, just print the server names
         false => {
@@ -99,15 +174,34 @@ pub fn list_servers(detailed: bool) -> Result<(), String> {
                 println!("{}", server.name);
             }
         }
-        // when running with the --detailed flag, print the server names, paths, and versions
+        // when running with the --detailed flag, print the server names, paths, and versions,
+        // plus what the daemon knows about each one if it's running
         true => {
+            let states: Vec<InstanceState> = match try_daemon(ControlCommand::List) {
+                Some(Ok(data)) => serde_json::from_value(data).unwrap_or_default(),
+                _ => Vec::new(),
+            };
             for server in servers {
-                println!(
-                    "{}: {} ({})",
-                    server.name,
-                    server.path.display(),
-                    server.version
-                );
+                match states.iter().find(|s| s.name == server.name) {
+                    Some(state) => println!(
+                        "{}: {} ({}) [{}{}{}]",
+                        server.name,
+                        server.path.display(),
+                        server.version,
+                        match state.pid {
+                            Some(pid) => format!("running, pid {}", pid),
+                            None => "stopped".to_string(),
+                        },
+                        if state.supervised { ", supervised" } else { "" },
+                        if state.autostart { ", autostart" } else { "" }
+                    ),
+                    None => println!(
+                        "{}: {} ({})",
+                        server.name,
+                        server.path.display(),
+                        server.version
+                    ),
+                }
             }
         }
     }
@@ -152,16 +246,16 @@ pub fn remove_server_from_list(server: &Server) -> Result<(), String> {
 pub fn rename_server(name: &String, new_name: &String) -> Result<(), String> {
     // this will load the server from slapaman's master list by name
     // fails when the server is not found
-    let server_old = Server::load_by_name(&name).unwrap();
+    let server_old = Server::load_by_name(name).unwrap();
 
     // make sure the new name is not already taken
-    if Server::load_by_name(&new_name).is_ok() {
+    if Server::load_by_name(new_name).is_ok() {
         return Err(format!("server name already taken: {}", new_name));
     }
 
     // attempt to rename the server directory
-    let old_path = server_old.path.join(&name).clone();
-    let new_path = server_old.path.join(&new_name).clone();
+    let old_path = server_old.path.join(name).clone();
+    let new_path = server_old.path.join(new_name).clone();
     fs::rename(&old_path, &new_path)
         .map_err(|e| format!("failed to rename server directory: {}", e))?;
 
@@ -200,6 +294,13 @@ pub fn update_server_by_name(name: &String, server: &Server) -> Result<(), Strin
     Ok(())
 }
 
+// bump an instance's restart counter after slapaman restarts it
+pub fn record_restart(name: &String) -> Result<(), String> {
+    let mut server = Server::load_by_name(name)?;
+    server.restarts += 1;
+    update_server_by_name(name, &server)
+}
+
 pub fn copy_server(name: &String, new_name: &String) -> Result<(), String> {
     // path for where the servers list is stored
     let servers_list = ProjectDirs::from("com", "wyomingwade", "slapaman")
@@ -209,23 +310,23 @@ pub fn copy_server(name: &String, new_name: &String) -> Result<(), String> {
 
     // this will load the server from slapaman's master list by name
     // fails when the server is not found
-    let server = Server::load_by_name(&name).unwrap();
+    let server = Server::load_by_name(name).unwrap();
 
     // make sure the new name is not already taken
-    if Server::load_by_name(&new_name).is_ok() {
+    if Server::load_by_name(new_name).is_ok() {
         return Err(format!("server name already taken: {}", new_name));
     }
 
     // copy the server directory
     let old_paths = server
         .path
-        .join(&name)
+        .join(name)
         .clone()
         .read_dir()
         .unwrap()
         .map(|e| e.unwrap().path())
         .collect::<Vec<PathBuf>>();
-    let new_path = server.path.join(&new_name).clone();
+    let new_path = server.path.join(new_name).clone();
     if !new_path.exists() {
         fs::create_dir_all(&new_path)
             .map_err(|e| format!("failed to create server directory: {}", e))?;
@@ -260,7 +361,7 @@ pub fn move_server(name: &String, new_path: &PathBuf) -> Result<(), String> {
 
     // this will load the server from slapaman's master list by name
     // fails when the server is not found
-    let server = Server::load_by_name(&name).unwrap();
+    let server = Server::load_by_name(name).unwrap();
 
     // make sure the new path is not already taken
     let servers = load_servers_list(&servers_list).unwrap_or_default();
@@ -271,8 +372,8 @@ pub fn move_server(name: &String, new_path: &PathBuf) -> Result<(), String> {
     }
 
     // move the server directory
-    let old_path = server.path.join(&name).clone();
-    let new_path = new_path.join(&name).clone();
+    let old_path = server.path.join(name).clone();
+    let new_path = new_path.join(name).clone();
     fs::rename(&old_path, &new_path)
         .map_err(|e| format!("failed to move server directory: {}", e))?;
 
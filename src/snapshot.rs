@@ -0,0 +1,676 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{entry_name, walk_entries};
+
+// the content-addressed store shared by every incremental backup of an instance, kept in its
+// backups directory
+pub const CHUNKS_DIR_NAME: &str = ".chunks";
+pub const SNAPSHOT_EXTENSION: &str = "snapshot.json";
+const MANIFEST_VERSION: u32 = 1;
+// region files are laid out in 4 KiB sectors, and most of them don't change between backups
+const SECTOR_SIZE: usize = 4096;
+const REGION_EXTENSIONS: [&str; 2] = ["mca", "mcc"];
+const ZSTD_LEVEL: i32 = 3;
+// chunks are written under this name at the top of the store, then renamed into place
+const TEMP_CHUNK_PREFIX: &str = ".tmp-";
+// unreferenced chunks younger than this are left alone
+const GC_GRACE_PERIOD: Duration = Duration::from_secs(3600);
+
+// a snapshot manifest: everything needed to rebuild the backed up files from the chunk store
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct Manifest {
+    pub version: u32,
+    pub created: String,
+    // whether region files were split into sectors
+    pub sectors: bool,
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct ManifestEntry {
+    // relative to the instance directory, with forward slashes
+    pub path: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub dir: bool,
+    #[serde(default)]
+    pub size: u64,
+    // SHA-256 of each piece of the file, in order (one for a whole file, or one per sector)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|e| format!("failed to open snapshot {}: {}", path.display(), e))?;
+        serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("failed to parse snapshot {}: {}", path.display(), e))
+    }
+}
+
+// what a snapshot added to the chunk store
+pub struct SnapshotStats {
+    pub files: usize,
+    pub chunks: usize,
+    pub new_chunks: usize,
+    pub new_bytes: u64,
+}
+
+// store `entries` (paths relative to `root`) in the chunk store next to `manifest_path` and write
+// the manifest, splitting region files into sectors when `sectors` is set
+pub fn write_snapshot(
+    root: &Path,
+    entries: &[String],
+    manifest_path: &Path,
+    sectors: bool,
+) -> Result<SnapshotStats, String> {
+    let chunks_dir = chunks_dir_for(manifest_path);
+    fs::create_dir_all(&chunks_dir).map_err(|e| format!("failed to create chunk store: {}", e))?;
+
+    let mut stats = SnapshotStats {
+        files: 0,
+        chunks: 0,
+        new_chunks: 0,
+        new_bytes: 0,
+    };
+    let files = walk_entries(root, entries)?;
+    let mut entries = Vec::new();
+    // a snapshot stores contents, and a link's target may well be outside the instance
+    for (path, relative) in files.into_iter().filter(|(p, _)| !p.is_symlink()) {
+        let name = entry_name(&relative);
+        if path.is_dir() {
+            entries.push(ManifestEntry {
+                path: name,
+                dir: true,
+                size: 0,
+                chunks: Vec::new(),
+            });
+            continue;
+        }
+
+        let (size, chunks) = match sectors && is_region_file(&path) {
+            true => store_sectors(&chunks_dir, &path, &mut stats)?,
+            false => store_file(&chunks_dir, &path, &mut stats)?,
+        };
+        stats.files += 1;
+        stats.chunks += chunks.len();
+        entries.push(ManifestEntry {
+            path: name,
+            dir: false,
+            size,
+            chunks,
+        });
+    }
+
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        created: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        sectors,
+        entries,
+    };
+
+    // the manifest goes last, so a snapshot only exists once all of its chunks do
+    let temp_path = manifest_path.with_extension("partial");
+    let file = File::create(&temp_path)
+        .map_err(|e| format!("failed to create snapshot manifest: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &manifest)
+        .map_err(|e| format!("failed to write snapshot manifest: {}", e))?;
+    writer
+        .flush()
+        .map_err(|e| format!("failed to write snapshot manifest: {}", e))?;
+    drop(writer);
+    fs::rename(&temp_path, manifest_path)
+        .map_err(|e| format!("failed to save snapshot manifest: {}", e))?;
+
+    Ok(stats)
+}
+
+// rebuild a snapshot's files into `destination`, which must already exist
+pub fn restore_snapshot(manifest_path: &Path, destination: &Path) -> Result<(), String> {
+    let manifest = Manifest::load(manifest_path)?;
+    let chunks_dir = chunks_dir_for(manifest_path);
+
+    for entry in &manifest.entries {
+        let target = safe_join(destination, &entry.path)?;
+        if entry.dir {
+            fs::create_dir_all(&target)
+                .map_err(|e| format!("failed to create {}: {}", target.display(), e))?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+        }
+
+        let file = File::create(&target)
+            .map_err(|e| format!("failed to create {}: {}", target.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        let written = io::copy(
+            &mut SnapshotFileReader::new(&chunks_dir, entry),
+            &mut writer,
+        )
+        .map_err(|e| format!("failed to restore {}: {}", entry.path, e))?;
+        writer
+            .flush()
+            .map_err(|e| format!("failed to restore {}: {}", entry.path, e))?;
+        if written != entry.size {
+            return Err(format!(
+                "restored {} is {} bytes, but the snapshot says {}",
+                entry.path, written, entry.size
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// delete chunks no snapshot in `backups_dir` refers to any more, and chunks left half written,
+// returning how many chunks were removed and how much space that freed
+pub fn collect_garbage(backups_dir: &Path) -> Result<(usize, u64), String> {
+    let chunks_dir = backups_dir.join(CHUNKS_DIR_NAME);
+    if !chunks_dir.exists() {
+        return Ok((0, 0));
+    }
+
+    let mut referenced = HashSet::new();
+    for entry in
+        fs::read_dir(backups_dir).map_err(|e| format!("failed to read backups directory: {}", e))?
+    {
+        let path = entry
+            .map_err(|e| format!("failed to read backups directory: {}", e))?
+            .path();
+        if !is_snapshot(&path) {
+            continue;
+        }
+        // a manifest we can't read might still need its chunks, so don't collect anything
+        let manifest =
+            Manifest::load(&path).map_err(|e| format!("{} (not collecting garbage)", e))?;
+        for entry in manifest.entries {
+            referenced.extend(entry.chunks);
+        }
+    }
+
+    let mut removed = 0;
+    let mut freed = 0;
+    for prefix in fs::read_dir(&chunks_dir)
+        .map_err(|e| format!("failed to read chunk store: {}", e))?
+        .filter_map(|e| e.ok())
+    {
+        // a backup that failed part way through can leave the chunk it was writing behind
+        if prefix
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_CHUNK_PREFIX)
+        {
+            if !recently_modified(&prefix) {
+                freed += prefix.metadata().map(|m| m.len()).unwrap_or(0);
+                fs::remove_file(prefix.path())
+                    .map_err(|e| format!("failed to remove {}: {}", prefix.path().display(), e))?;
+            }
+            continue;
+        }
+        if !prefix.path().is_dir() {
+            continue;
+        }
+        for chunk in fs::read_dir(prefix.path())
+            .map_err(|e| format!("failed to read chunk store: {}", e))?
+            .filter_map(|e| e.ok())
+        {
+            let name = chunk.file_name().to_string_lossy().to_string();
+            if referenced.contains(&name) {
+                continue;
+            }
+            // a backup running right now may have stored or reused chunks its manifest doesn't
+            // list yet
+            if recently_modified(&chunk) {
+                continue;
+            }
+            let size = chunk.metadata().map(|m| m.len()).unwrap_or(0);
+            fs::remove_file(chunk.path())
+                .map_err(|e| format!("failed to remove chunk {}: {}", name, e))?;
+            removed += 1;
+            freed += size;
+        }
+        // leave no empty prefix directories behind
+        let _ = fs::remove_dir(prefix.path());
+    }
+
+    Ok((removed, freed))
+}
+
+fn recently_modified(entry: &fs::DirEntry) -> bool {
+    entry
+        .metadata()
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_none_or(|age| age < GC_GRACE_PERIOD)
+}
+
+// check that every chunk a snapshot needs is in the store
+pub fn check_snapshot(manifest_path: &Path) -> Result<(), String> {
+    let manifest = Manifest::load(manifest_path)?;
+    let chunks_dir = chunks_dir_for(manifest_path);
+    let missing = manifest
+        .entries
+        .iter()
+        .flat_map(|entry| entry.chunks.iter().map(move |hash| (entry, hash)))
+        .filter(|(_, hash)| !chunk_path(&chunks_dir, hash).is_ok_and(|path| path.exists()))
+        .map(|(entry, _)| entry.path.as_str())
+        .collect::<Vec<_>>();
+    match missing.first() {
+        None => Ok(()),
+        Some(first) => Err(format!(
+            "{} chunks are missing from the store (first needed by {})",
+            missing.len(),
+            first
+        )),
+    }
+}
+
+pub fn is_snapshot(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.ends_with(&format!(".{}", SNAPSHOT_EXTENSION)))
+}
+
+// the decompressed contents of one chunk
+pub fn open_chunk(chunks_dir: &Path, hash: &str) -> Result<impl Read, String> {
+    let path = chunk_path(chunks_dir, hash)?;
+    let file =
+        File::open(&path).map_err(|e| format!("snapshot chunk {} is missing: {}", hash, e))?;
+    zstd::Decoder::new(file).map_err(|e| format!("failed to read chunk {}: {}", hash, e))
+}
+
+// reads one file of a snapshot back, opening its chunks one at a time
+pub struct SnapshotFileReader {
+    chunks_dir: PathBuf,
+    chunks: std::vec::IntoIter<String>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl SnapshotFileReader {
+    pub fn new(chunks_dir: &Path, entry: &ManifestEntry) -> Self {
+        Self {
+            chunks_dir: chunks_dir.to_path_buf(),
+            chunks: entry.chunks.clone().into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl Read for SnapshotFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(chunk) = &mut self.current {
+                let read = chunk.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+            }
+            match self.chunks.next() {
+                Some(hash) => {
+                    let chunk = open_chunk(&self.chunks_dir, &hash).map_err(io::Error::other)?;
+                    self.current = Some(Box::new(chunk));
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+pub fn chunks_dir_for(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(CHUNKS_DIR_NAME)
+}
+
+fn store_file(
+    chunks_dir: &Path,
+    path: &Path,
+    stats: &mut SnapshotStats,
+) -> Result<(u64, Vec<String>), String> {
+    // hash first, and only compress files the store doesn't have yet
+    let mut file =
+        File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)
+        .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let hash = format!("{:x}", hasher.finalize());
+    if reuse_chunk(&chunk_path(chunks_dir, &hash)?) {
+        return Ok((size, vec![hash]));
+    }
+
+    // the file may have changed since it was hashed, so hash what actually gets stored
+    let temp_path = chunks_dir.join(format!("{}{}", TEMP_CHUNK_PREFIX, hash));
+    let mut input = HashingReader {
+        inner: File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?,
+        hasher: Sha256::new(),
+        size: 0,
+    };
+    let output = File::create(&temp_path).map_err(|e| format!("failed to create chunk: {}", e))?;
+    let mut encoder = zstd::Encoder::new(BufWriter::new(output), ZSTD_LEVEL)
+        .map_err(|e| format!("failed to start zstd stream: {}", e))?;
+    io::copy(&mut input, &mut encoder)
+        .and_then(|_| encoder.finish())
+        .and_then(|mut writer| writer.flush())
+        .map_err(|e| format!("failed to store {}: {}", path.display(), e))?;
+
+    let hash = format!("{:x}", input.hasher.finalize());
+    commit_chunk(chunks_dir, &temp_path, &hash, stats)?;
+    Ok((input.size, vec![hash]))
+}
+
+fn store_sectors(
+    chunks_dir: &Path,
+    path: &Path,
+    stats: &mut SnapshotStats,
+) -> Result<(u64, Vec<String>), String> {
+    let data = fs::read(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+
+    let mut hashes = Vec::with_capacity(data.len().div_ceil(SECTOR_SIZE));
+    for sector in data.chunks(SECTOR_SIZE) {
+        let hash = format!("{:x}", Sha256::digest(sector));
+        if !reuse_chunk(&chunk_path(chunks_dir, &hash)?) {
+            let temp_path = chunks_dir.join(format!("{}{}", TEMP_CHUNK_PREFIX, hash));
+            let compressed = zstd::encode_all(sector, ZSTD_LEVEL)
+                .map_err(|e| format!("failed to compress sector: {}", e))?;
+            fs::write(&temp_path, compressed)
+                .map_err(|e| format!("failed to create chunk: {}", e))?;
+            commit_chunk(chunks_dir, &temp_path, &hash, stats)?;
+        }
+        hashes.push(hash);
+    }
+
+    Ok((data.len() as u64, hashes))
+}
+
+// move a freshly written chunk into place, unless an identical one got there first
+fn commit_chunk(
+    chunks_dir: &Path,
+    temp_path: &Path,
+    hash: &str,
+    stats: &mut SnapshotStats,
+) -> Result<(), String> {
+    let path = chunk_path(chunks_dir, hash)?;
+    if reuse_chunk(&path) {
+        let _ = fs::remove_file(temp_path);
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("failed to create chunk store: {}", e))?;
+    }
+    stats.new_bytes += fs::metadata(temp_path).map(|m| m.len()).unwrap_or(0);
+    stats.new_chunks += 1;
+    fs::rename(temp_path, &path).map_err(|e| format!("failed to store chunk: {}", e))
+}
+
+// whether the store already has the chunk at `path`; reusing one bumps its mtime, so a
+// `collect_garbage` running alongside counts it as new until this backup's manifest lists it
+fn reuse_chunk(path: &Path) -> bool {
+    File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()))
+        .is_ok()
+}
+
+pub fn chunk_path(chunks_dir: &Path, hash: &str) -> Result<PathBuf, String> {
+    // manifests can be edited by hand, so never let a "hash" point outside the store
+    if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid chunk hash: {}", hash));
+    }
+    Ok(chunks_dir.join(&hash[..2]).join(hash))
+}
+
+// join a manifest path onto `root`, refusing anything that would escape it
+pub fn safe_join(root: &Path, relative: &str) -> Result<PathBuf, String> {
+    if relative.starts_with('/') {
+        return Err(format!("refusing unsafe path: {}", relative));
+    }
+    let mut path = root.to_path_buf();
+    for part in relative.split('/') {
+        match part {
+            "" | "." => continue,
+            ".." => return Err(format!("refusing unsafe path: {}", relative)),
+            part if part.contains('\\') || Path::new(part).is_absolute() => {
+                return Err(format!("refusing unsafe path: {}", relative))
+            }
+            part => path.push(part),
+        }
+    }
+    Ok(path)
+}
+
+fn is_region_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| REGION_EXTENSIONS.contains(&e))
+}
+
+// hashes everything read through it
+struct HashingReader<R: Read> {
+    inner: R,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    const OLD: Duration = Duration::from_secs(2 * 24 * 3600);
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("slapaman-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // an instance with a world holding a small file, a region file of two identical sectors and
+    // a partial one, and an empty directory
+    fn instance(root: &Path) {
+        fs::create_dir_all(root.join("world/region")).unwrap();
+        fs::create_dir_all(root.join("world/data")).unwrap();
+        fs::write(root.join("world/level.dat"), b"level").unwrap();
+        let mut region = vec![7u8; SECTOR_SIZE * 2];
+        region.extend_from_slice(&[9u8; 100]);
+        fs::write(root.join("world/region/r.0.0.mca"), region).unwrap();
+    }
+
+    fn age(path: &Path) {
+        File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(SystemTime::now() - OLD))
+            .unwrap();
+    }
+
+    fn every_chunk(chunks_dir: &Path) -> Vec<PathBuf> {
+        walk_entries(chunks_dir, &[".".to_string()])
+            .unwrap()
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    #[test]
+    fn snapshots_restore_what_was_stored() {
+        for sectors in [false, true] {
+            let dir = scratch_dir(&format!("snapshot-round-trip-{}", sectors));
+            let root = dir.join("instance");
+            instance(&root);
+            let manifest_path = dir.join("backups/world-20250101-000000.snapshot.json");
+            fs::create_dir_all(manifest_path.parent().unwrap()).unwrap();
+
+            let stats =
+                write_snapshot(&root, &["world".to_string()], &manifest_path, sectors).unwrap();
+            assert_eq!(stats.files, 2);
+            let manifest = Manifest::load(&manifest_path).unwrap();
+            let region = manifest
+                .entries
+                .iter()
+                .find(|e| e.path == "world/region/r.0.0.mca")
+                .unwrap();
+            // the two identical sectors share a chunk
+            match sectors {
+                true => {
+                    assert_eq!(region.chunks.len(), 3);
+                    assert_eq!(region.chunks[0], region.chunks[1]);
+                    assert_eq!(stats.new_chunks, 3);
+                }
+                false => {
+                    assert_eq!(region.chunks.len(), 1);
+                    assert_eq!(stats.new_chunks, 2);
+                }
+            }
+
+            let restored = dir.join("restored");
+            fs::create_dir_all(&restored).unwrap();
+            restore_snapshot(&manifest_path, &restored).unwrap();
+            for file in ["world/level.dat", "world/region/r.0.0.mca"] {
+                assert_eq!(
+                    fs::read(restored.join(file)).unwrap(),
+                    fs::read(root.join(file)).unwrap()
+                );
+            }
+            assert!(restored.join("world/data").is_dir());
+            assert!(check_snapshot(&manifest_path).is_ok());
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn unchanged_files_reuse_their_chunks() {
+        let dir = scratch_dir("snapshot-reuse");
+        let root = dir.join("instance");
+        instance(&root);
+        let backups_dir = dir.join("backups");
+        fs::create_dir_all(&backups_dir).unwrap();
+        let entries = ["world".to_string()];
+        write_snapshot(
+            &root,
+            &entries,
+            &backups_dir.join("world-20250101-000000.snapshot.json"),
+            true,
+        )
+        .unwrap();
+        let chunks_dir = backups_dir.join(CHUNKS_DIR_NAME);
+        for chunk in every_chunk(&chunks_dir) {
+            age(&chunk);
+        }
+
+        // only the sector that changed is stored again
+        let mut region = fs::read(root.join("world/region/r.0.0.mca")).unwrap();
+        region[SECTOR_SIZE] = 8;
+        fs::write(root.join("world/region/r.0.0.mca"), region).unwrap();
+        let stats = write_snapshot(
+            &root,
+            &entries,
+            &backups_dir.join("world-20250102-000000.snapshot.json"),
+            true,
+        )
+        .unwrap();
+        assert_eq!(stats.chunks, 4);
+        assert_eq!(stats.new_chunks, 1);
+
+        // and the reused ones count as recent, so a prune running alongside keeps them
+        for chunk in every_chunk(&chunks_dir) {
+            let modified = fs::metadata(&chunk).unwrap().modified().unwrap();
+            assert!(modified.elapsed().unwrap() < GC_GRACE_PERIOD);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn garbage_collection_spares_referenced_and_recent_chunks() {
+        let dir = scratch_dir("snapshot-gc");
+        let root = dir.join("instance");
+        instance(&root);
+        let backups_dir = dir.join("backups");
+        fs::create_dir_all(&backups_dir).unwrap();
+        write_snapshot(
+            &root,
+            &["world".to_string()],
+            &backups_dir.join("world-20250101-000000.snapshot.json"),
+            false,
+        )
+        .unwrap();
+        let chunks_dir = backups_dir.join(CHUNKS_DIR_NAME);
+        let referenced = every_chunk(&chunks_dir);
+        for chunk in &referenced {
+            age(chunk);
+        }
+
+        let stale = chunk_path(&chunks_dir, &"a".repeat(64)).unwrap();
+        let recent = chunk_path(&chunks_dir, &"b".repeat(64)).unwrap();
+        let stale_temp = chunks_dir.join(format!("{}stale", TEMP_CHUNK_PREFIX));
+        let recent_temp = chunks_dir.join(format!("{}recent", TEMP_CHUNK_PREFIX));
+        for path in [&stale, &recent, &stale_temp, &recent_temp] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"unreferenced").unwrap();
+        }
+        age(&stale);
+        age(&stale_temp);
+
+        let (removed, freed) = collect_garbage(&backups_dir).unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(freed, 2 * b"unreferenced".len() as u64);
+        assert!(!stale.exists());
+        assert!(!stale_temp.exists());
+        assert!(recent.exists());
+        assert!(recent_temp.exists());
+        assert!(referenced.iter().all(|chunk| chunk.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_and_hashes_stay_inside_the_store() {
+        let root = Path::new("/backups");
+        assert_eq!(
+            safe_join(root, "world/./region/r.0.0.mca").unwrap(),
+            root.join("world/region/r.0.0.mca")
+        );
+        for unsafe_path in ["../x", "world/../../x", "/etc/passwd", "world\\..\\x"] {
+            assert!(safe_join(root, unsafe_path).is_err(), "{}", unsafe_path);
+        }
+
+        let hash = "0123456789abcdef".repeat(4);
+        assert_eq!(
+            chunk_path(root, &hash).unwrap(),
+            root.join("01").join(&hash)
+        );
+        for bad_hash in ["../../etc/passwd", &"g".repeat(64), &hash[..63], ""] {
+            assert!(chunk_path(root, bad_hash).is_err(), "{}", bad_hash);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::time::Duration;
+
+use crate::net::ping::{ping, PingResponse};
+use crate::net::query::{query, QueryResponse};
+use crate::properties::{parse_address, query_address, server_address};
+use crate::run::running_pid;
+use crate::server::{get_all_servers, Server};
+
+#[derive(serde_derive::Serialize)]
+struct InstanceStatus {
+    name: String,
+    flavor: String,
+    version: String,
+    address: String,
+    pid: Option<u32>,
+    ping: Option<PingResponse>,
+    ping_error: Option<String>,
+    // only for instances that are up with enable-query=true
+    query: Option<QueryResponse>,
+}
+
+// ping a registered instance by name, or any server by host[:port]
+pub fn ping_target(target: &String, timeout: Duration, json: bool) -> Result<(), String> {
+    let (host, port) = match Server::load_by_name(target) {
+        Ok(server) => server_address(&server.path.join(&server.name))?,
+        Err(_) => parse_address(target)?,
+    };
+
+    let response = ping(&host, port, timeout)?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| format!("failed to serialize ping response: {}", e))?;
+        println!("{}", json);
+    } else {
+        print_ping_response(&host, port, &response);
+    }
+
+    Ok(())
+}
+
+// show whether each instance's JVM is running and whether it's accepting connections
+pub fn show_status(name: Option<String>, timeout: Duration, json: bool) -> Result<(), String> {
+    let servers = match name {
+        Some(name) => vec![Server::load_by_name(&name)?],
+        None => get_all_servers()?,
+    };
+
+    let mut statuses = Vec::new();
+    for server in servers {
+        let server_dir = server.path.join(&server.name);
+        let (host, port) = server_address(&server_dir)?;
+        let pid = running_pid(&server_dir);
+
+        // a server started some other way has no pid file but still answers on its port
+        let (ping, ping_error) = match ping(&host, port, timeout) {
+            Ok(response) => (Some(response), None),
+            Err(e) => (None, pid.map(|_| e)),
+        };
+        let query = match (pid.is_some() || ping.is_some(), query_address(&server_dir)?) {
+            (true, Some((host, port))) => query(&host, port, true, timeout).ok(),
+            _ => None,
+        };
+
+        statuses.push(InstanceStatus {
+            name: server.name.clone(),
+            flavor: server.flavor.clone(),
+            version: server.version.clone(),
+            address: format!("{}:{}", host, port),
+            pid,
+            ping,
+            ping_error,
+            query,
+        });
+    }
+
+    if json {
+        let json = serde_json::to_string_pretty(&statuses)
+            .map_err(|e| format!("failed to serialize status: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    for status in statuses {
+        let state = match (status.pid, &status.ping, &status.ping_error) {
+            (None, None, _) => "stopped".to_string(),
+            (None, Some(ping), _) => format!(
+                "online (not started by slapaman, {}/{} players, {})",
+                ping.online_players,
+                ping.max_players,
+                format_latency(ping.latency_ms)
+            ),
+            (Some(pid), Some(ping), _) => format!(
+                "online (pid {}, {}/{} players, {})",
+                pid,
+                ping.online_players,
+                ping.max_players,
+                format_latency(ping.latency_ms)
+            ),
+            (Some(pid), None, Some(e)) => {
+                format!("running (pid {}) but not responding: {}", pid, e)
+            }
+            (Some(pid), None, None) => format!("running (pid {})", pid),
+        };
+        println!(
+            "{}: {} [{} {}, {}]",
+            status.name, state, status.flavor, status.version, status.address
+        );
+    }
+
+    Ok(())
+}
+
+// full player list, plugins, and map over the UDP query protocol
+pub fn query_instance(
+    name: &String,
+    basic: bool,
+    timeout: Duration,
+    json: bool,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let server_dir = server.path.join(name);
+    let (host, port) = query_address(&server_dir)?.ok_or_else(|| {
+        format!(
+            "query is not enabled for {} (set enable-query=true in server.properties)",
+            name
+        )
+    })?;
+
+    let response = query(&host, port, !basic, timeout)?;
+
+    if json {
+        let json = serde_json::to_string_pretty(&response)
+            .map_err(|e| format!("failed to serialize query response: {}", e))?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    println!("{}:{}", host, port);
+    println!("  motd: {}", response.motd);
+    println!("  game type: {}", response.game_type);
+    println!("  map: {}", response.map);
+    if response.full {
+        println!("  version: {}", response.version);
+        if !response.server_mod.is_empty() {
+            println!("  server: {}", response.server_mod);
+        }
+        if !response.plugins.is_empty() {
+            println!("  plugins: {}", response.plugins.join(", "));
+        }
+    }
+    println!(
+        "  players: {}/{}",
+        response.online_players, response.max_players
+    );
+    for player in &response.players {
+        println!("    {}", player);
+    }
+
+    Ok(())
+}
+
+fn print_ping_response(host: &str, port: u16, response: &PingResponse) {
+    println!(
+        "{}:{} ({})",
+        host,
+        port,
+        format_latency(response.latency_ms)
+    );
+    if response.legacy {
+        println!("  version: {} (legacy ping)", response.version);
+    } else {
+        println!(
+            "  version: {} (protocol {})",
+            response.version, response.protocol
+        );
+    }
+    println!("  motd: {}", response.motd);
+    println!(
+        "  players: {}/{}",
+        response.online_players, response.max_players
+    );
+    for player in &response.sample {
+        println!("    {} ({})", player.name, player.id);
+    }
+}
+
+fn format_latency(latency_ms: Option<u64>) -> String {
+    match latency_ms {
+        Some(ms) => format!("{} ms", ms),
+        None => "n/a".to_string(),
+    }
+}
@@ -0,0 +1,277 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::ValueEnum;
+
+use crate::server::Server;
+
+// how long `slapaman stop` gives the server to shut down after `stop` before killing it
+const STOP_TIMEOUT_SECS: u64 = 90;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemdAction {
+    /// print the unit file
+    Generate,
+    /// write the unit file and enable it
+    Install,
+    /// disable and stop the unit, then remove its file
+    Uninstall,
+}
+
+// `run_as` is the account a system unit runs the server as, which otherwise is whoever invoked
+// slapaman (through sudo, if that's how it's running)
+pub fn systemd(
+    name: &String,
+    action: SystemdAction,
+    user: bool,
+    run_as: Option<&str>,
+) -> Result<(), String> {
+    let server = Server::load_by_name(name)?;
+    let unit_name = unit_name(name);
+
+    match action {
+        SystemdAction::Generate => {
+            let account = (!user).then(|| service_account(run_as)).transpose()?;
+            print!("{}", generate_unit(&server, account.as_ref())?);
+            Ok(())
+        }
+        SystemdAction::Install => {
+            let account = (!user).then(|| service_account(run_as)).transpose()?;
+            let unit_path = unit_dir(user)?.join(&unit_name);
+            if let Some(parent) = unit_path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+            }
+            fs::write(&unit_path, generate_unit(&server, account.as_ref())?)
+                .map_err(|e| format!("failed to write {}: {}", unit_path.display(), e))?;
+            println!("[slapaman] wrote {}", unit_path.display());
+            if let Some(account) = account.as_ref() {
+                if home::home_dir().as_deref() != Some(account.home.as_path()) {
+                    println!(
+                        "[slapaman] note: the unit runs as {} and reads the registry under {}, so {} has to be registered there too",
+                        account.name,
+                        account.home.display(),
+                        name
+                    );
+                }
+            }
+
+            systemctl(user, &["daemon-reload"])?;
+            systemctl(user, &["enable", &unit_name])?;
+            println!(
+                "[slapaman] start it with: systemctl {}start {}",
+                if user { "--user " } else { "" },
+                unit_name
+            );
+            Ok(())
+        }
+        SystemdAction::Uninstall => {
+            let unit_path = unit_dir(user)?.join(&unit_name);
+            if !unit_path.exists() {
+                return Err(format!("{} is not installed", unit_path.display()));
+            }
+
+            // stopping goes through ExecStop, so the server still shuts down cleanly
+            systemctl(user, &["disable", "--now", &unit_name])?;
+            fs::remove_file(&unit_path)
+                .map_err(|e| format!("failed to remove {}: {}", unit_path.display(), e))?;
+            systemctl(user, &["daemon-reload"])?;
+            println!("[slapaman] removed {}", unit_path.display());
+            Ok(())
+        }
+    }
+}
+
+pub fn unit_name(name: &str) -> String {
+    format!("slapaman-{}.service", name)
+}
+
+// the account a system unit runs the server as, and its home directory, where slapaman's registry
+// is read from
+pub struct ServiceAccount {
+    pub name: String,
+    pub home: PathBuf,
+}
+
+// a user unit (`account` is None) runs as whoever it belongs to
+pub fn generate_unit(server: &Server, account: Option<&ServiceAccount>) -> Result<String, String> {
+    let slapaman = env::current_exe().map_err(|e| format!("failed to locate slapaman: {}", e))?;
+    let slapaman = quote(&slapaman.display().to_string());
+    let server_dir = server.path.join(&server.name);
+    let name = quote(&server.name);
+
+    let mut unit = String::new();
+    unit.push_str("[Unit]\n");
+    unit.push_str(&format!(
+        "Description=Minecraft server {} (slapaman)\n",
+        escape_specifiers(&server.name)
+    ));
+    unit.push_str("After=network-online.target\n");
+    unit.push_str("Wants=network-online.target\n");
+    // give up after five crashes in ten minutes instead of restarting forever
+    unit.push_str("StartLimitIntervalSec=600\n");
+    unit.push_str("StartLimitBurst=5\n");
+
+    unit.push_str("\n[Service]\n");
+    unit.push_str("Type=simple\n");
+    if let Some(account) = account {
+        unit.push_str(&format!("User={}\n", account.name));
+        unit.push_str(&format!(
+            "Environment=HOME={}\n",
+            quote(&account.home.display().to_string())
+        ));
+    }
+    unit.push_str(&format!(
+        "WorkingDirectory={}\n",
+        escape_specifiers(&server_dir.display().to_string())
+    ));
+    // the memory and JVM arguments come from the instance's stored settings, and systemd rather
+    // than the slapaman daemon supervises the server
+    unit.push_str(&format!(
+        "ExecStart={} run {} --no-daemon\n",
+        slapaman, name
+    ));
+    unit.push_str(&format!(
+        "ExecStop={} stop {} --timeout {}\n",
+        slapaman, name, STOP_TIMEOUT_SECS
+    ));
+    // the JVM is only signalled if `slapaman stop` itself gets stuck
+    unit.push_str("KillMode=mixed\n");
+    unit.push_str(&format!("TimeoutStopSec={}\n", STOP_TIMEOUT_SECS + 30));
+    unit.push_str("Restart=on-failure\n");
+    unit.push_str("RestartSec=10\n");
+
+    unit.push_str("NoNewPrivileges=true\n");
+    unit.push_str("PrivateTmp=true\n");
+    unit.push_str("ProtectSystem=strict\n");
+    unit.push_str("ProtectHome=read-only\n");
+    unit.push_str(&format!(
+        "ReadWritePaths={}\n",
+        quote(&server_dir.display().to_string())
+    ));
+
+    unit.push_str("\n[Install]\n");
+    unit.push_str(match account {
+        None => "WantedBy=default.target\n",
+        Some(_) => "WantedBy=multi-user.target\n",
+    });
+
+    Ok(unit)
+}
+
+// whether the instance's unit is what's running it, and if so, whether it's a user unit
+pub fn active_unit(name: &str) -> Option<bool> {
+    let unit_name = unit_name(name);
+    [true, false].into_iter().find(|user| {
+        unit_dir(*user).is_ok_and(|dir| dir.join(&unit_name).exists())
+            && systemctl(*user, &["is-active", "--quiet", &unit_name]).is_ok()
+    })
+}
+
+// restart the instance's unit, which stops the server through its ExecStop and starts it again
+pub fn restart_unit(name: &str, user: bool) -> Result<(), String> {
+    systemctl(user, &["--no-ask-password", "restart", &unit_name(name)])
+}
+
+fn unit_dir(user: bool) -> Result<PathBuf, String> {
+    match user {
+        true => Ok(home::home_dir()
+            .ok_or("could not determine a home directory")?
+            .join(".config/systemd/user")),
+        false => Ok(Path::new("/etc/systemd/system").to_path_buf()),
+    }
+}
+
+fn systemctl(user: bool, args: &[&str]) -> Result<(), String> {
+    let mut command = Command::new("systemctl");
+    if user {
+        command.arg("--user");
+    }
+    let status = command
+        .args(args)
+        .status()
+        .map_err(|e| format!("failed to run systemctl: {}", e))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("systemctl {} failed: {}", args.join(" "), status)),
+    }
+}
+
+// installing a system unit takes root, so under sudo the server runs as the user who ran sudo
+// rather than as root; running it as root takes asking for it with --run-as root
+fn service_account(run_as: Option<&str>) -> Result<ServiceAccount, String> {
+    let sudo_user = env::var("SUDO_USER").ok().filter(|u| !u.is_empty());
+    let name = match (run_as, &sudo_user) {
+        (Some(run_as), _) => run_as.to_string(),
+        (None, Some(sudo_user)) => sudo_user.clone(),
+        (None, None) => current_user()?,
+    };
+    if name == "root" && run_as.is_none() {
+        return Err(
+            "refusing to run the server as root; pass --run-as <user> (or --run-as root if you really mean it)"
+                .to_string(),
+        );
+    }
+    let home = account_home(&name)?;
+
+    // the unit reads the registry under the account's home, so it has to be the one this
+    // instance was found in; sudo usually points HOME at root's
+    if run_as.is_none() && home::home_dir().as_deref() != Some(home.as_path()) {
+        return Err(format!(
+            "{}'s registry is under {}, but this is reading the one under {}; run with `sudo --preserve-env=HOME`, or pass --run-as",
+            name,
+            home.display(),
+            home::home_dir()
+                .map(|h| h.display().to_string())
+                .unwrap_or_default()
+        ));
+    }
+    Ok(ServiceAccount { name, home })
+}
+
+// an account's home directory, from the passwd database
+fn account_home(account: &str) -> Result<PathBuf, String> {
+    let output = Command::new("getent")
+        .args(["passwd", account])
+        .output()
+        .map_err(|e| format!("failed to look up user {}: {}", account, e))?;
+    let entry = String::from_utf8_lossy(&output.stdout);
+    entry
+        .trim()
+        .split(':')
+        .nth(5)
+        .filter(|home| output.status.success() && !home.is_empty())
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("no such user: {}", account))
+}
+
+fn current_user() -> Result<String, String> {
+    if let Ok(account) = env::var("USER") {
+        if !account.is_empty() {
+            return Ok(account);
+        }
+    }
+    let output = Command::new("id")
+        .arg("-un")
+        .output()
+        .map_err(|e| format!("failed to determine the current user: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// systemd splits command lines on whitespace unless the word is quoted, and expands %
+fn quote(word: &str) -> String {
+    let word = escape_specifiers(word);
+    match word.contains(char::is_whitespace) || word.contains('"') {
+        true => format!("\"{}\"", word.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => word,
+    }
+}
+
+fn escape_specifiers(value: &str) -> String {
+    value.replace('%', "%%")
+}
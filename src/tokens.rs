@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use chrono::Local;
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use sha2::{Digest, Sha256};
+
+const TOKENS_FILE: &str = "tokens.json";
+const TOKEN_PREFIX: &str = "slap_";
+
+// what a token may do through the HTTP API; each scope includes the ones before it
+#[derive(
+    ValueEnum,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenScope {
+    /// list instances, read properties and follow logs
+    ReadOnly,
+    /// also start, stop and restart instances, send console commands and take backups
+    Operator,
+    /// also edit server.properties
+    Admin,
+}
+
+impl TokenScope {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenScope::ReadOnly => "read-only",
+            TokenScope::Operator => "operator",
+            TokenScope::Admin => "admin",
+        }
+    }
+}
+
+// only a hash of each token is kept, so the tokens file alone can't be used to log in
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct ApiToken {
+    pub id: u32,
+    pub label: String,
+    pub scope: TokenScope,
+    pub sha256: String,
+    pub created: String,
+}
+
+// create a token and return its secret, which is shown only this once
+pub fn create_token(label: &str, scope: TokenScope) -> Result<(u32, String), String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|e| format!("failed to generate a random token: {}", e))?;
+    let secret = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    );
+
+    let mut tokens = load_tokens()?;
+    let id = tokens.iter().map(|t| t.id).max().unwrap_or(0) + 1;
+    tokens.push(ApiToken {
+        id,
+        label: label.to_string(),
+        scope,
+        sha256: hash_token(&secret),
+        created: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    });
+    save_tokens(&tokens)?;
+
+    Ok((id, secret))
+}
+
+pub fn list_tokens() -> Result<(), String> {
+    let tokens = load_tokens()?;
+    if tokens.is_empty() {
+        println!("no API tokens (create one with `slapaman token create <label>`)");
+        return Ok(());
+    }
+
+    for token in tokens {
+        println!(
+            "{}: {} [{}] (created {})",
+            token.id,
+            token.label,
+            token.scope.name(),
+            token.created
+        );
+    }
+
+    Ok(())
+}
+
+pub fn revoke_token(id: u32) -> Result<(), String> {
+    let mut tokens = load_tokens()?;
+    let before = tokens.len();
+    tokens.retain(|t| t.id != id);
+    if tokens.len() == before {
+        return Err(format!("no API token with id {}", id));
+    }
+    save_tokens(&tokens)
+}
+
+// the stored token matching a bearer secret, if any
+pub fn authenticate(secret: &str) -> Result<Option<ApiToken>, String> {
+    let hash = hash_token(secret.trim());
+    Ok(load_tokens()?.into_iter().find(|t| t.sha256 == hash))
+}
+
+pub fn load_tokens() -> Result<Vec<ApiToken>, String> {
+    let path = tokens_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(&path).map_err(|e| format!("failed to open tokens file: {}", e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("failed to parse tokens file: {}", e))
+}
+
+fn save_tokens(tokens: &[ApiToken]) -> Result<(), String> {
+    let path = tokens_path();
+    let temp_path = path.with_extension("json.tmp");
+
+    let file =
+        File::create(&temp_path).map_err(|e| format!("failed to create tokens file: {}", e))?;
+    // the hashes aren't secrets, but nobody else needs to see who holds which token
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("failed to restrict tokens file: {}", e))?;
+    }
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, tokens)
+        .map_err(|e| format!("failed to write tokens file: {}", e))?;
+    writer
+        .flush()
+        .map_err(|e| format!("failed to write tokens file: {}", e))?;
+    drop(writer);
+
+    fs::rename(&temp_path, &path).map_err(|e| format!("failed to save tokens file: {}", e))
+}
+
+fn tokens_path() -> PathBuf {
+    ProjectDirs::from("com", "wyomingwade", "slapaman")
+        .expect("could not determine a home directory")
+        .data_dir()
+        .join(TOKENS_FILE)
+}
+
+fn hash_token(secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
@@ -1,8 +1,11 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Wyoming Wade
 
+use serde_json::json;
+
 use crate::server::{get_all_servers, update_server_by_name, Server};
 use crate::version::{download_server_version, format_version_string, Version};
+use crate::webhooks::{notify, Notification, WebhookEvent};
 
 // basically, replace the server.jar file with a new one while preserving everything else
 pub async fn update_server(
@@ -12,7 +15,7 @@ pub async fn update_server(
 ) -> Result<(), String> {
     // load the server
     // this will fail if the server doesn't exist
-    let server = Server::load_by_name(&name).unwrap();
+    let server = Server::load_by_name(name).unwrap();
 
     let target_flavor = flavor_override
         .map(|f| f.to_lowercase())
@@ -34,9 +37,22 @@ pub async fn update_server(
 
     // update the server's version in slapaman's master list
     let mut server_new = server.clone();
-    server_new.version = version_string;
-    server_new.flavor = target_flavor;
-    update_server_by_name(&name, &server_new).unwrap();
+    server_new.version = version_string.clone();
+    server_new.flavor = target_flavor.clone();
+    update_server_by_name(name, &server_new).unwrap();
+
+    notify(Notification::new(
+        WebhookEvent::Updated,
+        name,
+        format!(
+            "{} was updated from {} ({}) to {} ({})",
+            name, server.version, server.flavor, version_string, target_flavor
+        ),
+        json!({
+            "from": { "version": server.version, "flavor": server.flavor },
+            "to": { "version": version_string, "flavor": target_flavor },
+        }),
+    ));
 
     Ok(())
 }
@@ -55,3 +71,14 @@ pub async fn update_all_servers(version: Version) -> Result<(), String> {
 
     Ok(())
 }
+
+// the newest version of the instance's release channel, if it isn't already on it
+pub async fn check_for_update(server: &Server) -> Result<Option<String>, String> {
+    let current = Version::from_string(server.version.clone());
+    let latest = format_version_string(&Version::new("latest".to_string(), current.v_type)).await;
+
+    match latest == server.version {
+        true => Ok(None),
+        false => Ok(Some(latest)),
+    }
+}
@@ -4,8 +4,9 @@
 use clap::ValueEnum;
 use serde_json::Value;
 use sha1::{Digest, Sha1};
+use std::fmt;
 use std::io::Write;
-use std::{fs::File, path::PathBuf};
+use std::{fs::File, path::Path};
 
 use crate::flavors::fabric::get_fabric_version_bytes;
 use crate::flavors::paper::get_paper_version_bytes;
@@ -35,11 +36,13 @@ impl Version {
 
         Self::new(v_id.to_string(), v_type)
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.v_type {
-            VersionType::Snapshot => format!("snapshot-{}", self.v_id),
-            VersionType::Release => format!("release-{}", self.v_id),
+            VersionType::Snapshot => write!(f, "snapshot-{}", self.v_id),
+            VersionType::Release => write!(f, "release-{}", self.v_id),
         }
     }
 }
@@ -54,7 +57,7 @@ pub enum VersionType {
 pub async fn download_server_version(
     version_id: &Version,
     flavor: &String,
-    default_directory: &PathBuf,
+    default_directory: &Path,
     server_name: &str,
     overwrite_existing: bool,
 ) -> Result<(), String> {
@@ -67,7 +70,7 @@ pub async fn download_server_version(
             Ok(server_jar_bytes)
         }
         "fabric" => {
-            let server_jar_bytes = get_fabric_version_bytes(&version_id, None, None)
+            let server_jar_bytes = get_fabric_version_bytes(version_id, None, None)
                 .await
                 .unwrap();
             Ok(server_jar_bytes)
@@ -79,7 +82,7 @@ pub async fn download_server_version(
                 let server_jar_bytes = get_paper_version_bytes(&version_id_new).await.unwrap();
                 Ok(server_jar_bytes)
             } else {
-                let server_jar_bytes = get_paper_version_bytes(&version_id).await.unwrap();
+                let server_jar_bytes = get_paper_version_bytes(version_id).await.unwrap();
                 Ok(server_jar_bytes)
             }
         }
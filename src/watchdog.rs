@@ -0,0 +1,357 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use chrono::Local;
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::java::jdks_dir;
+use crate::logs::LOGS_DIR_NAME;
+use crate::net::ping::ping;
+use crate::net::rcon::RconClient;
+use crate::process::{signal, terminate};
+use crate::properties::{rcon_address, server_address};
+use crate::run::{mark_stop_reason, restart_supervised, running_pid, spawn_background_server};
+use crate::server::{get_all_servers, record_restart, Server};
+
+const THREAD_DUMPS_DIR_NAME: &str = "thread-dumps";
+const STOP_GRACE: Duration = Duration::from_secs(30);
+const TOOL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProbeKind {
+    /// Server List Ping
+    Ping,
+    /// an RCON `list` command (needs enable-rcon and rcon.password)
+    Rcon,
+    /// both have to answer
+    Both,
+}
+
+#[derive(Clone, Debug)]
+pub struct WatchdogConfig {
+    pub probe: ProbeKind,
+    pub interval: Duration,
+    pub timeout: Duration,
+    pub max_failures: u32,
+    // how long after a (re)start to wait before probes count
+    pub grace: Duration,
+    // treat a server as hung when nothing has been logged for this long
+    pub stale_log: Option<Duration>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            probe: ProbeKind::Ping,
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            max_failures: 3,
+            grace: Duration::from_secs(180),
+            stale_log: None,
+        }
+    }
+}
+
+struct InstanceWatch {
+    pid: u32,
+    since: Instant,
+    failures: u32,
+}
+
+// counts failed probes per instance; once an instance reaches the limit it gets a thread dump
+// and is reported as hung so the caller can restart it
+pub struct Watchdog {
+    config: WatchdogConfig,
+    watches: HashMap<String, InstanceWatch>,
+}
+
+impl Watchdog {
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self {
+            config,
+            watches: HashMap::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.config.interval
+    }
+
+    // returns true when the instance is hung and should be restarted
+    pub fn check(&mut self, server: &Server, pid: u32) -> bool {
+        let watch = self
+            .watches
+            .entry(server.name.clone())
+            .or_insert(InstanceWatch {
+                pid,
+                since: Instant::now(),
+                failures: 0,
+            });
+        // a new JVM gets a fresh grace period
+        if watch.pid != pid {
+            *watch = InstanceWatch {
+                pid,
+                since: Instant::now(),
+                failures: 0,
+            };
+        }
+        if watch.since.elapsed() < self.config.grace {
+            return false;
+        }
+
+        let server_dir = server.path.join(&server.name);
+        match probe(&server_dir, &self.config) {
+            Ok(_) => {
+                if watch.failures > 0 {
+                    println!("[slapaman] watchdog: {} is responding again", server.name);
+                }
+                watch.failures = 0;
+                false
+            }
+            Err(e) => {
+                watch.failures += 1;
+                println!(
+                    "[slapaman] watchdog: {} failed a probe ({}/{}): {}",
+                    server.name, watch.failures, self.config.max_failures, e
+                );
+                if watch.failures < self.config.max_failures {
+                    return false;
+                }
+
+                match capture_thread_dump(server, pid) {
+                    Ok(path) => println!(
+                        "[slapaman] watchdog: saved thread dump for {} to {}",
+                        server.name,
+                        path.display()
+                    ),
+                    Err(e) => println!(
+                        "[slapaman] watchdog: failed to capture thread dump for {}: {}",
+                        server.name, e
+                    ),
+                }
+                self.watches.remove(&server.name);
+                true
+            }
+        }
+    }
+
+    pub fn forget(&mut self, name: &str) {
+        self.watches.remove(name);
+    }
+}
+
+// watch running instances until interrupted, restarting any that hang
+pub fn run_watchdog(names: Vec<String>, config: WatchdogConfig, once: bool) -> Result<(), String> {
+    let mut watchdog = Watchdog::new(config);
+    let mut restarted: Vec<Child> = Vec::new();
+
+    println!("[slapaman] watchdog started");
+    loop {
+        let servers = match names.is_empty() {
+            true => get_all_servers()?,
+            false => names
+                .iter()
+                .map(Server::load_by_name)
+                .collect::<Result<Vec<Server>, String>>()?,
+        };
+
+        for server in servers {
+            let server_dir = server.path.join(&server.name);
+            let pid = match running_pid(&server_dir) {
+                Some(pid) => pid,
+                None => {
+                    watchdog.forget(&server.name);
+                    continue;
+                }
+            };
+
+            if !watchdog.check(&server, pid) {
+                continue;
+            }
+
+            println!("[slapaman] watchdog: restarting {}", server.name);
+            // a supervised server is restarted by its supervisor, which would otherwise see it
+            // go down and start a second JVM, or lose track of the one started here
+            match restart_supervised(&server.name) {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    println!(
+                        "[slapaman] watchdog: failed to restart {}: {}",
+                        server.name, e
+                    );
+                    continue;
+                }
+                None => (),
+            }
+            mark_stop_reason(&server_dir, "restarted by the watchdog");
+            if let Err(e) = terminate(pid, STOP_GRACE) {
+                println!("[slapaman] watchdog: failed to stop {}: {}", server.name, e);
+                continue;
+            }
+            // the old `slapaman run` needs a moment to notice and clean up its pid file
+            thread::sleep(Duration::from_secs(1));
+            match spawn_background_server(&server.name, None) {
+                Ok(child) => {
+                    restarted.push(child);
+                    if let Err(e) = record_restart(&server.name) {
+                        println!(
+                            "[slapaman] watchdog: failed to record restart of {}: {}",
+                            server.name, e
+                        );
+                    }
+                }
+                Err(e) => println!(
+                    "[slapaman] watchdog: failed to restart {}: {}",
+                    server.name, e
+                ),
+            }
+        }
+
+        // reap the background runs we started once they exit
+        restarted.retain_mut(|child| matches!(child.try_wait(), Ok(None)));
+
+        if once {
+            return Ok(());
+        }
+        thread::sleep(watchdog.interval());
+    }
+}
+
+fn probe(server_dir: &Path, config: &WatchdogConfig) -> Result<(), String> {
+    if matches!(config.probe, ProbeKind::Ping | ProbeKind::Both) {
+        let (host, port) = server_address(server_dir)?;
+        ping(&host, port, config.timeout).map_err(|e| format!("ping: {}", e))?;
+    }
+
+    if matches!(config.probe, ProbeKind::Rcon | ProbeKind::Both) {
+        let (host, port, password) = rcon_address(server_dir)?
+            .ok_or("RCON is not enabled (set enable-rcon and rcon.password)")?;
+        RconClient::connect(&host, port, &password, config.timeout)
+            .and_then(|mut rcon| rcon.command("list"))
+            .map_err(|e| format!("rcon: {}", e))?;
+    }
+
+    if let Some(stale_log) = config.stale_log {
+        if let Some(modified) = last_log_write(server_dir) {
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            if age > stale_log {
+                return Err(format!("nothing logged for {}s", age.as_secs()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the most recent write to either slapaman's captured logs or the server's latest.log
+fn last_log_write(server_dir: &Path) -> Option<SystemTime> {
+    let mut candidates = vec![server_dir.join("logs").join("latest.log")];
+    if let Ok(entries) = server_dir.join(LOGS_DIR_NAME).read_dir() {
+        candidates.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()));
+    }
+
+    candidates
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+// save the JVM's thread dump into the instance, preferring jcmd, then jstack, then SIGQUIT
+// (which makes the JVM print the dump into its own output, and so into the captured logs)
+pub fn capture_thread_dump(server: &Server, pid: u32) -> Result<PathBuf, String> {
+    let server_dir = server.path.join(&server.name);
+    let dumps_dir = server_dir.join(THREAD_DUMPS_DIR_NAME);
+    fs::create_dir_all(&dumps_dir)
+        .map_err(|e| format!("failed to create thread dumps directory: {}", e))?;
+    let dump_path = dumps_dir.join(format!(
+        "threaddump-{}.txt",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    let pid_arg = pid.to_string();
+    let attempts = [
+        ("jcmd", vec![pid_arg.as_str(), "Thread.print", "-l"]),
+        ("jstack", vec!["-l", pid_arg.as_str()]),
+    ];
+    for (tool, args) in attempts {
+        let tool_path = match jdk_tool(server, tool) {
+            Some(path) => path,
+            None => continue,
+        };
+        if run_tool(&tool_path, &args, &dump_path) {
+            return Ok(dump_path);
+        }
+    }
+    let _ = fs::remove_file(&dump_path);
+
+    if cfg!(unix) && signal(pid, "QUIT") {
+        let note = "jcmd and jstack were unavailable; the JVM was sent SIGQUIT and printed its \
+                    thread dump to the server output (see slapaman-logs)\n";
+        fs::write(&dump_path, note).map_err(|e| format!("failed to write thread dump: {}", e))?;
+        return Ok(dump_path);
+    }
+
+    Err("no way to capture a thread dump (install a JDK with jcmd or jstack)".to_string())
+}
+
+// run a JDK tool with its output going straight to `output`; attaching to a JVM that's
+// badly wedged can hang, so give up after a while
+fn run_tool(tool_path: &Path, args: &[&str], output: &Path) -> bool {
+    let file = match File::create(output) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    let mut child = match Command::new(tool_path)
+        .args(args)
+        .stdout(Stdio::from(file))
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(_) => return false,
+    };
+
+    let started = Instant::now();
+    while started.elapsed() < TOOL_TIMEOUT {
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                return status.success()
+                    && fs::metadata(output).map(|m| m.len() > 0).unwrap_or(false)
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(250)),
+            Err(_) => break,
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    false
+}
+
+// JDK tools live next to the java the instance runs with
+fn jdk_tool(server: &Server, tool: &str) -> Option<PathBuf> {
+    let file_name = match cfg!(windows) {
+        true => format!("{}.exe", tool),
+        false => tool.to_string(),
+    };
+
+    if let Some(major) = server.java {
+        let path = jdks_dir()
+            .join(major.to_string())
+            .join("bin")
+            .join(&file_name);
+        if path.exists() {
+            return Some(path);
+        }
+    }
+
+    which::which(tool).ok()
+}
@@ -0,0 +1,456 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Wyoming Wade
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{Local, SecondsFormat};
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use serde_json::{json, Value};
+
+use crate::net::http::post_json;
+use crate::server::{get_all_servers, update_server_by_name, Server};
+
+const WEBHOOKS_FILE: &str = "webhooks.json";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: u32 = 5;
+// retries back off from this, doubling each time
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+// the longest we'll honour a Retry-After header for
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(
+    ValueEnum, serde_derive::Serialize, serde_derive::Deserialize, Clone, Copy, Debug, PartialEq, Eq,
+)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Created,
+    Started,
+    Ready,
+    Stopped,
+    Crashed,
+    Updated,
+    BackupCompleted,
+    BackupFailed,
+    PlayerJoin,
+    PlayerLeave,
+}
+
+impl WebhookEvent {
+    // what a webhook without an explicit event list is sent
+    fn is_lifecycle(&self) -> bool {
+        !matches!(self, WebhookEvent::PlayerJoin | WebhookEvent::PlayerLeave)
+    }
+
+    // embed colours for Discord
+    fn color(&self) -> u32 {
+        match self {
+            WebhookEvent::Crashed | WebhookEvent::BackupFailed => 0xe74c3c,
+            WebhookEvent::Stopped => 0xe67e22,
+            WebhookEvent::Ready | WebhookEvent::BackupCompleted => 0x2ecc71,
+            _ => 0x3498db,
+        }
+    }
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = serde_json::to_value(self)
+            .ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(
+    ValueEnum,
+    serde_derive::Serialize,
+    serde_derive::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookFormat {
+    /// slapaman's own JSON payload
+    #[default]
+    Generic,
+    /// a Discord incoming webhook
+    Discord,
+    /// a Slack-compatible incoming webhook
+    Slack,
+}
+
+// one configured webhook, either global (in webhooks.json) or on an instance in the registry
+#[derive(serde_derive::Serialize, serde_derive::Deserialize, Clone, Debug)]
+pub struct Webhook {
+    pub id: u32,
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    // empty means every lifecycle event (everything but player joins and leaves)
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl Webhook {
+    fn wants(&self, event: WebhookEvent) -> bool {
+        match self.events.is_empty() {
+            true => event.is_lifecycle(),
+            false => self.events.contains(&event),
+        }
+    }
+}
+
+// something that happened to an instance, ready to be sent to webhooks
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub event: WebhookEvent,
+    pub instance: String,
+    pub message: String,
+    pub details: Value,
+}
+
+impl Notification {
+    pub fn new(event: WebhookEvent, instance: &str, message: String, details: Value) -> Self {
+        Self {
+            event,
+            instance: instance.to_string(),
+            message,
+            details,
+        }
+    }
+
+    fn payload(&self, format: WebhookFormat) -> Value {
+        let timestamp = Local::now().to_rfc3339_opts(SecondsFormat::Secs, false);
+        match format {
+            WebhookFormat::Generic => json!({
+                "event": self.event,
+                "instance": self.instance,
+                "message": self.message,
+                "timestamp": timestamp,
+                "details": self.details,
+            }),
+            WebhookFormat::Discord => json!({
+                "username": "slapaman",
+                "embeds": [{
+                    "title": format!("{}: {}", self.instance, self.event),
+                    "description": self.message,
+                    "color": self.event.color(),
+                    "timestamp": timestamp,
+                }],
+            }),
+            WebhookFormat::Slack => json!({
+                "text": format!("*{}* ({}): {}", self.instance, self.event, self.message),
+            }),
+        }
+    }
+}
+
+// send a notification to every webhook that wants it, returning once they've all been delivered
+// (or given up on), so short-lived commands don't exit before their notifications go out
+pub fn notify(notification: Notification) {
+    if let Some(handle) = deliver_all(notification) {
+        let _ = handle.join();
+    }
+}
+
+// like `notify`, but doesn't wait, for events reported while a server keeps running
+pub fn notify_in_background(notification: Notification) {
+    deliver_all(notification);
+}
+
+fn deliver_all(notification: Notification) -> Option<JoinHandle<()>> {
+    let webhooks: Vec<Webhook> = webhooks_for(&notification.instance)
+        .into_iter()
+        .filter(|w| w.wants(notification.event))
+        .collect();
+    if webhooks.is_empty() {
+        return None;
+    }
+
+    // deliveries get their own thread (and runtime), since callers may already be inside one
+    Some(thread::spawn(move || {
+        let runtime = match tokio::runtime::Runtime::new() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                println!(
+                    "[slapaman] failed to start async runtime for webhooks: {}",
+                    e
+                );
+                return;
+            }
+        };
+        runtime.block_on(async {
+            let mut deliveries = tokio::task::JoinSet::new();
+            for webhook in webhooks {
+                let notification = notification.clone();
+                deliveries.spawn(async move {
+                    if let Err(e) = deliver(&webhook, &notification).await {
+                        println!("[slapaman] webhook {} failed: {}", webhook.id, e);
+                    }
+                });
+            }
+            while deliveries.join_next().await.is_some() {}
+        });
+    }))
+}
+
+// post one payload, retrying with backoff on network errors, rate limits and server errors
+async fn deliver(webhook: &Webhook, notification: &Notification) -> Result<(), String> {
+    let payload = notification.payload(webhook.format);
+    let mut backoff = RETRY_BACKOFF;
+    let mut last_error = String::new();
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mut wait = backoff;
+        match post_json(&webhook.url, &payload, REQUEST_TIMEOUT).await {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                let status = response.status();
+                last_error = format!("{} responded with {}", masked_url(&webhook.url), status);
+                if status.as_u16() == 429 {
+                    if let Some(retry_after) = response
+                        .headers()
+                        .get("Retry-After")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse::<f64>().ok())
+                    {
+                        wait = Duration::from_secs_f64(retry_after.max(0.0)).min(MAX_RETRY_AFTER);
+                    }
+                } else if !status.is_server_error() {
+                    // anything else in the 4xx range won't get better by retrying
+                    return Err(last_error);
+                }
+            }
+            Err(e) => last_error = e,
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(wait).await;
+            backoff *= 2;
+        }
+    }
+
+    Err(format!(
+        "{} (gave up after {} attempts)",
+        last_error, MAX_ATTEMPTS
+    ))
+}
+
+// the global webhooks plus the instance's own
+fn webhooks_for(instance: &str) -> Vec<Webhook> {
+    let mut webhooks = load_global_webhooks().unwrap_or_default();
+    if let Ok(server) = Server::load_by_name(&instance.to_string()) {
+        webhooks.extend(server.webhooks);
+    }
+    webhooks
+}
+
+pub fn add_webhook(
+    instance: Option<&String>,
+    url: &str,
+    format: WebhookFormat,
+    events: Vec<WebhookEvent>,
+) -> Result<u32, String> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(format!("webhook URL must be http or https: {}", url));
+    }
+
+    // ids are shared between global and instance webhooks, so `remove` and `test` need only one
+    let id = all_webhooks()?.iter().map(|(_, w)| w.id).max().unwrap_or(0) + 1;
+    let webhook = Webhook {
+        id,
+        url: url.to_string(),
+        format,
+        events,
+    };
+
+    match instance {
+        Some(name) => {
+            let mut server = Server::load_by_name(name)?;
+            server.webhooks.push(webhook);
+            update_server_by_name(name, &server)?;
+        }
+        None => {
+            let mut webhooks = load_global_webhooks()?;
+            webhooks.push(webhook);
+            save_global_webhooks(&webhooks)?;
+        }
+    }
+
+    Ok(id)
+}
+
+pub fn remove_webhook(id: u32) -> Result<(), String> {
+    let mut webhooks = load_global_webhooks()?;
+    if webhooks.iter().any(|w| w.id == id) {
+        webhooks.retain(|w| w.id != id);
+        return save_global_webhooks(&webhooks);
+    }
+
+    for mut server in get_all_servers()? {
+        if server.webhooks.iter().any(|w| w.id == id) {
+            server.webhooks.retain(|w| w.id != id);
+            return update_server_by_name(&server.name.clone(), &server);
+        }
+    }
+
+    Err(format!("no webhook with id {}", id))
+}
+
+pub fn list_webhooks() -> Result<(), String> {
+    let webhooks = all_webhooks()?;
+    if webhooks.is_empty() {
+        println!("no webhooks configured");
+        return Ok(());
+    }
+
+    for (instance, webhook) in webhooks {
+        let events = match webhook.events.is_empty() {
+            true => "lifecycle events".to_string(),
+            false => webhook
+                .events
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<String>>()
+                .join(", "),
+        };
+        println!(
+            "{}: {} [{}] {} ({})",
+            webhook.id,
+            instance.as_deref().unwrap_or("global"),
+            serde_json::to_value(webhook.format)
+                .ok()
+                .and_then(|v| v.as_str().map(|s| s.to_string()))
+                .unwrap_or_default(),
+            masked_url(&webhook.url),
+            events
+        );
+    }
+
+    Ok(())
+}
+
+// webhook URLs usually carry their secret in the path or query (Discord, Slack), so only show
+// where they point
+fn masked_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.path() == "/" && parsed.query().is_none() => {
+            parsed.origin().ascii_serialization()
+        }
+        Ok(parsed) => format!("{}/...", parsed.origin().ascii_serialization()),
+        Err(_) => "(invalid URL)".to_string(),
+    }
+}
+
+// send a test notification to one webhook (or all of them), reporting each result
+pub fn test_webhooks(id: Option<u32>) -> Result<(), String> {
+    let webhooks: Vec<(Option<String>, Webhook)> = all_webhooks()?
+        .into_iter()
+        .filter(|(_, w)| id.is_none_or(|id| w.id == id))
+        .collect();
+    if webhooks.is_empty() {
+        return Err(match id {
+            Some(id) => format!("no webhook with id {}", id),
+            None => "no webhooks configured".to_string(),
+        });
+    }
+
+    let handle = thread::spawn(move || {
+        let runtime = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("failed to start async runtime: {}", e))?;
+        let mut failed = 0;
+        for (instance, webhook) in &webhooks {
+            let instance = instance.clone().unwrap_or_else(|| "slapaman".to_string());
+            let notification = Notification::new(
+                WebhookEvent::Ready,
+                &instance,
+                "this is a test notification from `slapaman webhook test`".to_string(),
+                json!({ "test": true }),
+            );
+            match runtime.block_on(deliver(webhook, &notification)) {
+                Ok(_) => println!("[slapaman] webhook {} delivered", webhook.id),
+                Err(e) => {
+                    println!("[slapaman] webhook {} failed: {}", webhook.id, e);
+                    failed += 1;
+                }
+            }
+        }
+        match failed {
+            0 => Ok(()),
+            n => Err(format!("{} webhook(s) failed", n)),
+        }
+    });
+
+    handle
+        .join()
+        .map_err(|_| "webhook test thread panicked".to_string())?
+}
+
+// every webhook, with the instance it belongs to (None for global ones)
+fn all_webhooks() -> Result<Vec<(Option<String>, Webhook)>, String> {
+    let mut webhooks: Vec<(Option<String>, Webhook)> = load_global_webhooks()?
+        .into_iter()
+        .map(|w| (None, w))
+        .collect();
+    for server in get_all_servers()? {
+        for webhook in server.webhooks {
+            webhooks.push((Some(server.name.clone()), webhook));
+        }
+    }
+    webhooks.sort_by_key(|(_, w)| w.id);
+    Ok(webhooks)
+}
+
+fn load_global_webhooks() -> Result<Vec<Webhook>, String> {
+    let path = webhooks_path();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = File::open(&path).map_err(|e| format!("failed to open webhooks file: {}", e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("failed to parse webhooks file: {}", e))
+}
+
+fn save_global_webhooks(webhooks: &[Webhook]) -> Result<(), String> {
+    let path = webhooks_path();
+    let temp_path = path.with_extension("json.tmp");
+
+    let file =
+        File::create(&temp_path).map_err(|e| format!("failed to create webhooks file: {}", e))?;
+    // webhook URLs carry their secret, so keep the file to ourselves
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("failed to restrict webhooks file: {}", e))?;
+    }
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, webhooks)
+        .map_err(|e| format!("failed to write webhooks file: {}", e))?;
+    writer
+        .flush()
+        .map_err(|e| format!("failed to write webhooks file: {}", e))?;
+    drop(writer);
+
+    fs::rename(&temp_path, &path).map_err(|e| format!("failed to save webhooks file: {}", e))
+}
+
+fn webhooks_path() -> PathBuf {
+    ProjectDirs::from("com", "wyomingwade", "slapaman")
+        .expect("could not determine a home directory")
+        .data_dir()
+        .join(WEBHOOKS_FILE)
+}
@@ -3,21 +3,110 @@
 
 use fs_extra::{copy_items, dir::CopyOptions};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::backup::{take_safety_snapshot, BackupScope};
+use crate::crypto::BackupKeys;
+use crate::properties::read_properties;
+use crate::run::running_pid;
 use crate::server::Server;
 
+// the overworld's folder when server.properties doesn't set level-name
+const DEFAULT_LEVEL_NAME: &str = "world";
+// Bukkit-based servers keep the Nether and the End next to the overworld instead of inside it
+const SPLIT_DIMENSION_FLAVORS: [&str; 4] = ["paper", "spigot", "purpur", "folia"];
+const DIMENSION_SUFFIXES: [&str; 2] = ["_nether", "_the_end"];
+// the file a running server keeps locked in each world it has open
+const SESSION_LOCK: &str = "session.lock";
+// where `world-set` copies a world to before swapping it in
+const WORLD_STAGING_DIR_NAME: &str = ".slapaman-world-set";
+// where swapped-out files wait until the new ones are all in place
+const REPLACED_DIR_NAME: &str = ".slapaman-replaced";
+
+// a world's dimensions
+#[derive(serde_derive::Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "overworld" => Some(Dimension::Overworld),
+            "nether" | "the_nether" => Some(Dimension::Nether),
+            "end" | "the_end" => Some(Dimension::End),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dimension::Overworld => "overworld",
+            Dimension::Nether => "nether",
+            Dimension::End => "end",
+        }
+    }
+}
+
+// the folder holding a dimension's region, entities and poi folders, relative to the instance
+// directory; the game keeps the Nether and the End in DIM-1 and DIM1 inside their world folder,
+// which is the overworld's unless the flavor splits them out
+pub fn dimension_dir(world_dirs: &[String], dimension: Dimension) -> String {
+    let (index, subfolder) = match dimension {
+        Dimension::Overworld => return world_dirs[0].clone(),
+        Dimension::Nether => (1, "DIM-1"),
+        Dimension::End => (2, "DIM1"),
+    };
+    format!(
+        "{}/{}",
+        world_dirs.get(index).unwrap_or(&world_dirs[0]),
+        subfolder
+    )
+}
+
+// the world folders of an instance relative to its directory, overworld first; the other
+// dimensions are listed whether they exist yet or not
+pub fn world_dirs(server: &Server, server_dir: &Path) -> Result<Vec<String>, String> {
+    let level_name = read_properties(server_dir)
+        .ok()
+        .and_then(|properties| properties.get("level-name").cloned())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_string());
+    if level_name.contains(['/', '\\']) || level_name == "." || level_name == ".." {
+        return Err(format!(
+            "unsupported level-name in server.properties: {}",
+            level_name
+        ));
+    }
+
+    let mut dirs = vec![level_name.clone()];
+    if SPLIT_DIMENSION_FLAVORS.contains(&server.flavor.as_str()) {
+        dirs.extend(
+            DIMENSION_SUFFIXES
+                .iter()
+                .map(|suffix| format!("{}{}", level_name, suffix)),
+        );
+    }
+    Ok(dirs)
+}
+
 // set the world for a server instance to a pre-existing world
 pub fn set_world(
     // slapaman params
-    _verbose: u8,
+    verbose: u8,
     // command args
     name: String,
-    world_path: &PathBuf,
+    world_path: &Path,
+    // back up the current world first
+    snapshot: bool,
 ) -> Result<(), String> {
     println!("[slapaman] setting world for server instance: {}", name);
 
-    let server = Server::load_by_name(&name).unwrap();
+    let server = Server::load_by_name(&name)?;
 
     // check if world_path is a valid world
     if !world_path.exists() {
@@ -40,18 +129,209 @@ pub fn set_world(
         ));
     }
 
-    // get the server's world directory
-    let server_dir = server.path.join(&name).join("world");
+    let server_dir = server.path.join(&name);
+    ensure_stopped(&server, &server_dir)?;
+    if snapshot {
+        take_safety_snapshot(
+            verbose,
+            &name,
+            BackupScope::World,
+            &BackupKeys::new(&server, None),
+        )?;
+    }
+    install_world(&server, &server_dir, world_path)?;
+
+    println!("[slapaman] world set for server instance: {}", name);
+    Ok(())
+}
+
+// copy a world into an instance, replacing its current one
+pub fn install_world(server: &Server, server_dir: &Path, world_path: &Path) -> Result<(), String> {
+    // the overworld goes where level-name says, and on flavors that keep the other dimensions
+    // in their own folders, any `<world>_nether` and `<world>_the_end` next to the given world
+    // come along with it
+    let world_dirs = world_dirs(server, server_dir)?;
+    let source_name = world_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let sources = std::iter::once(world_path.to_path_buf()).chain(
+        DIMENSION_SUFFIXES
+            .iter()
+            .map(|suffix| world_path.with_file_name(format!("{}{}", source_name, suffix))),
+    );
 
-    // remove existing world directory if it exists
-    if server_dir.exists() {
-        fs::remove_dir_all(&server_dir)
-            .map_err(|e| format!("failed to remove existing world: {}", e))?;
+    // everything is copied next to the instance first, so a failed copy leaves the current
+    // world untouched
+    let staging_dir = server_dir.join(WORLD_STAGING_DIR_NAME);
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)
+            .map_err(|e| format!("failed to clear old staging directory: {}", e))?;
     }
+    let mut copied = Vec::new();
+    let mut result = Ok(());
+    for (i, (world_dir, source)) in world_dirs.iter().zip(sources).enumerate() {
+        if i > 0 && !source.is_dir() {
+            continue;
+        }
+        result = copy_dir(&source, &staging_dir.join(world_dir));
+        if result.is_err() {
+            break;
+        }
+        copied.push(world_dir.clone());
+    }
+    let result = result.and_then(|_| swap_in(server_dir, &staging_dir, &copied, &[]));
+    let _ = fs::remove_dir_all(&staging_dir);
+    result
+}
 
+// refuse to touch the world of an instance that's running, whether slapaman started it (and
+// tracks its pid) or not (the server holds a lock on each world's session.lock while it's open)
+pub fn ensure_stopped(server: &Server, server_dir: &Path) -> Result<(), String> {
+    if let Some(pid) = running_pid(server_dir) {
+        return Err(format!(
+            "{} is running (pid {}); stop it first with `slapaman stop {}`",
+            server.name, pid, server.name
+        ));
+    }
+    if let Some(world_dir) = locked_world(server, server_dir)? {
+        return Err(format!(
+            "{} is in use by a running server (its {} is locked); stop the server first",
+            world_dir, SESSION_LOCK
+        ));
+    }
+    Ok(())
+}
+
+// whether the instance's server is up, by the same checks as `ensure_stopped`
+pub fn server_running(server: &Server, server_dir: &Path) -> Result<bool, String> {
+    Ok(running_pid(server_dir).is_some() || locked_world(server, server_dir)?.is_some())
+}
+
+// the first world dir a server holds open
+fn locked_world(server: &Server, server_dir: &Path) -> Result<Option<String>, String> {
+    Ok(world_dirs(server, server_dir)?
+        .into_iter()
+        .find(|world_dir| session_locked(&server_dir.join(world_dir).join(SESSION_LOCK))))
+}
+
+// Java locks files with fcntl, so ask whether anyone holds a lock on it
+#[cfg(unix)]
+fn session_locked(path: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    // F_GETLK overwrites l_type with F_UNLCK when nothing would conflict with the lock
+    let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) };
+    result == 0 && lock.l_type as libc::c_int != libc::F_UNLCK
+}
+
+// Windows refuses to read a locked file
+#[cfg(not(unix))]
+fn session_locked(path: &Path) -> bool {
+    path.exists() && fs::read(path).is_err()
+}
+
+// move `entries` (which may be nested paths) from `staging_dir` into `server_dir` (which must be on
+// the same filesystem), replacing whatever is there, and take out the `removed` entries too; what
+// gets replaced is moved aside rather than deleted until everything new is in place, so a failure
+// part way through puts it all back instead of leaving the instance without a world
+pub fn swap_in(
+    server_dir: &Path,
+    staging_dir: &Path,
+    entries: &[String],
+    removed: &[String],
+) -> Result<(), String> {
+    let aside_dir = server_dir.join(REPLACED_DIR_NAME);
+    if aside_dir.exists() {
+        fs::remove_dir_all(&aside_dir)
+            .map_err(|e| format!("failed to clear {}: {}", aside_dir.display(), e))?;
+    }
+    fs::create_dir_all(&aside_dir)
+        .map_err(|e| format!("failed to create {}: {}", aside_dir.display(), e))?;
+
+    let mut moved_aside = Vec::new();
+    let mut installed = Vec::new();
+    let mut result = Ok(());
+    let mut replaced: Vec<&String> = entries.iter().chain(removed).collect();
+    replaced.sort();
+    replaced.dedup();
+    for entry in replaced {
+        let target = server_dir.join(entry);
+        if !target.exists() && !target.is_symlink() {
+            continue;
+        }
+        result = create_parent(&aside_dir.join(entry))
+            .and_then(|_| fs::rename(&target, aside_dir.join(entry)))
+            .map_err(|e| format!("failed to move {} aside: {}", entry, e));
+        if result.is_err() {
+            break;
+        }
+        moved_aside.push(entry);
+    }
+    if result.is_ok() {
+        for entry in entries {
+            result = create_parent(&server_dir.join(entry))
+                .and_then(|_| fs::rename(staging_dir.join(entry), server_dir.join(entry)))
+                .map_err(|e| format!("failed to move {} into place: {}", entry, e));
+            if result.is_err() {
+                break;
+            }
+            installed.push(entry);
+        }
+    }
+
+    if let Err(e) = result {
+        // undo in reverse, so the instance ends up exactly as it was
+        let mut stuck = Vec::new();
+        for entry in installed {
+            if fs::rename(server_dir.join(entry), staging_dir.join(entry)).is_err() {
+                stuck.push(entry.as_str());
+            }
+        }
+        for entry in moved_aside {
+            if fs::rename(aside_dir.join(entry), server_dir.join(entry)).is_err() {
+                stuck.push(entry.as_str());
+            }
+        }
+        return Err(match stuck.is_empty() {
+            true => e,
+            false => format!(
+                "{}, and putting back {} failed; the previous files are in {}",
+                e,
+                stuck.join(", "),
+                aside_dir.display()
+            ),
+        });
+    }
+
+    if let Err(e) = fs::remove_dir_all(&aside_dir) {
+        println!(
+            "[slapaman] warning: failed to remove the replaced files in {}: {}",
+            aside_dir.display(),
+            e
+        );
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+// copy the directory `source` to `target`, which must not exist yet
+fn copy_dir(source: &Path, target: &Path) -> Result<(), String> {
     // create the world directory
-    fs::create_dir_all(&server_dir)
-        .map_err(|e| format!("failed to create world directory: {}", e))?;
+    fs::create_dir_all(target).map_err(|e| format!("failed to create world directory: {}", e))?;
 
     // copy the contents of the world to the server's world directory
     let options = CopyOptions {
@@ -63,15 +343,15 @@ pub fn set_world(
         depth: 0,
     };
 
-    let world_dir_contents = world_path
+    let world_dir_contents = source
         .read_dir()
-        .unwrap()
-        .map(|e| e.unwrap().path())
+        .map_err(|e| format!("failed to read world: {}", e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
         .collect::<Vec<PathBuf>>();
-    copy_items(&world_dir_contents, &server_dir, &options)
+    copy_items(&world_dir_contents, target, &options)
         .map_err(|e| format!("failed to copy world contents: {}", e))?;
     // ...that was way harder than it should have been
 
-    println!("[slapaman] world set for server instance: {}", name);
     Ok(())
 }